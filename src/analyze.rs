@@ -1,11 +1,36 @@
-//! ELF binary analysis using readelf.
+//! ELF binary analysis through an [`ElfBackend`](crate::ElfBackend), GNU readelf by default.
 
-use anyhow::{bail, Context, Result};
-use std::collections::HashSet;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::path::Path;
-use std::process::Command;
 
-use crate::paths::find_library;
+use crate::backend::default_backend;
+use crate::cache::AnalysisCache;
+use crate::closure::resolve_closure;
+use crate::dlopen::{parse_readelf_dlopen, DlopenDependency};
+
+/// Dynamic-linking facts extracted from one ELF object.
+///
+/// Non-ELF files, static binaries and unreadable files produce the default
+/// (empty) value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElfFacts {
+    /// DT_NEEDED entries, in file order.
+    pub needed: Vec<String>,
+    /// DT_SONAME, if the object declares one.
+    pub soname: Option<String>,
+    /// DT_RUNPATH entries, split on `:`.
+    pub runpath: Vec<String>,
+    /// DT_RPATH entries, split on `:`.
+    pub rpath: Vec<String>,
+    /// Program interpreter from PT_INTERP (e.g. `/lib64/ld-linux-x86-64.so.2`).
+    pub interpreter: Option<String>,
+    /// Optional `dlopen()` dependencies from `.note.dlopen`. Only the
+    /// readelf and llvm-readelf backends read notes.
+    #[serde(default)]
+    pub dlopen: Vec<DlopenDependency>,
+}
 
 /// Extract library dependencies from an ELF binary using readelf.
 ///
@@ -22,39 +47,135 @@ use crate::paths::find_library;
 /// Returns `Ok(Vec::new())` if the file is not an ELF binary (e.g., a text file).
 #[must_use = "library dependencies should be processed"]
 pub fn get_library_dependencies(binary_path: &Path) -> Result<Vec<String>> {
-    // Check file exists first for a clear error message
-    if !binary_path.exists() {
-        bail!("File does not exist: {}", binary_path.display());
+    Ok(get_elf_facts(binary_path)?.needed)
+}
+
+/// Extract NEEDED, SONAME, RUNPATH, RPATH and the interpreter from an ELF file.
+///
+/// Uses the auto-detected [`default_backend`].
+///
+/// # Errors
+///
+/// Same as [`get_library_dependencies`]. Non-ELF files yield `ElfFacts::default()`.
+#[must_use = "ELF facts should be processed"]
+pub fn get_elf_facts(binary_path: &Path) -> Result<ElfFacts> {
+    default_backend()?.analyze(binary_path)
+}
+
+/// Extract library dependencies from many files with as few readelf runs as possible.
+///
+/// Returns one entry per input path, in input order. Non-ELF and unreadable
+/// files yield an empty list, exactly as with [`get_library_dependencies`].
+///
+/// # Errors
+///
+/// Returns an error under the same conditions as [`get_library_dependencies`]
+/// for any file in the batch.
+#[must_use = "library dependencies should be processed"]
+pub fn get_library_dependencies_batch(paths: &[&Path]) -> Result<Vec<Vec<String>>> {
+    Ok(get_elf_facts_batch(paths)?
+        .into_iter()
+        .map(|facts| facts.needed)
+        .collect())
+}
+
+/// Batch counterpart of [`get_elf_facts`], one entry per input path.
+#[must_use = "ELF facts should be processed"]
+pub fn get_elf_facts_batch(paths: &[&Path]) -> Result<Vec<ElfFacts>> {
+    default_backend()?.analyze_batch(paths)
+}
+
+/// Split multi-file readelf output at its `File: <path>` headers.
+pub(crate) fn split_readelf_files(output: &str) -> Vec<(&str, &str)> {
+    let mut files = Vec::new();
+    let mut current: Option<(&str, usize)> = None;
+    let mut offset = 0;
+
+    for line in output.split_inclusive('\n') {
+        if let Some(name) = line.strip_prefix("File: ") {
+            if let Some((prev, start)) = current {
+                files.push((prev, &output[start..offset]));
+            }
+            current = Some((name.trim_end(), offset + line.len()));
+        }
+        offset += line.len();
+    }
+    if let Some((prev, start)) = current {
+        files.push((prev, &output[start..]));
     }
 
-    let output = Command::new("readelf")
-        .args(["-d"])
-        .arg(binary_path)
-        .output()
-        .context("readelf command not found - install binutils")?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        // These are legitimate "not an ELF" or permission cases, not fatal errors
-        // For unreadable files (like setuid binaries), we return empty deps and
-        // let the caller copy the binary without library analysis
-        if stderr.contains("Not an ELF file")
-            || stderr.contains("not a dynamic executable")
-            || stderr.contains("File format not recognized")
-            || stderr.contains("Failed to read file header")
-            || stderr.contains("is not readable")
+    files
+}
+
+/// Parse multi-file readelf -d output into per-file NEEDED lists.
+///
+/// When given several files, readelf prints a header before each one:
+/// ```text
+/// File: /usr/bin/bash
+///
+/// Dynamic section at offset 0x2d0e0 contains 28 entries:
+///  0x0000000000000001 (NEEDED)             Shared library: [libtinfo.so.6]
+///
+/// File: /etc/passwd
+/// ```
+///
+/// Files with no dynamic section (static binaries, non-ELF files) get an
+/// empty list.
+pub fn parse_readelf_batch_output(output: &str) -> Result<Vec<(String, Vec<String>)>> {
+    split_readelf_files(output)
+        .into_iter()
+        .map(|(name, section)| Ok((name.to_string(), parse_readelf_output(section)?)))
+        .collect()
+}
+
+/// Parse readelf -W -d -l -n output into [`ElfFacts`].
+///
+/// Example readelf output:
+/// ```text
+///       [Requesting program interpreter: /lib64/ld-linux-x86-64.so.2]
+///  0x0000000000000001 (NEEDED)             Shared library: [libc.so.6]
+///  0x000000000000000e (SONAME)             Library soname: [libz.so.1]
+///  0x000000000000001d (RUNPATH)            Library runpath: [$ORIGIN/../lib]
+/// ```
+pub fn parse_readelf_facts(output: &str) -> Result<ElfFacts> {
+    let mut facts = ElfFacts {
+        needed: parse_readelf_output(output)?,
+        dlopen: parse_readelf_dlopen(output),
+        ..ElfFacts::default()
+    };
+
+    for line in output.lines() {
+        if let Some(rest) = line
+            .trim()
+            .strip_prefix("[Requesting program interpreter: ")
         {
-            return Ok(Vec::new());
+            facts.interpreter = Some(rest.trim_end_matches(']').to_string());
+        } else if line.contains("(SONAME)") {
+            facts.soname = bracketed(line).map(str::to_string);
+        } else if line.contains("(RUNPATH)") {
+            facts.runpath = bracketed(line).map(split_search_path).unwrap_or_default();
+        } else if line.contains("(RPATH)") {
+            facts.rpath = bracketed(line).map(split_search_path).unwrap_or_default();
         }
-        bail!(
-            "readelf failed on {}: {}",
-            binary_path.display(),
-            stderr.trim()
-        );
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    parse_readelf_output(&stdout)
+    Ok(facts)
+}
+
+/// The text between the first `[` and the last `]` of a line.
+pub(crate) fn bracketed(line: &str) -> Option<&str> {
+    let start = line.find('[')?;
+    let end = line.rfind(']')?;
+    (start < end).then(|| &line[start + 1..end])
+}
+
+/// Split a colon-separated search path, dropping empty entries.
+pub(crate) fn split_search_path(value: &str) -> Vec<String> {
+    value
+        .split(':')
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 
 /// Parse readelf -d output to extract NEEDED library names.
@@ -89,33 +210,22 @@ pub fn parse_readelf_output(output: &str) -> Result<Vec<String>> {
 ///
 /// Some libraries depend on other libraries. We need to copy all of them.
 /// The `extra_lib_paths` parameter is passed to `find_library` for each lookup.
+/// Library names are returned sorted.
+///
+/// When resolving many binaries, prefer [`resolve_closure`](crate::resolve_closure)
+/// with a shared [`AnalysisCache`] so common libraries are only analyzed once.
 pub fn get_all_dependencies(
     source_root: &Path,
     binary_path: &Path,
     extra_lib_paths: &[&str],
-) -> Result<HashSet<String>> {
-    let mut all_libs = HashSet::new();
-    let mut to_process = vec![binary_path.to_path_buf()];
-    let mut processed = HashSet::new();
-
-    while let Some(path) = to_process.pop() {
-        if processed.contains(&path) {
-            continue;
-        }
-        processed.insert(path.clone());
-
-        let deps = get_library_dependencies(&path)?;
-        for lib_name in deps {
-            if all_libs.insert(lib_name.clone()) {
-                // New library - find it and check its dependencies too
-                if let Some(lib_path) = find_library(source_root, &lib_name, extra_lib_paths) {
-                    to_process.push(lib_path);
-                }
-            }
-        }
-    }
-
-    Ok(all_libs)
+) -> Result<BTreeSet<String>> {
+    let closure = resolve_closure(
+        source_root,
+        &[binary_path],
+        extra_lib_paths,
+        &mut AnalysisCache::new(),
+    )?;
+    Ok(closure.libraries.into_keys().collect())
 }
 
 #[cfg(test)]
@@ -168,4 +278,70 @@ Dynamic section at offset 0x2f50 contains 10 entries:
         let libs = parse_readelf_output(output).unwrap();
         assert!(libs.is_empty());
     }
+
+    #[test]
+    fn test_parse_readelf_facts() {
+        let output = r#"
+Elf file type is DYN (Position-Independent Executable file)
+Program Headers:
+  Type           Offset   VirtAddr           PhysAddr           FileSiz  MemSiz   Flg Align
+  INTERP         0x000318 0x0000000000000318 0x0000000000000318 0x00001c 0x00001c R   0x1
+      [Requesting program interpreter: /lib64/ld-linux-x86-64.so.2]
+
+Dynamic section at offset 0x2d0e0 contains 28 entries:
+  Tag        Type                         Name/Value
+ 0x0000000000000001 (NEEDED)             Shared library: [libc.so.6]
+ 0x000000000000000e (SONAME)             Library soname: [libfoo.so.1]
+ 0x000000000000000f (RPATH)              Library rpath: [/opt/foo/lib]
+ 0x000000000000001d (RUNPATH)            Library runpath: [$ORIGIN/../lib:/usr/lib64]
+"#;
+        let facts = parse_readelf_facts(output).unwrap();
+        assert_eq!(facts.needed, vec!["libc.so.6"]);
+        assert_eq!(facts.soname.as_deref(), Some("libfoo.so.1"));
+        assert_eq!(facts.rpath, vec!["/opt/foo/lib"]);
+        assert_eq!(facts.runpath, vec!["$ORIGIN/../lib", "/usr/lib64"]);
+        assert_eq!(
+            facts.interpreter.as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+    }
+
+    #[test]
+    fn test_parse_readelf_batch_output() {
+        let output = r#"
+File: /usr/bin/bash
+
+Dynamic section at offset 0x2d0e0 contains 28 entries:
+  Tag        Type                         Name/Value
+ 0x0000000000000001 (NEEDED)             Shared library: [libtinfo.so.6]
+ 0x0000000000000001 (NEEDED)             Shared library: [libc.so.6]
+
+File: /etc/passwd
+
+File: /usr/bin/static
+
+There is no dynamic section in this file.
+
+File: /usr/lib64/libtinfo.so.6
+
+Dynamic section at offset 0x31d40 contains 26 entries:
+ 0x0000000000000001 (NEEDED)             Shared library: [libc.so.6]
+ 0x000000000000000e (SONAME)             Library soname: [libtinfo.so.6]
+"#;
+        let files = parse_readelf_batch_output(output).unwrap();
+        let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "/usr/bin/bash",
+                "/etc/passwd",
+                "/usr/bin/static",
+                "/usr/lib64/libtinfo.so.6"
+            ]
+        );
+        assert_eq!(files[0].1, vec!["libtinfo.so.6", "libc.so.6"]);
+        assert!(files[1].1.is_empty());
+        assert!(files[2].1.is_empty());
+        assert_eq!(files[3].1, vec!["libc.so.6"]);
+    }
 }
@@ -0,0 +1,547 @@
+//! Audit of a finished root: can every ELF in it actually be loaded?
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::analyze::ElfFacts;
+use crate::cache::AnalysisCache;
+use crate::closure::{analyze_misses, read_each, ClosureOptions};
+use crate::elf::{read_elf_header, ElfClass, ElfHeader};
+use crate::filter::glob_match;
+use crate::libc_flavor::{LibcEvidence, LibcReport};
+use crate::paths::resolve_in_root;
+
+/// Nesting limit for `include` lines in ld.so.conf.
+const MAX_CONF_DEPTH: usize = 8;
+
+/// Options for [`audit_root_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct AuditOptions {
+    /// How ELF files are analyzed (backend and worker threads).
+    pub analysis: ClosureOptions,
+}
+
+/// A problem that would stop an ELF file from loading in the audited root.
+///
+/// `path` is always the host path of the ELF file the finding is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditFinding {
+    /// The PT_INTERP loader does not exist in the root.
+    MissingInterpreter { path: PathBuf, interpreter: String },
+    /// The PT_INTERP loader exists but can't run this file.
+    IncompatibleInterpreter {
+        path: PathBuf,
+        interpreter: String,
+        found: String,
+    },
+    /// A NEEDED library was not found in any search directory.
+    MissingLibrary {
+        path: PathBuf,
+        library: String,
+        /// Image directories searched, in order.
+        searched: Vec<PathBuf>,
+    },
+    /// A NEEDED library was only found with the wrong class or machine.
+    IncompatibleLibrary {
+        path: PathBuf,
+        library: String,
+        /// Host paths of the candidates that were skipped.
+        candidates: Vec<PathBuf>,
+    },
+    /// The file uses a different C library (glibc or musl) than the rest of
+    /// the root, or links against both.
+    LibcMismatch {
+        path: PathBuf,
+        evidence: Vec<LibcEvidence>,
+    },
+    /// The file, or its symbol table for the libc check, could not be read.
+    AnalysisFailed { path: PathBuf, error: String },
+}
+
+impl AuditFinding {
+    /// The ELF file this finding is about.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::MissingInterpreter { path, .. }
+            | Self::IncompatibleInterpreter { path, .. }
+            | Self::MissingLibrary { path, .. }
+            | Self::IncompatibleLibrary { path, .. }
+            | Self::LibcMismatch { path, .. }
+            | Self::AnalysisFailed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for AuditFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingInterpreter { path, interpreter } => {
+                write!(
+                    f,
+                    "{}: interpreter {} not found",
+                    path.display(),
+                    interpreter
+                )
+            }
+            Self::IncompatibleInterpreter {
+                path,
+                interpreter,
+                found,
+            } => write!(
+                f,
+                "{}: interpreter {} is {}",
+                path.display(),
+                interpreter,
+                found
+            ),
+            Self::MissingLibrary { path, library, .. } => write!(
+                f,
+                "{}: error while loading shared libraries: {}: cannot open shared object file",
+                path.display(),
+                library
+            ),
+            Self::IncompatibleLibrary {
+                path,
+                library,
+                candidates,
+            } => write!(
+                f,
+                "{}: {} only found with the wrong class or machine ({})",
+                path.display(),
+                library,
+                candidates
+                    .iter()
+                    .map(|c| c.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::LibcMismatch { path, evidence } => write!(
+                f,
+                "{}: C library differs from the rest of the root ({})",
+                path.display(),
+                evidence
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::AnalysisFailed { path, error } => {
+                write!(f, "{}: analysis failed: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+/// Outcome of [`audit_root`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Loadable ELF files found (executables and shared objects).
+    pub elf_files: usize,
+    /// Problems found, ordered by file.
+    pub findings: Vec<AuditFinding>,
+}
+
+impl AuditReport {
+    /// Returns `true` if nothing was found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Check that every ELF file under `dest_root` can be loaded there.
+///
+/// Shorthand for [`audit_root_with_options`] with a fresh cache and default options.
+pub fn audit_root(dest_root: &Path) -> Result<AuditReport> {
+    audit_root_with_options(
+        dest_root,
+        &mut AnalysisCache::new(),
+        &AuditOptions::default(),
+    )
+}
+
+/// Check that every ELF file under `dest_root` can be loaded there.
+///
+/// For each executable and shared object this checks that:
+/// - its PT_INTERP loader exists in the root and matches its class and machine
+/// - every NEEDED entry resolves the way the dynamic loader would inside the
+///   root: DT_RPATH (only without DT_RUNPATH), DT_RUNPATH with `$ORIGIN` and
+///   `$LIB` expanded, directories from the root's `/etc/ld.so.conf` (with
+///   `include`s), then the trusted directories `/lib64`, `/usr/lib64`, `/lib`
+///   and `/usr/lib`; candidates of the wrong class or machine are skipped, as
+///   the loader does
+/// - it uses the same C library as the rest of the root, judged by its
+///   interpreter, NEEDED entries and symbol versions: when both glibc and
+///   musl objects are present, the ones of the less common flavor (and any
+///   linking both) are reported
+///
+/// Symlinks are followed inside the root, never out to the host. DT_RPATH
+/// inherited from the executable by its libraries is not modelled.
+///
+/// # Errors
+///
+/// Fails if the tree can't be walked or no analysis backend is available.
+/// Files that fail to analyze are reported as findings.
+pub fn audit_root_with_options(
+    dest_root: &Path,
+    cache: &mut AnalysisCache,
+    options: &AuditOptions,
+) -> Result<AuditReport> {
+    let backend = options.analysis.backend()?;
+
+    let elves = find_elf_files(dest_root)?;
+    let mut report = AuditReport {
+        elf_files: elves.len(),
+        findings: Vec::new(),
+    };
+
+    for (path, e) in analyze_misses(backend, elves.keys(), cache, options.analysis.jobs) {
+        report.findings.push(AuditFinding::AnalysisFailed {
+            path,
+            error: format!("{:#}", e),
+        });
+    }
+
+    let conf_dirs = ld_so_conf_dirs(dest_root)?;
+    for (path, header) in &elves {
+        let Some(facts) = cache.get(path) else {
+            continue; // Analysis failed, already reported
+        };
+        audit_file(dest_root, path, header, facts, &conf_dirs, &mut report)?;
+    }
+
+    // Symbol versions catch glibc objects that don't link libc directly
+    let analyzed: Vec<PathBuf> = elves
+        .keys()
+        .filter(|path| cache.get(path).is_some())
+        .cloned()
+        .collect();
+    let tables = read_each(&analyzed, options.analysis.jobs, |path| {
+        Ok(backend.symbols(path))
+    })
+    .expect("per-file results carry their own errors");
+    let mut needs = Vec::with_capacity(analyzed.len());
+    for (path, table) in analyzed.iter().zip(tables) {
+        match table {
+            Ok(table) => needs.push(table.version_needs),
+            Err(e) => {
+                report.findings.push(AuditFinding::AnalysisFailed {
+                    path: path.clone(),
+                    error: format!("{:#}", e),
+                });
+                needs.push(Vec::new());
+            }
+        }
+    }
+    let libc =
+        LibcReport::from_facts(analyzed.iter().zip(&needs).filter_map(|(path, needs)| {
+            Some((path.as_path(), cache.get(path)?, needs.as_slice()))
+        }));
+    for (path, evidence) in libc.offenders() {
+        report.findings.push(AuditFinding::LibcMismatch {
+            path: path.to_path_buf(),
+            evidence: evidence.to_vec(),
+        });
+    }
+    report.findings.sort_by(|a, b| a.path().cmp(b.path()));
+
+    Ok(report)
+}
+
+fn audit_file(
+    root: &Path,
+    path: &Path,
+    header: &ElfHeader,
+    facts: &ElfFacts,
+    conf_dirs: &[PathBuf],
+    report: &mut AuditReport,
+) -> Result<()> {
+    if let Some(interpreter) = &facts.interpreter {
+        let loader = resolve_in_root(root, Path::new(interpreter))?;
+        match fs::metadata(&loader)
+            .ok()
+            .filter(|meta| meta.is_file())
+            .map(|_| read_elf_header(&loader))
+        {
+            None => report.findings.push(AuditFinding::MissingInterpreter {
+                path: path.to_path_buf(),
+                interpreter: interpreter.clone(),
+            }),
+            Some(Ok(Some(found))) if found.is_compatible(header) => {}
+            Some(found) => report.findings.push(AuditFinding::IncompatibleInterpreter {
+                path: path.to_path_buf(),
+                interpreter: interpreter.clone(),
+                found: match found {
+                    Ok(Some(found)) => found.to_string(),
+                    Ok(None) => "not an ELF file".to_string(),
+                    Err(e) => format!("unreadable ({:#})", e),
+                },
+            }),
+        }
+    }
+
+    let search_dirs = search_dirs(root, path, header, facts, conf_dirs)?;
+    for library in &facts.needed {
+        let mut incompatible = Vec::new();
+        let candidates: Vec<PathBuf> = if library.contains('/') {
+            vec![PathBuf::from(library)]
+        } else {
+            search_dirs.iter().map(|dir| dir.join(library)).collect()
+        };
+
+        let mut found = false;
+        for candidate in candidates {
+            let resolved = resolve_in_root(root, &candidate)?;
+            if !resolved.is_file() {
+                continue;
+            }
+            match read_elf_header(&resolved) {
+                Ok(Some(lib_header)) if lib_header.is_compatible(header) => {
+                    found = true;
+                    break;
+                }
+                Ok(_) => incompatible.push(resolved),
+                // The loader can't open it either and moves on to the next one
+                Err(_) => continue,
+            }
+        }
+
+        if found {
+            continue;
+        }
+        report.findings.push(if incompatible.is_empty() {
+            AuditFinding::MissingLibrary {
+                path: path.to_path_buf(),
+                library: library.clone(),
+                searched: search_dirs.clone(),
+            }
+        } else {
+            AuditFinding::IncompatibleLibrary {
+                path: path.to_path_buf(),
+                library: library.clone(),
+                candidates: incompatible,
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Image directories searched for the NEEDED entries of `path`, in loader order.
+fn search_dirs(
+    root: &Path,
+    path: &Path,
+    header: &ElfHeader,
+    facts: &ElfFacts,
+    conf_dirs: &[PathBuf],
+) -> Result<Vec<PathBuf>> {
+    let origin = image_origin(root, path)?;
+    let expand = |dir: &String| expand_search_dir(dir, &origin, header.class);
+
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if facts.runpath.is_empty() {
+        dirs.extend(facts.rpath.iter().map(expand));
+    }
+    dirs.extend(facts.runpath.iter().map(expand));
+    dirs.extend(conf_dirs.iter().cloned());
+    dirs.extend(trusted_dirs(header.class).iter().map(PathBuf::from));
+
+    let mut seen = std::collections::HashSet::new();
+    dirs.retain(|dir| seen.insert(dir.clone()));
+    Ok(dirs)
+}
+
+/// The loader's built-in trusted directories, in search order.
+pub(crate) fn trusted_dirs(class: ElfClass) -> &'static [&'static str] {
+    match class {
+        ElfClass::Elf64 => &["/lib64", "/usr/lib64", "/lib", "/usr/lib"],
+        ElfClass::Elf32 => &["/lib", "/usr/lib", "/lib64", "/usr/lib64"],
+    }
+}
+
+/// What `$ORIGIN` means for `path`: the image directory the file really lives in.
+pub(crate) fn image_origin(root: &Path, path: &Path) -> Result<PathBuf> {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let real_parent = resolve_in_root(root, rel.parent().unwrap_or(Path::new("")))?;
+    Ok(Path::new("/").join(real_parent.strip_prefix(root).unwrap_or(&real_parent)))
+}
+
+/// Expand `$ORIGIN` and `$LIB` (and their `${...}` forms) in a search path entry.
+pub(crate) fn expand_search_dir(dir: &str, origin: &Path, class: ElfClass) -> PathBuf {
+    let lib = match class {
+        ElfClass::Elf64 => "lib64",
+        ElfClass::Elf32 => "lib",
+    };
+    PathBuf::from(
+        dir.replace("${ORIGIN}", &origin.to_string_lossy())
+            .replace("$ORIGIN", &origin.to_string_lossy())
+            .replace("${LIB}", lib)
+            .replace("$LIB", lib),
+    )
+}
+
+/// Directories listed in the root's `/etc/ld.so.conf`, following `include`s.
+pub(crate) fn ld_so_conf_dirs(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    read_ld_so_conf(root, Path::new("/etc/ld.so.conf"), 0, &mut dirs)?;
+    Ok(dirs)
+}
+
+fn read_ld_so_conf(root: &Path, conf: &Path, depth: usize, dirs: &mut Vec<PathBuf>) -> Result<()> {
+    if depth > MAX_CONF_DEPTH {
+        return Ok(());
+    }
+    let host_path = resolve_in_root(root, conf)?;
+    let Ok(text) = fs::read_to_string(&host_path) else {
+        return Ok(()); // No ld.so.conf: only the trusted directories apply
+    };
+
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if let Some(pattern) = line.strip_prefix("include") {
+            for pattern in pattern.split_whitespace() {
+                // Relative includes are relative to /etc, where ldconfig runs them from
+                let pattern = Path::new("/etc").join(pattern);
+                for included in expand_include(root, &pattern)? {
+                    read_ld_so_conf(root, &included, depth + 1, dirs)?;
+                }
+            }
+        } else if !line.is_empty() && !line.starts_with("hwcap") {
+            dirs.extend(
+                line.split(|c: char| c.is_whitespace() || c == ':' || c == ',')
+                    .filter(|dir| dir.starts_with('/'))
+                    .map(PathBuf::from),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Image paths matching an include pattern (globbing in the last component only).
+fn expand_include(root: &Path, pattern: &Path) -> Result<Vec<PathBuf>> {
+    let (Some(dir), Some(name)) = (pattern.parent(), pattern.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let host_dir = resolve_in_root(root, dir)?;
+    let Ok(entries) = fs::read_dir(&host_dir) else {
+        return Ok(Vec::new());
+    };
+    let mut matches: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            glob_match(
+                name.as_encoded_bytes(),
+                entry.file_name().as_encoded_bytes(),
+            )
+        })
+        .map(|entry| dir.join(entry.file_name()))
+        .collect();
+    matches.sort();
+    Ok(matches)
+}
+
+/// Every loadable ELF file under `root` (regular files only), with its header.
+pub(crate) fn find_elf_files(root: &Path) -> Result<BTreeMap<PathBuf, ElfHeader>> {
+    let mut elves = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                // Unreadable files can't be loaded either, but that's not ours to judge
+                if let Ok(Some(header)) = read_elf_header(&entry.path()) {
+                    if header.is_loadable() {
+                        elves.insert(entry.path(), header);
+                    }
+                }
+            }
+        }
+    }
+    Ok(elves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_ld_so_conf_includes() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        write(
+            &root.join("etc/ld.so.conf"),
+            "# comment\ninclude ld.so.conf.d/*.conf\n/opt/lib\n",
+        );
+        write(&root.join("etc/ld.so.conf.d/b.conf"), "/usr/local/lib\n");
+        write(
+            &root.join("etc/ld.so.conf.d/a.conf"),
+            "/usr/lib/x86_64-linux-gnu /lib/x86_64-linux-gnu\n",
+        );
+        write(&root.join("etc/ld.so.conf.d/README"), "/ignored\n");
+
+        assert_eq!(
+            ld_so_conf_dirs(root).unwrap(),
+            vec![
+                PathBuf::from("/usr/lib/x86_64-linux-gnu"),
+                PathBuf::from("/lib/x86_64-linux-gnu"),
+                PathBuf::from("/usr/local/lib"),
+                PathBuf::from("/opt/lib"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_dirs_order() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        symlink("usr/bin", root.join("bin")).unwrap();
+        let header = ElfHeader {
+            class: ElfClass::Elf64,
+            little_endian: true,
+            os_abi: 0,
+            elf_type: crate::elf::ET_DYN,
+            machine: 62,
+        };
+        let facts = ElfFacts {
+            runpath: vec!["$ORIGIN/../$LIB".to_string()],
+            rpath: vec!["/ignored/with/runpath".to_string()],
+            ..ElfFacts::default()
+        };
+
+        let dirs = search_dirs(
+            root,
+            &root.join("bin/tool"),
+            &header,
+            &facts,
+            &[PathBuf::from("/opt/lib")],
+        )
+        .unwrap();
+        assert_eq!(
+            dirs,
+            [
+                "/usr/bin/../lib64",
+                "/opt/lib",
+                "/lib64",
+                "/usr/lib64",
+                "/lib",
+                "/usr/lib"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
@@ -0,0 +1,1506 @@
+//! Pluggable ELF analysis backends.
+//!
+//! Every backend shells out to an ELF inspection tool that parses headers
+//! without executing the file, always under the C locale so that messages
+//! and labels are stable. [`default_backend`] picks the first tool found on
+//! the host in the order GNU readelf, llvm-readelf, eu-readelf, objdump;
+//! each of them can answer every query of [`ElfBackend`].
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::OnceLock;
+
+use crate::analyze::{bracketed, parse_readelf_facts, split_search_path, ElfFacts};
+use crate::compat::{parse_readelf_notes, AbiTag, ElfNotes, KernelVersion, X86IsaLevel};
+use crate::hardening::{parse_readelf_hardening, Hardening, Pie, Relro};
+use crate::implicit::{parse_readelf_strings, SectionString};
+use crate::symbols::{
+    parse_readelf_symbols, DynamicSymbol, SymbolBinding, SymbolTable, VersionNeed,
+};
+
+/// Maximum number of files passed to a single tool invocation.
+const BATCH_SIZE: usize = 64;
+
+/// readelf flags producing the dynamic section, program headers and notes.
+const GNU_READELF_ARGS: &[&str] = &["-W", "-d", "-l", "-n"];
+
+/// llvm-readelf flags; the GNU output style matches readelf's layout.
+const LLVM_READELF_ARGS: &[&str] = &["--elf-output-style=GNU", "-W", "-d", "-l", "-n"];
+
+/// readelf flags producing `.dynsym` and the symbol version sections.
+const GNU_SYMBOL_ARGS: &[&str] = &["-W", "--dyn-syms", "-V"];
+
+/// llvm-readelf counterpart of [`GNU_SYMBOL_ARGS`].
+const LLVM_SYMBOL_ARGS: &[&str] = &["--elf-output-style=GNU", "-W", "--dyn-syms", "-V"];
+
+/// readelf flags producing the ELF notes.
+const GNU_NOTE_ARGS: &[&str] = &["-W", "-n"];
+
+/// llvm-readelf counterpart of [`GNU_NOTE_ARGS`].
+const LLVM_NOTE_ARGS: &[&str] = &["--elf-output-style=GNU", "-W", "-n"];
+
+/// readelf flags producing everything a hardening check looks at.
+const GNU_HARDENING_ARGS: &[&str] = &["-W", "-l", "-d", "-n", "--dyn-syms"];
+
+/// llvm-readelf counterpart of [`GNU_HARDENING_ARGS`].
+const LLVM_HARDENING_ARGS: &[&str] = &[
+    "--elf-output-style=GNU",
+    "-W",
+    "-l",
+    "-d",
+    "-n",
+    "--dyn-syms",
+];
+
+/// readelf flags dumping the strings a heuristic dependency scan looks at.
+const GNU_STRING_ARGS: &[&str] = &["-W", "-p", ".rodata", "-p", ".dynstr"];
+
+/// llvm-readelf counterpart of [`GNU_STRING_ARGS`].
+const LLVM_STRING_ARGS: &[&str] = &[
+    "--elf-output-style=GNU",
+    "-W",
+    "-p",
+    ".rodata",
+    "-p",
+    ".dynstr",
+];
+
+/// eu-readelf flags producing the dynamic section and program headers.
+const EU_READELF_ARGS: &[&str] = &["-W", "-d", "-l"];
+
+/// eu-readelf counterpart of [`GNU_SYMBOL_ARGS`].
+const EU_SYMBOL_ARGS: &[&str] = &["-W", "--dyn-syms", "-V"];
+
+/// eu-readelf counterpart of [`GNU_NOTE_ARGS`].
+const EU_NOTE_ARGS: &[&str] = &["-W", "-n"];
+
+/// eu-readelf counterpart of [`GNU_HARDENING_ARGS`]; `-h` supplies the file
+/// type that GNU readelf prints with the program headers.
+const EU_HARDENING_ARGS: &[&str] = &["-W", "-h", "-l", "-d", "-n", "--dyn-syms"];
+
+/// eu-readelf counterpart of [`GNU_STRING_ARGS`].
+const EU_STRING_ARGS: &[&str] = &["-W", "--string-dump=.rodata", "--string-dump=.dynstr"];
+
+/// objdump flags producing the private headers and the interpreter path.
+const OBJDUMP_ARGS: &[&str] = &["-p", "-s", "-j", ".interp"];
+
+/// objdump flags producing the version sections and the dynamic symbols.
+const OBJDUMP_SYMBOL_ARGS: &[&str] = &["-p", "-T"];
+
+/// objdump flags dumping the raw ABI tag and GNU property notes.
+const OBJDUMP_NOTE_ARGS: &[&str] = &["-s", "-j", ".note.ABI-tag", "-j", ".note.gnu.property"];
+
+/// objdump flags producing the file flags, headers and dynamic symbols;
+/// CET features come from [`OBJDUMP_NOTE_ARGS`].
+const OBJDUMP_HARDENING_ARGS: &[&str] = &["-f", "-p", "-T"];
+
+/// objdump flags dumping the raw contents of the scanned string sections.
+const OBJDUMP_STRING_ARGS: &[&str] = &["-s", "-j", ".rodata", "-j", ".dynstr"];
+
+/// ELF note type of `.note.ABI-tag`.
+const NT_GNU_ABI_TAG: u32 = 1;
+
+/// ELF note type of `.note.gnu.property`.
+const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+
+/// GNU property holding the x86 CET feature bits.
+const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc000_0002;
+
+/// GNU property holding the x86-64 ISA levels an object needs.
+const GNU_PROPERTY_X86_ISA_1_NEEDED: u32 = 0xc000_8002;
+
+/// A tool that can extract [`ElfFacts`] from files on disk.
+pub trait ElfBackend: fmt::Debug + Send + Sync {
+    /// Name of the tool, for diagnostics.
+    fn name(&self) -> &str;
+
+    /// Returns `true` if the tool can be run on this host.
+    fn is_available(&self) -> bool;
+
+    /// Analyze one file.
+    ///
+    /// Must fail if the file does not exist, and must return
+    /// `ElfFacts::default()` for non-ELF or unreadable files.
+    fn analyze(&self, path: &Path) -> Result<ElfFacts>;
+
+    /// Analyze many files, one result per input path in input order.
+    ///
+    /// The default runs [`analyze`](Self::analyze) once per file; backends
+    /// whose tool understands multi-file output override this.
+    fn analyze_batch(&self, paths: &[&Path]) -> Result<Vec<ElfFacts>> {
+        paths.iter().map(|p| self.analyze(p)).collect()
+    }
+
+    /// Read the dynamic symbol table and symbol versions of one file.
+    ///
+    /// Non-ELF and unreadable files yield `SymbolTable::default()`. The
+    /// default fails; every built-in backend overrides it.
+    fn symbols(&self, path: &Path) -> Result<SymbolTable> {
+        bail!(
+            "{} can't read symbol versions of {}",
+            self.name(),
+            path.display()
+        )
+    }
+
+    /// Read the ABI tag and GNU property notes of one file.
+    ///
+    /// Non-ELF and unreadable files yield `ElfNotes::default()`. The default
+    /// fails; every built-in backend overrides it.
+    fn notes(&self, path: &Path) -> Result<ElfNotes> {
+        bail!("{} can't read ELF notes of {}", self.name(), path.display())
+    }
+
+    /// Read the program headers, dynamic flags, symbols and notes that
+    /// make up a checksec-style [`Hardening`] summary of one file.
+    ///
+    /// Non-ELF and unreadable files yield `Hardening::default()`. The
+    /// default fails, as for [`notes`](Self::notes).
+    fn hardening(&self, path: &Path) -> Result<Hardening> {
+        bail!(
+            "{} can't read hardening features of {}",
+            self.name(),
+            path.display()
+        )
+    }
+
+    /// Dump the strings of `.rodata` and `.dynstr` of one file.
+    ///
+    /// Missing sections and non-ELF or unreadable files yield no strings.
+    /// The default fails; every built-in backend overrides it.
+    fn strings(&self, path: &Path) -> Result<Vec<SectionString>> {
+        bail!("{} can't dump strings of {}", self.name(), path.display())
+    }
+}
+
+/// GNU binutils `readelf`, optionally a cross variant such as
+/// `aarch64-linux-gnu-readelf`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GnuReadelf {
+    program: String,
+}
+
+impl GnuReadelf {
+    /// Use `readelf` from `PATH`.
+    pub fn new() -> Self {
+        Self::with_program("readelf")
+    }
+
+    /// Use an explicit readelf binary (name or path).
+    pub fn with_program(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+        }
+    }
+
+    /// Use a cross toolchain's readelf, e.g. prefix `aarch64-linux-gnu-`.
+    pub fn with_cross_prefix(prefix: &str) -> Self {
+        Self::with_program(format!("{}readelf", prefix))
+    }
+
+    /// readelf stderr for files that simply aren't (readable) ELF objects.
+    fn is_not_elf_error(stderr: &str) -> bool {
+        stderr.contains("Not an ELF file")
+            || stderr.contains("not a dynamic executable")
+            || stderr.contains("File format not recognized")
+            || stderr.contains("Failed to read file header")
+            || stderr.contains("is not readable")
+    }
+}
+
+impl Default for GnuReadelf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ElfBackend for GnuReadelf {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn is_available(&self) -> bool {
+        probe(&self.program)
+    }
+
+    fn analyze(&self, path: &Path) -> Result<ElfFacts> {
+        let output = run_single(
+            &self.program,
+            "binutils",
+            GNU_READELF_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        output.map_or(Ok(ElfFacts::default()), |out| parse_readelf_facts(&out))
+    }
+
+    fn analyze_batch(&self, paths: &[&Path]) -> Result<Vec<ElfFacts>> {
+        // GNU readelf keeps going after a bad file, so benign errors can stay in the batch
+        let format = BatchFormat {
+            package: "binutils",
+            args: GNU_READELF_ARGS,
+            tolerated: |line| !line.contains("Error:") || Self::is_not_elf_error(line),
+            header: readelf_file_header,
+            parse: parse_readelf_facts,
+        };
+        multi_file_batch(self, &self.program, &format, paths)
+    }
+
+    fn symbols(&self, path: &Path) -> Result<SymbolTable> {
+        let output = run_single(
+            &self.program,
+            "binutils",
+            GNU_SYMBOL_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(SymbolTable::default, |out| parse_readelf_symbols(&out)))
+    }
+
+    fn notes(&self, path: &Path) -> Result<ElfNotes> {
+        let output = run_single(
+            &self.program,
+            "binutils",
+            GNU_NOTE_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(ElfNotes::default, |out| parse_readelf_notes(&out)))
+    }
+
+    fn hardening(&self, path: &Path) -> Result<Hardening> {
+        let output = run_single(
+            &self.program,
+            "binutils",
+            GNU_HARDENING_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(Hardening::default, |out| parse_readelf_hardening(&out)))
+    }
+
+    fn strings(&self, path: &Path) -> Result<Vec<SectionString>> {
+        let output = run_single(
+            &self.program,
+            "binutils",
+            GNU_STRING_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(Vec::new, |out| parse_readelf_strings(&out)))
+    }
+}
+
+/// LLVM's `llvm-readelf`, whose default GNU output style shares readelf's parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlvmReadelf {
+    program: String,
+}
+
+impl LlvmReadelf {
+    /// Use `llvm-readelf` from `PATH`.
+    pub fn new() -> Self {
+        Self::with_program("llvm-readelf")
+    }
+
+    /// Use an explicit llvm-readelf binary (e.g. `llvm-readelf-17`).
+    pub fn with_program(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+        }
+    }
+
+    fn is_not_elf_error(stderr: &str) -> bool {
+        stderr.contains("not recognized as a valid object file")
+            || stderr.contains("Permission denied")
+    }
+}
+
+impl Default for LlvmReadelf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ElfBackend for LlvmReadelf {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn is_available(&self) -> bool {
+        probe(&self.program)
+    }
+
+    fn analyze(&self, path: &Path) -> Result<ElfFacts> {
+        let output = run_single(
+            &self.program,
+            "llvm",
+            LLVM_READELF_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        output.map_or(Ok(ElfFacts::default()), |out| parse_readelf_facts(&out))
+    }
+
+    fn analyze_batch(&self, paths: &[&Path]) -> Result<Vec<ElfFacts>> {
+        // llvm-readelf stops at the first bad file, so any error means a per-file rerun
+        let format = BatchFormat {
+            package: "llvm",
+            args: LLVM_READELF_ARGS,
+            tolerated: |line| !line.contains("error:"),
+            header: readelf_file_header,
+            parse: parse_readelf_facts,
+        };
+        multi_file_batch(self, &self.program, &format, paths)
+    }
+
+    fn symbols(&self, path: &Path) -> Result<SymbolTable> {
+        let output = run_single(
+            &self.program,
+            "llvm",
+            LLVM_SYMBOL_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(SymbolTable::default, |out| parse_readelf_symbols(&out)))
+    }
+
+    fn notes(&self, path: &Path) -> Result<ElfNotes> {
+        let output = run_single(
+            &self.program,
+            "llvm",
+            LLVM_NOTE_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(ElfNotes::default, |out| parse_readelf_notes(&out)))
+    }
+
+    fn hardening(&self, path: &Path) -> Result<Hardening> {
+        let output = run_single(
+            &self.program,
+            "llvm",
+            LLVM_HARDENING_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(Hardening::default, |out| parse_readelf_hardening(&out)))
+    }
+
+    fn strings(&self, path: &Path) -> Result<Vec<SectionString>> {
+        let output = run_single(
+            &self.program,
+            "llvm",
+            LLVM_STRING_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(Vec::new, |out| parse_readelf_strings(&out)))
+    }
+}
+
+/// elfutils `eu-readelf`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EuReadelf {
+    program: String,
+}
+
+impl EuReadelf {
+    /// Use `eu-readelf` from `PATH`.
+    pub fn new() -> Self {
+        Self::with_program("eu-readelf")
+    }
+
+    /// Use an explicit eu-readelf binary.
+    pub fn with_program(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+        }
+    }
+
+    fn is_not_elf_error(stderr: &str) -> bool {
+        stderr.contains("Not an ELF file")
+            || stderr.contains("not a valid ELF file")
+            || stderr.contains("invalid file")
+            || stderr.contains("Permission denied")
+    }
+}
+
+impl Default for EuReadelf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ElfBackend for EuReadelf {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn is_available(&self) -> bool {
+        probe(&self.program)
+    }
+
+    fn analyze(&self, path: &Path) -> Result<ElfFacts> {
+        let output = run_single(
+            &self.program,
+            "elfutils",
+            EU_READELF_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        output.map_or(Ok(ElfFacts::default()), |out| {
+            Ok(parse_eu_readelf_facts(&out))
+        })
+    }
+
+    fn analyze_batch(&self, paths: &[&Path]) -> Result<Vec<ElfFacts>> {
+        let format = BatchFormat {
+            package: "elfutils",
+            args: EU_READELF_ARGS,
+            tolerated: Self::is_not_elf_error,
+            header: |line| line.strip_suffix(':'),
+            parse: |section| Ok(parse_eu_readelf_facts(section)),
+        };
+        multi_file_batch(self, &self.program, &format, paths)
+    }
+
+    fn symbols(&self, path: &Path) -> Result<SymbolTable> {
+        let output = run_single(
+            &self.program,
+            "elfutils",
+            EU_SYMBOL_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(SymbolTable::default, |out| parse_readelf_symbols(&out)))
+    }
+
+    fn notes(&self, path: &Path) -> Result<ElfNotes> {
+        let output = run_single(
+            &self.program,
+            "elfutils",
+            EU_NOTE_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(ElfNotes::default, |out| parse_readelf_notes(&out)))
+    }
+
+    fn hardening(&self, path: &Path) -> Result<Hardening> {
+        let output = run_single(
+            &self.program,
+            "elfutils",
+            EU_HARDENING_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(Hardening::default, |out| parse_readelf_hardening(&out)))
+    }
+
+    fn strings(&self, path: &Path) -> Result<Vec<SectionString>> {
+        let output = run_single(
+            &self.program,
+            "elfutils",
+            EU_STRING_ARGS,
+            path,
+            Self::is_not_elf_error,
+        )?;
+        Ok(output.map_or_else(Vec::new, |out| parse_readelf_strings(&out)))
+    }
+}
+
+/// GNU binutils `objdump -p`, optionally a cross variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Objdump {
+    program: String,
+}
+
+impl Objdump {
+    /// Use `objdump` from `PATH`.
+    pub fn new() -> Self {
+        Self::with_program("objdump")
+    }
+
+    /// Use an explicit objdump binary (name or path).
+    pub fn with_program(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+        }
+    }
+
+    /// Use a cross toolchain's objdump, e.g. prefix `aarch64-linux-gnu-`.
+    pub fn with_cross_prefix(prefix: &str) -> Self {
+        Self::with_program(format!("{}objdump", prefix))
+    }
+
+    fn is_not_elf_error(stderr: &str) -> bool {
+        stderr.contains("file format not recognized") || stderr.contains("Permission denied")
+    }
+
+    /// objdump exits non-zero when a `-j` section is in none of its inputs,
+    /// which only means there is nothing to dump.
+    fn is_missing_section(stderr: &str) -> bool {
+        stderr.contains("mentioned in a -j option, but not found")
+    }
+
+    /// Run objdump on one file, like [`run_single`], accepting missing sections.
+    fn dump(&self, args: &[&str], path: &Path) -> Result<Option<String>> {
+        if !path.exists() {
+            bail!("File does not exist: {}", path.display());
+        }
+
+        let output = run(&self.program, "binutils", args, &[path])?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() || stderr.lines().all(Self::is_missing_section) {
+            return Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()));
+        }
+        if Self::is_not_elf_error(&stderr) {
+            return Ok(None);
+        }
+        bail!(
+            "{} failed on {}: {}",
+            self.program,
+            path.display(),
+            stderr.trim()
+        );
+    }
+}
+
+impl Default for Objdump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ElfBackend for Objdump {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn is_available(&self) -> bool {
+        probe(&self.program)
+    }
+
+    fn analyze(&self, path: &Path) -> Result<ElfFacts> {
+        let output = self.dump(OBJDUMP_ARGS, path)?;
+        Ok(output.map_or_else(ElfFacts::default, |out| parse_objdump_output(&out)))
+    }
+
+    fn analyze_batch(&self, paths: &[&Path]) -> Result<Vec<ElfFacts>> {
+        let format = BatchFormat {
+            package: "binutils",
+            args: OBJDUMP_ARGS,
+            tolerated: |line| Self::is_not_elf_error(line) || Self::is_missing_section(line),
+            header: |line| line.split_once(":     file format ").map(|(path, _)| path),
+            parse: |section| Ok(parse_objdump_output(section)),
+        };
+        multi_file_batch(self, &self.program, &format, paths)
+    }
+
+    fn symbols(&self, path: &Path) -> Result<SymbolTable> {
+        let output = self.dump(OBJDUMP_SYMBOL_ARGS, path)?;
+        Ok(output.map_or_else(SymbolTable::default, |out| parse_objdump_symbols(&out)))
+    }
+
+    fn notes(&self, path: &Path) -> Result<ElfNotes> {
+        let output = self.dump(OBJDUMP_NOTE_ARGS, path)?;
+        Ok(output.map_or_else(ElfNotes::default, |out| parse_objdump_notes(&out)))
+    }
+
+    fn hardening(&self, path: &Path) -> Result<Hardening> {
+        let Some(output) = self.dump(OBJDUMP_HARDENING_ARGS, path)? else {
+            return Ok(Hardening::default());
+        };
+        let mut hardening = parse_objdump_hardening(&output);
+        let notes = self.notes(path)?;
+        hardening.ibt = notes.ibt;
+        hardening.shstk = notes.shstk;
+        Ok(hardening)
+    }
+
+    fn strings(&self, path: &Path) -> Result<Vec<SectionString>> {
+        let output = self.dump(OBJDUMP_STRING_ARGS, path)?;
+        Ok(output.map_or_else(Vec::new, |out| parse_objdump_strings(&out)))
+    }
+}
+
+/// All built-in backends, in auto-detection order.
+fn builtin_backends() -> Vec<Box<dyn ElfBackend>> {
+    vec![
+        Box::new(GnuReadelf::new()),
+        Box::new(LlvmReadelf::new()),
+        Box::new(EuReadelf::new()),
+        Box::new(Objdump::new()),
+    ]
+}
+
+/// Find the first available backend on this host.
+///
+/// Tries GNU readelf, llvm-readelf, eu-readelf and objdump, in that order.
+pub fn detect_backend() -> Option<Box<dyn ElfBackend>> {
+    builtin_backends().into_iter().find(|b| b.is_available())
+}
+
+/// The process-wide auto-detected backend, probed once on first use.
+///
+/// # Errors
+///
+/// Returns an error if none of the supported tools is installed.
+pub fn default_backend() -> Result<&'static dyn ElfBackend> {
+    static DETECTED: OnceLock<Option<Box<dyn ElfBackend>>> = OnceLock::new();
+    match DETECTED.get_or_init(detect_backend) {
+        Some(backend) => Ok(backend.as_ref()),
+        None => bail!(
+            "readelf command not found - install binutils \
+             (also tried llvm-readelf, eu-readelf and objdump)"
+        ),
+    }
+}
+
+/// Returns `true` if `program --version` runs successfully.
+fn probe(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .env("LC_ALL", "C")
+        .output()
+        .is_ok_and(|out| out.status.success())
+}
+
+/// Run `program` under the C locale.
+fn run(program: &str, package: &str, args: &[&str], paths: &[&Path]) -> Result<Output> {
+    Command::new(program)
+        .args(args)
+        .args(paths)
+        .env("LC_ALL", "C")
+        .output()
+        .with_context(|| format!("{} command not found - install {}", program, package))
+}
+
+/// Run `program` on one file, returning its stdout.
+///
+/// Returns `Ok(None)` when the tool fails in a way `is_not_elf` recognizes.
+fn run_single(
+    program: &str,
+    package: &str,
+    args: &[&str],
+    path: &Path,
+    is_not_elf: fn(&str) -> bool,
+) -> Result<Option<String>> {
+    // Check file exists first for a clear error message
+    if !path.exists() {
+        bail!("File does not exist: {}", path.display());
+    }
+
+    let output = run(program, package, args, &[path])?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // For unreadable files (like setuid binaries), we return empty deps and
+        // let the caller copy the binary without library analysis
+        if is_not_elf(&stderr) {
+            return Ok(None);
+        }
+        bail!(
+            "{} failed on {}: {}",
+            program,
+            path.display(),
+            stderr.trim()
+        );
+    }
+
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+/// How one tool's multi-file output is requested and taken apart.
+struct BatchFormat {
+    package: &'static str,
+    args: &'static [&'static str],
+    /// Returns `true` for stderr lines that don't spoil the rest of the batch.
+    tolerated: fn(&str) -> bool,
+    /// The path named by a line that may start one input's output.
+    header: fn(&str) -> Option<&str>,
+    /// Parser for one input's share of the output.
+    parse: fn(&str) -> Result<ElfFacts>,
+}
+
+/// The path of readelf's and llvm-readelf's `File: <path>` header lines.
+fn readelf_file_header(line: &str) -> Option<&str> {
+    line.strip_prefix("File: ")
+}
+
+/// Batch analysis for tools that print a header line per input.
+///
+/// Only header lines naming one of the inputs split the output. If any
+/// stderr line fails `format.tolerated`, or a file's section is missing, the
+/// affected files are re-run through `backend.analyze` so that results and
+/// errors match the single-file path exactly.
+fn multi_file_batch(
+    backend: &dyn ElfBackend,
+    program: &str,
+    format: &BatchFormat,
+    paths: &[&Path],
+) -> Result<Vec<ElfFacts>> {
+    if let Some(missing) = paths.iter().find(|p| !p.exists()) {
+        bail!("File does not exist: {}", missing.display());
+    }
+
+    let mut results = Vec::with_capacity(paths.len());
+    for chunk in paths.chunks(BATCH_SIZE) {
+        // Tools only print per-file headers when given more than one file
+        if chunk.len() < 2 {
+            for path in chunk {
+                results.push(backend.analyze(path)?);
+            }
+            continue;
+        }
+
+        let output = run(program, format.package, format.args, chunk)?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.lines().all(format.tolerated) {
+            for path in chunk {
+                results.push(backend.analyze(path)?);
+            }
+            continue;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let names: Vec<_> = chunk.iter().map(|path| path.to_string_lossy()).collect();
+        let mut sections = split_files(&stdout, &names, format.header);
+        for (path, name) in chunk.iter().zip(&names) {
+            results.push(match sections.remove(&**name) {
+                Some(section) => (format.parse)(section)?,
+                None => backend.analyze(path)?,
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Split multi-file output at the header lines that name one of `names`.
+fn split_files<'a>(
+    output: &'a str,
+    names: &[impl AsRef<str>],
+    header: fn(&str) -> Option<&str>,
+) -> HashMap<&'a str, &'a str> {
+    let mut files = HashMap::new();
+    let mut current: Option<(&str, usize)> = None;
+    let mut offset = 0;
+
+    for line in output.split_inclusive('\n') {
+        let named =
+            header(line.trim_end()).filter(|name| names.iter().any(|n| n.as_ref() == *name));
+        if let Some(name) = named {
+            if let Some((prev, start)) = current {
+                files.insert(prev, &output[start..offset]);
+            }
+            current = Some((name, offset + line.len()));
+        }
+        offset += line.len();
+    }
+    if let Some((prev, start)) = current {
+        files.insert(prev, &output[start..]);
+    }
+
+    files
+}
+
+/// Parse eu-readelf -d -l output.
+///
+/// ```text
+///       [Requesting program interpreter: /lib64/ld-linux-x86-64.so.2]
+/// Dynamic segment contains 27 entries:
+///   Type              Value
+///   NEEDED            Shared library: [libc.so.6]
+///   SONAME            Library soname: [libz.so.1]
+///   RUNPATH           Library runpath: [$ORIGIN/../lib]
+/// ```
+fn parse_eu_readelf_facts(output: &str) -> ElfFacts {
+    let mut facts = ElfFacts::default();
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed.strip_prefix("[Requesting program interpreter: ") {
+            facts.interpreter = Some(rest.trim_end_matches(']').to_string());
+            continue;
+        }
+        let value = bracketed(trimmed);
+        match (trimmed.split_whitespace().next(), value) {
+            (Some("NEEDED"), Some(v)) => facts.needed.push(v.to_string()),
+            (Some("SONAME"), Some(v)) => facts.soname = Some(v.to_string()),
+            (Some("RUNPATH"), Some(v)) => facts.runpath = split_search_path(v),
+            (Some("RPATH"), Some(v)) => facts.rpath = split_search_path(v),
+            _ => {}
+        }
+    }
+
+    facts
+}
+
+/// Parse the `Dynamic Section:` of objdump -p output.
+///
+/// ```text
+/// Dynamic Section:
+///   NEEDED               libc.so.6
+///   SONAME               libz.so.1
+///   RUNPATH              $ORIGIN/../lib
+/// ```
+fn parse_objdump_facts(output: &str) -> ElfFacts {
+    let mut facts = ElfFacts::default();
+    let mut in_dynamic = false;
+
+    for line in output.lines() {
+        if line.starts_with("Dynamic Section:") {
+            in_dynamic = true;
+            continue;
+        }
+        if !in_dynamic {
+            continue;
+        }
+        // The section ends at the first line that isn't an indented entry
+        if !line.starts_with(' ') {
+            break;
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(tag), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        match tag {
+            "NEEDED" => facts.needed.push(value.to_string()),
+            "SONAME" => facts.soname = Some(value.to_string()),
+            "RUNPATH" => facts.runpath = split_search_path(value),
+            "RPATH" => facts.rpath = split_search_path(value),
+            _ => {}
+        }
+    }
+
+    facts
+}
+
+/// Parse objdump -p -s -j .interp output: the dynamic section and interpreter.
+fn parse_objdump_output(output: &str) -> ElfFacts {
+    ElfFacts {
+        interpreter: parse_objdump_interp(output),
+        ..parse_objdump_facts(output)
+    }
+}
+
+/// Decode the NUL-terminated interpreter path from objdump -s -j .interp.
+///
+/// ```text
+/// Contents of section .interp:
+///  0318 2f6c6962 36342f6c 642d6c69 6e75782d  /lib64/ld-linux-
+///  0328 7838362d 36342e73 6f2e3200           x86-64.so.2.
+/// ```
+fn parse_objdump_interp(output: &str) -> Option<String> {
+    let contents = parse_objdump_contents(output);
+    let (_, bytes) = contents.iter().find(|(name, _)| name == ".interp")?;
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let interp = String::from_utf8(bytes[..end].to_vec()).ok()?;
+    (!interp.is_empty()).then_some(interp)
+}
+
+/// Decode every section of objdump -s output into its name and raw bytes.
+fn parse_objdump_contents(output: &str) -> Vec<(String, Vec<u8>)> {
+    let mut sections: Vec<(String, Vec<u8>)> = Vec::new();
+
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("Contents of section ") {
+            let name = rest.trim_end().trim_end_matches(':');
+            sections.push((name.to_string(), Vec::new()));
+            continue;
+        }
+        let (Some((_, bytes)), Some(row)) = (sections.last_mut(), line.strip_prefix(' ')) else {
+            continue;
+        };
+        // Skip the address column; two spaces separate the hex groups from
+        // the ASCII column, even on a short last row
+        let Some((_, row)) = row.split_once(' ') else {
+            continue;
+        };
+        let hex = row.split("  ").next().unwrap_or("");
+        for group in hex.split_whitespace() {
+            for i in (0..group.len()).step_by(2) {
+                if let Some(byte) = group
+                    .get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                {
+                    bytes.push(byte);
+                }
+            }
+        }
+    }
+
+    sections
+}
+
+/// Parse objdump -p -T output into a [`SymbolTable`].
+///
+/// ```text
+/// Dynamic Section:
+///   VERSYM               0x000000000000161a
+///
+/// Version definitions:
+/// 1 0x01 0x0865f4e6 libfoo.so.1
+/// 2 0x00 0x09691a75 FOO_1.0
+///
+/// Version References:
+///   required from libc.so.6:
+///     0x09691a75 0x00 03 GLIBC_2.2.5
+///
+/// DYNAMIC SYMBOL TABLE:
+/// 0000000000000000      DF *UND*  0000000000000000 (GLIBC_2.2.5) getenv
+/// 0000000000001120 g    DF .text  000000000000002a  FOO_1.0     foo_open
+/// 0000000000001150 g    DF .text  000000000000002a (FOO_0.9)    foo_old
+/// ```
+///
+/// A parenthesized version on a definition is a hidden (`name@VER`) one.
+fn parse_objdump_symbols(output: &str) -> SymbolTable {
+    #[derive(PartialEq)]
+    enum Section {
+        None,
+        Dynamic,
+        Definitions,
+        Needs,
+        Symbols,
+    }
+
+    let mut table = SymbolTable::default();
+    let mut section = Section::None;
+    let mut needs_file: Option<String> = None;
+
+    for line in output.lines() {
+        section = match line {
+            "Dynamic Section:" => Section::Dynamic,
+            "Version definitions:" => Section::Definitions,
+            "Version References:" => Section::Needs,
+            "DYNAMIC SYMBOL TABLE:" => Section::Symbols,
+            "" => Section::None,
+            _ => {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                match section {
+                    Section::Dynamic => {
+                        table.versioned |= fields.first() == Some(&"VERSYM");
+                    }
+                    // Parent lines are indented with a tab
+                    Section::Definitions if !line.starts_with('\t') => {
+                        if let [_, flags, _, name] = fields[..] {
+                            if parse_hex(flags).is_some_and(|flags| flags & 0x1 == 0) {
+                                table.version_definitions.push(name.to_string());
+                            }
+                        }
+                    }
+                    Section::Needs => {
+                        if let Some(file) = line.trim().strip_prefix("required from ") {
+                            needs_file = Some(file.trim_end_matches(':').to_string());
+                        } else if let ([_, flags, _, name], Some(file)) = (&fields[..], &needs_file)
+                        {
+                            table.version_needs.push(VersionNeed {
+                                library: file.clone(),
+                                version: name.to_string(),
+                                weak: parse_hex(flags).is_some_and(|flags| flags & 0x2 != 0),
+                            });
+                        }
+                    }
+                    Section::Symbols => {
+                        if let Some(sym) = parse_objdump_symbol_line(line) {
+                            table.symbols.push(sym);
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+        };
+    }
+
+    table
+}
+
+/// Parse one `Value Flags Section<TAB>Size Version Name` row of objdump -T.
+fn parse_objdump_symbol_line(line: &str) -> Option<DynamicSymbol> {
+    let (value, rest) = line.split_once(' ')?;
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    // Seven flag characters: binding, weak, ..., then the section
+    let flags = rest.get(..7)?.as_bytes();
+    let (section, rest) = rest.get(7..)?.split_once('\t')?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let (version, name) = match fields[..] {
+        [_, name] => (None, name),
+        [_, version, name] => (Some(version), name),
+        // Non-default visibility is printed between version and name
+        [_, version, _, name] => (Some(version), name),
+        _ => return None,
+    };
+    let defined = section.trim() != "*UND*";
+    let binding = match (flags[0], flags[1]) {
+        (b'l', _) => SymbolBinding::Local,
+        (b'u', _) => SymbolBinding::Unique,
+        (_, b'w') => SymbolBinding::Weak,
+        _ => SymbolBinding::Global,
+    };
+
+    let (version, hidden) = match version {
+        // Base and unversioned entries, as readelf shows them without a version
+        None | Some("Base") => (None, false),
+        Some(v) if v.starts_with('*') => (None, false),
+        Some(v) => match v.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+            Some(v) => (Some(v), defined),
+            None => (Some(v), false),
+        },
+    };
+    // The marker symbol of a version definition, which readelf shows unversioned
+    let version = version.filter(|version| *version != name);
+    Some(DynamicSymbol {
+        name: name.to_string(),
+        version: version.map(str::to_string),
+        hidden,
+        binding,
+        defined,
+    })
+}
+
+/// Parse a `0x`-prefixed hex number.
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+/// Decode the ABI tag and x86 properties from objdump -s dumps of
+/// `.note.ABI-tag` and `.note.gnu.property`.
+///
+/// ```text
+/// Contents of section .note.ABI-tag:
+///  037c 04000000 10000000 01000000 474e5500  ............GNU.
+///  038c 00000000 03000000 02000000 00000000  ................
+/// ```
+fn parse_objdump_notes(output: &str) -> ElfNotes {
+    // Property arrays are padded to the ELF class's word size
+    let align = if output.contains("file format elf32") {
+        4
+    } else {
+        8
+    };
+    let mut notes = ElfNotes::default();
+
+    for (_, bytes) in parse_objdump_contents(output) {
+        for (kind, desc) in gnu_notes(&bytes) {
+            match (kind, &desc[..]) {
+                (NT_GNU_ABI_TAG, &[os, major, minor, patch, ..]) => {
+                    let os = match os {
+                        0 => "Linux",
+                        1 => "Hurd",
+                        2 => "Solaris",
+                        3 => "FreeBSD",
+                        _ => "Unknown",
+                    };
+                    notes.abi_tag = Some(AbiTag {
+                        os: os.to_string(),
+                        kernel: KernelVersion::new(major, minor, patch),
+                    });
+                }
+                (NT_GNU_PROPERTY_TYPE_0, _) => {
+                    // Each property is a type, a data size and the padded data
+                    let mut at = 0;
+                    while let Some(&[kind, size]) = desc.get(at..at + 2) {
+                        let value = desc.get(at + 2).copied().unwrap_or(0);
+                        match kind {
+                            GNU_PROPERTY_X86_FEATURE_1_AND => {
+                                notes.ibt |= value & 0x1 != 0;
+                                notes.shstk |= value & 0x2 != 0;
+                            }
+                            GNU_PROPERTY_X86_ISA_1_NEEDED => {
+                                let highest = [
+                                    X86IsaLevel::Baseline,
+                                    X86IsaLevel::V2,
+                                    X86IsaLevel::V3,
+                                    X86IsaLevel::V4,
+                                ]
+                                .into_iter()
+                                .enumerate()
+                                .filter(|(bit, _)| value & (1 << bit) != 0)
+                                .map(|(_, level)| level)
+                                .max();
+                                notes.x86_isa_needed = notes.x86_isa_needed.max(highest);
+                            }
+                            _ => {}
+                        }
+                        at += 2 + (size as usize).next_multiple_of(align) / 4;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    notes
+}
+
+/// Split raw note section bytes into the type and descriptor words of each
+/// note owned by `GNU`.
+///
+/// The byte order is taken from the first name size, which is 4 for `GNU`.
+fn gnu_notes(bytes: &[u8]) -> Vec<(u32, Vec<u32>)> {
+    let little: fn(&[u8]) -> u32 = |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let big: fn(&[u8]) -> u32 = |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+    let read = match bytes.get(..4) {
+        Some(b) if little(b) == 4 => little,
+        Some(b) if big(b) == 4 => big,
+        _ => return Vec::new(),
+    };
+
+    let mut notes = Vec::new();
+    let mut at = 0;
+    while let Some(header) = bytes.get(at..at + 12) {
+        let name_size = read(&header[0..4]) as usize;
+        let desc_size = read(&header[4..8]) as usize;
+        let kind = read(&header[8..12]);
+        let name_start = at + 12;
+        let desc_start = name_start + name_size.next_multiple_of(4);
+        let Some(desc) = bytes.get(desc_start..desc_start + desc_size) else {
+            break;
+        };
+        if bytes.get(name_start..name_start + name_size) == Some(b"GNU\0") {
+            notes.push((kind, desc.chunks_exact(4).map(read).collect()));
+        }
+        at = desc_start + desc_size.next_multiple_of(4);
+    }
+    notes
+}
+
+/// Parse objdump -f -p -T output into a [`Hardening`] summary, without the
+/// CET features, which live in the notes.
+///
+/// ```text
+/// architecture: i386:x86-64, flags 0x00000150:
+/// HAS_SYMS, DYNAMIC, D_PAGED
+///
+/// Program Header:
+///   INTERP off    0x0000000000000318 vaddr 0x0000000000000318 paddr 0x0000000000000318 align 2**0
+///    STACK off    0x0000000000000000 vaddr 0x0000000000000000 paddr 0x0000000000000000 align 2**4
+///          filesz 0x0000000000000000 memsz 0x0000000000000000 flags rw-
+///    RELRO off    0x00000000000232b0 vaddr 0x00000000000232b0 paddr 0x00000000000232b0 align 2**0
+///
+/// Dynamic Section:
+///   FLAGS                0x0000000000000008
+///   FLAGS_1              0x0000000008000001
+/// ```
+fn parse_objdump_hardening(output: &str) -> Hardening {
+    const DF_TEXTREL: u64 = 0x4;
+    const DF_BIND_NOW: u64 = 0x8;
+    const DF_1_NOW: u64 = 0x1;
+    const DF_1_PIE: u64 = 0x0800_0000;
+
+    let mut hardening = Hardening::default();
+    let mut is_dyn = false;
+    let mut has_interp = false;
+    let mut flags_1_pie = false;
+    let mut has_relro = false;
+    let mut in_stack = false;
+    let mut in_dynamic = false;
+
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if line.starts_with("Dynamic Section:") {
+            in_dynamic = true;
+            continue;
+        } else if !line.starts_with(' ') {
+            in_dynamic = false;
+        }
+
+        if line.split(", ").any(|flag| flag == "DYNAMIC") {
+            is_dyn = true;
+        } else if in_stack && fields.first() == Some(&"filesz") {
+            // The flags of a program header are on its second line
+            let flags = fields.last().copied().unwrap_or("");
+            hardening.executable_stack = flags.contains('x');
+            hardening.nx = !hardening.executable_stack;
+        } else if in_dynamic {
+            let value = fields.get(1).and_then(|v| parse_hex(v)).unwrap_or(0);
+            match fields.first().copied() {
+                Some("FLAGS") => {
+                    hardening.bind_now |= value & DF_BIND_NOW != 0;
+                    hardening.textrel |= value & DF_TEXTREL != 0;
+                }
+                Some("FLAGS_1") => {
+                    hardening.bind_now |= value & DF_1_NOW != 0;
+                    flags_1_pie = value & DF_1_PIE != 0;
+                }
+                Some("BIND_NOW") => hardening.bind_now = true,
+                Some("TEXTREL") => hardening.textrel = true,
+                _ => {}
+            }
+        } else if fields.get(1) == Some(&"off") {
+            match fields[0] {
+                "INTERP" => has_interp = true,
+                "RELRO" => has_relro = true,
+                _ => {}
+            }
+        }
+        in_stack = fields.first() == Some(&"STACK") && fields.get(1) == Some(&"off");
+    }
+
+    hardening.relro = match (has_relro, hardening.bind_now) {
+        (false, _) => Relro::None,
+        (true, false) => Relro::Partial,
+        (true, true) => Relro::Full,
+    };
+    hardening.pie = match (is_dyn, has_interp || flags_1_pie) {
+        (false, _) => Pie::Disabled,
+        (true, true) => Pie::Enabled,
+        (true, false) => Pie::SharedObject,
+    };
+
+    for sym in parse_objdump_symbols(output).symbols {
+        if sym.name == "__stack_chk_fail" || sym.name == "__stack_chk_guard" {
+            hardening.stack_protector = true;
+        } else if sym.name.starts_with("__") && sym.name.ends_with("_chk") && !sym.defined {
+            hardening.fortified += 1;
+        }
+    }
+
+    hardening
+}
+
+/// Split objdump -s dumps of string sections into strings, as readelf -p
+/// prints them.
+///
+/// Like readelf, leading non-printable bytes are skipped, only the first line
+/// of a multi-line string is kept, with its newline shown as `\n`, and other
+/// control characters are shown as `^X`.
+fn parse_objdump_strings(output: &str) -> Vec<SectionString> {
+    let mut strings = Vec::new();
+
+    for (section, bytes) in parse_objdump_contents(output) {
+        let mut at = 0;
+        while at < bytes.len() {
+            if !(0x20..0x7f).contains(&bytes[at]) {
+                at += 1;
+                continue;
+            }
+            let offset = at as u64;
+            let mut text = Vec::new();
+            while let Some(&byte) = bytes.get(at) {
+                at += 1;
+                match byte {
+                    0 => break,
+                    b'\n' => {
+                        // readelf prints the rest on unnumbered lines, which
+                        // parse_readelf_strings skips
+                        text.extend_from_slice(b"\\n");
+                        while bytes.get(at).is_some_and(|&b| b != 0) {
+                            at += 1;
+                        }
+                    }
+                    0x01..=0x1f => text.extend_from_slice(&[b'^', byte + 0x40]),
+                    _ => text.push(byte),
+                }
+            }
+            strings.push(SectionString {
+                section: section.clone(),
+                offset,
+                // parse_readelf_strings can't tell leading blanks from padding
+                text: String::from_utf8_lossy(&text).trim_start().to_string(),
+            });
+        }
+    }
+
+    strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_eu_readelf_facts() {
+        let output = r#"
+Program Headers:
+  INTERP         0x000318 0x0000000000000318 0x0000000000000318 0x00001c 0x00001c R   0x1
+        [Requesting program interpreter: /lib64/ld-linux-x86-64.so.2]
+
+Dynamic segment contains 27 entries:
+ Addr: 0x000000000001eb28  Offset: 0x01db28  Link to section: [ 7] '.dynstr'
+  Type              Value
+  NEEDED            Shared library: [libz.so.1]
+  NEEDED            Shared library: [libc.so.6]
+  SONAME            Library soname: [libfoo.so.1]
+  RUNPATH           Library runpath: [$ORIGIN/../lib]
+  INIT              0x0000000000004000
+"#;
+        let facts = parse_eu_readelf_facts(output);
+        assert_eq!(facts.needed, vec!["libz.so.1", "libc.so.6"]);
+        assert_eq!(facts.soname.as_deref(), Some("libfoo.so.1"));
+        assert_eq!(facts.runpath, vec!["$ORIGIN/../lib"]);
+        assert_eq!(
+            facts.interpreter.as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+    }
+
+    #[test]
+    fn test_parse_objdump_facts() {
+        let output = r#"
+/usr/lib64/libfoo.so.1:     file format elf64-x86-64
+
+Dynamic Section:
+  NEEDED               libz.so.1
+  NEEDED               libc.so.6
+  SONAME               libfoo.so.1
+  RPATH                /opt/foo/lib:/opt/bar/lib
+  INIT                 0x0000000000004000
+
+Version References:
+  required from libc.so.6:
+"#;
+        let facts = parse_objdump_facts(output);
+        assert_eq!(facts.needed, vec!["libz.so.1", "libc.so.6"]);
+        assert_eq!(facts.soname.as_deref(), Some("libfoo.so.1"));
+        assert_eq!(facts.rpath, vec!["/opt/foo/lib", "/opt/bar/lib"]);
+        assert!(facts.runpath.is_empty());
+    }
+
+    #[test]
+    fn test_parse_objdump_interp() {
+        let output = r#"
+/bin/sh:     file format elf64-x86-64
+
+Contents of section .interp:
+ 0318 2f6c6962 36342f6c 642d6c69 6e75782d  /lib64/ld-linux-
+ 0328 7838362d 36342e73 6f2e3200           x86-64.so.2.
+"#;
+        assert_eq!(
+            parse_objdump_interp(output).as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+    }
+
+    #[test]
+    fn test_parse_objdump_symbols() {
+        let output = "
+/usr/lib64/libfoo.so.1:     file format elf64-x86-64
+
+Dynamic Section:
+  NEEDED               libc.so.6
+  VERSYM               0x0000000000000480
+
+Version definitions:
+1 0x01 0x0865f4e6 libfoo.so.1
+2 0x00 0x09691a75 FOO_1.0
+3 0x00 0x09691a76 FOO_1.1
+\tFOO_1.0 
+
+Version References:
+  required from libc.so.6:
+    0x09691a75 0x00 04 GLIBC_2.2.5
+    0x069691b4 0x02 05 GLIBC_2.34
+
+DYNAMIC SYMBOL TABLE:
+0000000000000000      DF *UND*\t0000000000000000 (GLIBC_2.2.5) getenv
+0000000000000000  w   D  *UND*\t0000000000000000  Base        __gmon_start__
+0000000000001120 g    DF .text\t000000000000002a  FOO_1.1     foo_open
+0000000000001100 g    DF .text\t000000000000002a (FOO_1.0)    foo_open
+0000000000000000 g    DO *ABS*\t0000000000000000  FOO_1.0     FOO_1.0
+";
+        let table = parse_objdump_symbols(output);
+        assert!(table.versioned);
+        assert_eq!(table.version_definitions, ["FOO_1.0", "FOO_1.1"]);
+        assert_eq!(table.version_needs.len(), 2);
+        assert_eq!(table.version_needs[0].library, "libc.so.6");
+        assert!(!table.version_needs[0].weak);
+        assert!(table.version_needs[1].weak);
+
+        assert_eq!(table.symbols.len(), 5);
+        let getenv = &table.symbols[0];
+        assert_eq!(getenv.version.as_deref(), Some("GLIBC_2.2.5"));
+        assert!(!getenv.defined && !getenv.hidden);
+        assert_eq!(table.symbols[1].binding, SymbolBinding::Weak);
+        assert_eq!(table.symbols[1].version, None);
+        assert!(table.symbols[2].defined && !table.symbols[2].hidden);
+        assert!(table.symbols[3].hidden);
+        assert_eq!(table.symbols[4].version, None);
+    }
+
+    #[test]
+    fn test_parse_objdump_notes() {
+        let output = r#"
+/bin/ls:     file format elf64-x86-64
+
+Contents of section .note.gnu.property:
+ 0338 04000000 20000000 05000000 474e5500  .... .......GNU.
+ 0348 020000c0 04000000 03000000 00000000  ................
+ 0358 028000c0 04000000 05000000 00000000  ................
+Contents of section .note.ABI-tag:
+ 037c 04000000 10000000 01000000 474e5500  ............GNU.
+ 038c 00000000 03000000 02000000 00000000  ................
+"#;
+        let notes = parse_objdump_notes(output);
+        let abi_tag = notes.abi_tag.unwrap();
+        assert_eq!(abi_tag.os, "Linux");
+        assert_eq!(abi_tag.kernel, KernelVersion::new(3, 2, 0));
+        assert!(notes.ibt && notes.shstk);
+        assert_eq!(notes.x86_isa_needed, Some(X86IsaLevel::V3));
+    }
+
+    #[test]
+    fn test_parse_objdump_hardening() {
+        let output = "
+/bin/ls:     file format elf64-x86-64
+architecture: i386:x86-64, flags 0x00000150:
+HAS_SYMS, DYNAMIC, D_PAGED
+start address 0x00000000000061d0
+
+Program Header:
+  INTERP off    0x0000000000000318 vaddr 0x0000000000000318 paddr 0x0000000000000318 align 2**0
+         filesz 0x000000000000001c memsz 0x000000000000001c flags r--
+   STACK off    0x0000000000000000 vaddr 0x0000000000000000 paddr 0x0000000000000000 align 2**4
+         filesz 0x0000000000000000 memsz 0x0000000000000000 flags rw-
+   RELRO off    0x00000000000232b0 vaddr 0x00000000000232b0 paddr 0x00000000000232b0 align 2**0
+         filesz 0x0000000000000d50 memsz 0x0000000000000d50 flags r--
+
+Dynamic Section:
+  NEEDED               libc.so.6
+  FLAGS                0x0000000000000008
+  FLAGS_1              0x0000000008000001
+
+DYNAMIC SYMBOL TABLE:
+0000000000000000      DF *UND*\t0000000000000000 (GLIBC_2.4)  __stack_chk_fail
+0000000000000000      DF *UND*\t0000000000000000 (GLIBC_2.3.4) __snprintf_chk
+0000000000000000      DF *UND*\t0000000000000000 (GLIBC_2.3.4) __printf_chk
+";
+        let hardening = parse_objdump_hardening(output);
+        assert_eq!(hardening.relro, Relro::Full);
+        assert_eq!(hardening.pie, Pie::Enabled);
+        assert!(hardening.nx && !hardening.executable_stack);
+        assert!(hardening.bind_now && !hardening.textrel);
+        assert!(hardening.stack_protector);
+        assert_eq!(hardening.fortified, 2);
+    }
+
+    #[test]
+    fn test_parse_objdump_strings() {
+        let output = r#"
+/bin/ls:     file format elf64-x86-64
+
+Contents of section .dynstr:
+ 0450 006c6962 632e736f 2e360020 20666f6f  .libc.so.6.  foo
+ 0460 0a626172 00011b5b 4b00               .bar...[K.
+"#;
+        let strings = parse_objdump_strings(output);
+        let strings: Vec<_> = strings
+            .iter()
+            .map(|s| (s.section.as_str(), s.offset, s.text.as_str()))
+            .collect();
+        assert_eq!(
+            strings,
+            [
+                (".dynstr", 1, "libc.so.6"),
+                (".dynstr", 11, "foo\\n"),
+                (".dynstr", 23, "[K"),
+            ]
+        );
+    }
+}
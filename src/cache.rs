@@ -0,0 +1,352 @@
+//! Caching of per-object ELF analysis results.
+//!
+//! An [`AnalysisCache`] lives in memory for the duration of a build. Created
+//! with [`AnalysisCache::persistent`], it is also loaded from and saved to a
+//! JSON file, with every entry keyed by the identity (or content) of the file
+//! it describes and by the backend that analyzed it, so that changed files,
+//! or a switch to another tool, cause re-analysis automatically.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::analyze::ElfFacts;
+
+/// Version of the on-disk cache format. Files with another version are ignored.
+const CACHE_FORMAT_VERSION: u32 = 3;
+
+/// How persistent cache entries are matched against files on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheKeyMode {
+    /// Device, inode, size and mtime. Costs one `stat` per lookup.
+    #[default]
+    Identity,
+    /// SHA-256 of the file contents. Survives copies and touch, costs a full read.
+    ContentHash,
+}
+
+/// What a persistent entry was recorded against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FileKey {
+    Identity {
+        dev: u64,
+        ino: u64,
+        size: u64,
+        mtime: i64,
+        mtime_nsec: i64,
+    },
+    Sha256(String),
+}
+
+impl FileKey {
+    fn compute(path: &Path, mode: CacheKeyMode) -> io::Result<Self> {
+        match mode {
+            CacheKeyMode::Identity => {
+                let meta = fs::metadata(path)?;
+                Ok(FileKey::Identity {
+                    dev: meta.dev(),
+                    ino: meta.ino(),
+                    size: meta.size(),
+                    mtime: meta.mtime(),
+                    mtime_nsec: meta.mtime_nsec(),
+                })
+            }
+            CacheKeyMode::ContentHash => Ok(FileKey::Sha256(sha256_file(path)?)),
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 of a file's contents.
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    let digest = hasher.finalize();
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    key: Option<FileKey>,
+    /// [`ElfBackend::name`](crate::ElfBackend::name) of the tool that produced
+    /// `facts`; `None` for facts supplied through [`AnalysisCache::insert`].
+    backend: Option<String>,
+    /// [`CACHE_FORMAT_VERSION`] the entry was recorded under.
+    format: u32,
+    facts: ElfFacts,
+}
+
+impl CacheEntry {
+    /// Returns `true` if the facts came from `backend` under this format.
+    ///
+    /// Backends don't all extract the same facts (not every tool reads
+    /// `.note.dlopen`), so facts from one never stand in for another's.
+    fn produced_by(&self, backend: &str) -> bool {
+        self.format == CACHE_FORMAT_VERSION
+            && self.backend.as_deref().is_none_or(|name| name == backend)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: BTreeMap<PathBuf, CacheEntry>,
+}
+
+/// Cache of [`ElfFacts`], keyed by object path.
+///
+/// Share one cache across closure resolutions so that common libraries
+/// (libc, libsystemd, ...) are only analyzed once per build.
+#[derive(Debug, Default)]
+pub struct AnalysisCache {
+    entries: BTreeMap<PathBuf, CacheEntry>,
+    /// Backing file and key mode, for persistent caches.
+    persist: Option<(PathBuf, CacheKeyMode)>,
+    /// Entries already checked against the file on disk during this run.
+    verified: HashSet<PathBuf>,
+    analyzed: usize,
+}
+
+impl AnalysisCache {
+    /// Create an empty in-memory cache.
+    ///
+    /// Entries are trusted for the lifetime of the cache; files are assumed
+    /// not to change during a single build.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a persistent cache backed by `cache_file`.
+    ///
+    /// A missing file, or one written by an incompatible version of this
+    /// crate, yields an empty cache. Call [`save`](Self::save) to write it back.
+    pub fn persistent(cache_file: &Path, mode: CacheKeyMode) -> Result<Self> {
+        let entries = match fs::read(cache_file) {
+            Ok(data) => match serde_json::from_slice::<CacheFile>(&data) {
+                Ok(file) if file.version == CACHE_FORMAT_VERSION => file.entries,
+                _ => BTreeMap::new(),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read analysis cache: {}", cache_file.display())
+                })
+            }
+        };
+
+        Ok(Self {
+            entries,
+            persist: Some((cache_file.to_path_buf(), mode)),
+            ..Self::default()
+        })
+    }
+
+    /// Write a persistent cache back to its file. No-op for in-memory caches.
+    ///
+    /// Entries for files that no longer exist are dropped.
+    pub fn save(&self) -> Result<()> {
+        let Some((cache_file, _)) = &self.persist else {
+            return Ok(());
+        };
+
+        let entries = self
+            .entries
+            .iter()
+            .filter(|(path, _)| path.exists())
+            .map(|(path, entry)| (path.clone(), entry.clone()))
+            .collect();
+        let file = CacheFile {
+            version: CACHE_FORMAT_VERSION,
+            entries,
+        };
+        let data = serde_json::to_vec(&file)?;
+
+        if let Some(parent) = cache_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write then rename so an interrupted build never leaves a torn cache
+        let tmp = cache_file.with_extension("tmp");
+        fs::write(&tmp, data)
+            .with_context(|| format!("Failed to write analysis cache: {}", tmp.display()))?;
+        fs::rename(&tmp, cache_file)
+            .with_context(|| format!("Failed to write analysis cache: {}", cache_file.display()))?;
+        Ok(())
+    }
+
+    /// Cached facts for `path`, as recorded (not re-validated against disk).
+    pub fn get(&self, path: &Path) -> Option<&ElfFacts> {
+        self.entries.get(path).map(|entry| &entry.facts)
+    }
+
+    /// Record the facts for `path` without running readelf.
+    ///
+    /// The entry is trusted for this run only; a persistent cache re-analyzes
+    /// it on the next run since no file key was recorded.
+    pub fn insert(&mut self, path: PathBuf, facts: ElfFacts) {
+        self.verified.insert(path.clone());
+        self.entries.insert(
+            path,
+            CacheEntry {
+                key: None,
+                backend: None,
+                format: CACHE_FORMAT_VERSION,
+                facts,
+            },
+        );
+    }
+
+    /// Number of objects held in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if nothing has been cached yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of objects this cache actually had to analyze (cache misses).
+    pub fn analyzed(&self) -> usize {
+        self.analyzed
+    }
+
+    /// Returns `true` if the entry for `path` exists, was produced by
+    /// `backend` and still matches the file.
+    pub(crate) fn is_fresh(&mut self, path: &Path, backend: &str) -> bool {
+        let Some(entry) = self.entries.get(path) else {
+            return false;
+        };
+        if !entry.produced_by(backend) {
+            return false;
+        }
+        if self.verified.contains(path) {
+            return true;
+        }
+        let fresh = match &self.persist {
+            None => true,
+            Some((_, mode)) => FileKey::compute(path, *mode).ok() == entry.key,
+        };
+        if fresh {
+            self.verified.insert(path.to_path_buf());
+        }
+        fresh
+    }
+
+    /// Record the result of analyzing `path` with `backend` on behalf of the cache.
+    pub(crate) fn record(&mut self, path: PathBuf, backend: &str, facts: ElfFacts) {
+        let key = self
+            .persist
+            .as_ref()
+            .and_then(|(_, mode)| FileKey::compute(&path, *mode).ok());
+        self.analyzed += 1;
+        self.verified.insert(path.clone());
+        self.entries.insert(
+            path,
+            CacheEntry {
+                key,
+                backend: Some(backend.to_string()),
+                format: CACHE_FORMAT_VERSION,
+                facts,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn facts(needed: &[&str]) -> ElfFacts {
+        ElfFacts {
+            needed: needed.iter().map(|s| s.to_string()).collect(),
+            ..ElfFacts::default()
+        }
+    }
+
+    #[test]
+    fn test_persistent_cache_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let cache_file = temp.path().join("cache/elf.json");
+        let object = temp.path().join("libfoo.so.1");
+        fs::write(&object, "v1").unwrap();
+
+        let mut cache = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+        cache.record(object.clone(), "readelf", facts(&["libc.so.6"]));
+        cache.save().unwrap();
+
+        let mut reloaded = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+        assert!(reloaded.is_fresh(&object, "readelf"));
+        assert_eq!(reloaded.get(&object).unwrap().needed, vec!["libc.so.6"]);
+        assert_eq!(reloaded.analyzed(), 0);
+    }
+
+    #[test]
+    fn test_persistent_cache_invalidated_by_change() {
+        let temp = TempDir::new().unwrap();
+        let cache_file = temp.path().join("elf.json");
+        let object = temp.path().join("libfoo.so.1");
+        fs::write(&object, "v1").unwrap();
+
+        let mut cache = AnalysisCache::persistent(&cache_file, CacheKeyMode::ContentHash).unwrap();
+        cache.record(object.clone(), "readelf", facts(&["libc.so.6"]));
+        cache.save().unwrap();
+
+        fs::write(&object, "v2").unwrap();
+        let mut reloaded =
+            AnalysisCache::persistent(&cache_file, CacheKeyMode::ContentHash).unwrap();
+        assert!(!reloaded.is_fresh(&object, "readelf"));
+    }
+
+    #[test]
+    fn test_persistent_cache_keyed_by_backend() {
+        let temp = TempDir::new().unwrap();
+        let cache_file = temp.path().join("elf.json");
+        let object = temp.path().join("libfoo.so.1");
+        fs::write(&object, "v1").unwrap();
+
+        let mut cache = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+        cache.record(object.clone(), "eu-readelf", facts(&["libc.so.6"]));
+        assert!(!cache.is_fresh(&object, "readelf"));
+        cache.save().unwrap();
+
+        let mut reloaded = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+        assert!(reloaded.is_fresh(&object, "eu-readelf"));
+        assert!(!reloaded.is_fresh(&object, "readelf"));
+    }
+
+    #[test]
+    fn test_persistent_cache_drops_removed_files() {
+        let temp = TempDir::new().unwrap();
+        let cache_file = temp.path().join("elf.json");
+        let kept = temp.path().join("libfoo.so.1");
+        let removed = temp.path().join("libbar.so.1");
+        fs::write(&kept, "v1").unwrap();
+        fs::write(&removed, "v1").unwrap();
+
+        let mut cache = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+        cache.record(kept.clone(), "readelf", facts(&[]));
+        cache.record(removed.clone(), "readelf", facts(&[]));
+        fs::remove_file(&removed).unwrap();
+        cache.save().unwrap();
+
+        let reloaded = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+        assert!(reloaded.get(&kept).is_some());
+        assert!(reloaded.get(&removed).is_none());
+    }
+
+    #[test]
+    fn test_persistent_cache_ignores_corrupt_file() {
+        let temp = TempDir::new().unwrap();
+        let cache_file = temp.path().join("elf.json");
+        fs::write(&cache_file, "not json").unwrap();
+
+        let cache = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+        assert!(cache.is_empty());
+    }
+}
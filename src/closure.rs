@@ -0,0 +1,548 @@
+//! Dependency closure resolution for many binaries at once.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crate::analyze::ElfFacts;
+use crate::backend::{default_backend, ElfBackend};
+use crate::cache::AnalysisCache;
+use crate::dlopen::{DlopenDependency, DlopenPriority};
+use crate::paths::find_library;
+
+/// Merged dependency graph of a set of root binaries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyClosure {
+    /// Every library reached from any root, with the path it resolved to
+    /// in the source root (`None` if it could not be found).
+    pub libraries: BTreeMap<String, Option<PathBuf>>,
+    /// Direct NEEDED entries of every analyzed object (roots and libraries).
+    pub needed: BTreeMap<PathBuf, Vec<String>>,
+    /// Transitive library set pulled in by each root, including libraries
+    /// reached through followed dlopen edges.
+    pub roots: BTreeMap<PathBuf, BTreeSet<String>>,
+    /// `.note.dlopen` dependencies of every analyzed object that declares any.
+    pub dlopen: BTreeMap<PathBuf, Vec<DlopenEdge>>,
+}
+
+/// An optional edge from an object to a library it may `dlopen()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DlopenEdge {
+    pub dependency: DlopenDependency,
+    /// The soname that was followed and added to
+    /// [`libraries`](DependencyClosure::libraries): the first of the
+    /// dependency's sonames found in the source root. `None` if the
+    /// dependency is above [`ClosureOptions::dlopen`] or none was found.
+    pub library: Option<String>,
+}
+
+impl DependencyClosure {
+    /// Names of libraries that were needed but not found in the source root.
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.libraries
+            .iter()
+            .filter(|(_, path)| path.is_none())
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// dlopen dependencies at or above `priority` for which no library was
+    /// followed, with the object declaring them.
+    ///
+    /// With [`ClosureOptions::dlopen`] at least `priority`, these are the
+    /// features the source root can't provide.
+    pub fn unresolved_dlopen(
+        &self,
+        priority: DlopenPriority,
+    ) -> impl Iterator<Item = (&Path, &DlopenDependency)> {
+        self.dlopen.iter().flat_map(move |(path, edges)| {
+            edges
+                .iter()
+                .filter(move |edge| edge.library.is_none() && edge.dependency.priority <= priority)
+                .map(move |edge| (path.as_path(), &edge.dependency))
+        })
+    }
+
+    /// Libraries needed by `root` and by no other root.
+    ///
+    /// This is what removing `root` from the image would save.
+    pub fn exclusive_to(&self, root: &Path) -> BTreeSet<String> {
+        let Some(own) = self.roots.get(root) else {
+            return BTreeSet::new();
+        };
+        own.iter()
+            .filter(|lib| {
+                !self
+                    .roots
+                    .iter()
+                    .any(|(other, libs)| other != root && libs.contains(*lib))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Options controlling how a dependency closure is resolved.
+#[derive(Debug, Clone)]
+pub struct ClosureOptions {
+    /// Number of worker threads used to analyze files.
+    ///
+    /// `0` and `1` both mean serial analysis on the calling thread, which is
+    /// the default and the easiest mode to debug. Results are identical and
+    /// identically ordered regardless of this value.
+    pub jobs: usize,
+    /// Tool used to analyze files. `None` uses the auto-detected [`default_backend`].
+    pub backend: Option<Arc<dyn ElfBackend>>,
+    /// Also follow `.note.dlopen` dependencies of this priority or more
+    /// important, like NEEDED entries. `None`, the default, only records
+    /// them in [`DependencyClosure::dlopen`].
+    pub dlopen: Option<DlopenPriority>,
+}
+
+impl Default for ClosureOptions {
+    fn default() -> Self {
+        Self {
+            jobs: 1,
+            backend: None,
+            dlopen: None,
+        }
+    }
+}
+
+impl ClosureOptions {
+    /// The configured backend, or the auto-detected [`default_backend`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if no backend is configured and none is installed.
+    pub fn backend(&self) -> Result<&dyn ElfBackend> {
+        match &self.backend {
+            Some(backend) => Ok(backend.as_ref()),
+            None => default_backend(),
+        }
+    }
+}
+
+/// Resolve the merged library closure of several binaries.
+///
+/// Every object is analyzed at most once per `cache`, so passing the same
+/// cache to later calls avoids re-running readelf on shared libraries.
+/// The `extra_lib_paths` parameter is passed to `find_library` for each lookup.
+pub fn resolve_closure(
+    source_root: &Path,
+    roots: &[&Path],
+    extra_lib_paths: &[&str],
+    cache: &mut AnalysisCache,
+) -> Result<DependencyClosure> {
+    resolve_closure_with_options(
+        source_root,
+        roots,
+        extra_lib_paths,
+        cache,
+        &ClosureOptions::default(),
+    )
+}
+
+/// Resolve the merged library closure of several binaries with explicit options.
+///
+/// The graph is walked breadth-first. Each level's cache misses are analyzed
+/// together (on `options.jobs` workers), then merged in sorted path order so
+/// that the result never depends on scheduling.
+pub fn resolve_closure_with_options(
+    source_root: &Path,
+    roots: &[&Path],
+    extra_lib_paths: &[&str],
+    cache: &mut AnalysisCache,
+    options: &ClosureOptions,
+) -> Result<DependencyClosure> {
+    let backend = options.backend()?;
+    let mut closure = DependencyClosure::default();
+    let mut frontier: BTreeSet<PathBuf> = roots.iter().map(|p| p.to_path_buf()).collect();
+
+    while !frontier.is_empty() {
+        frontier.retain(|path| !closure.needed.contains_key(path));
+        // A closure with an unanalyzable object in it would be incomplete
+        if let Some((_, e)) = analyze_misses(backend, &frontier, cache, options.jobs)
+            .into_iter()
+            .next()
+        {
+            return Err(e);
+        }
+
+        let mut next = BTreeSet::new();
+        for path in frontier {
+            let (deps, dlopen) = cache
+                .get(&path)
+                .map(|facts| (facts.needed.clone(), facts.dlopen.clone()))
+                .unwrap_or_default();
+            for lib_name in &deps {
+                if closure.libraries.contains_key(lib_name) {
+                    continue;
+                }
+                let lib_path = find_library(source_root, lib_name, extra_lib_paths);
+                if let Some(lib_path) = &lib_path {
+                    if !closure.needed.contains_key(lib_path) {
+                        next.insert(lib_path.clone());
+                    }
+                }
+                closure.libraries.insert(lib_name.clone(), lib_path);
+            }
+
+            let mut edges = Vec::new();
+            for dependency in dlopen {
+                let followed = options.dlopen.is_some_and(|max| dependency.priority <= max);
+                let library = followed
+                    .then(|| {
+                        dependency.sonames.iter().find_map(|soname| {
+                            if let Some(found) = closure.libraries.get(soname) {
+                                return found.as_ref().map(|_| soname.clone());
+                            }
+                            let lib_path = find_library(source_root, soname, extra_lib_paths)?;
+                            if !closure.needed.contains_key(&lib_path) {
+                                next.insert(lib_path.clone());
+                            }
+                            closure.libraries.insert(soname.clone(), Some(lib_path));
+                            Some(soname.clone())
+                        })
+                    })
+                    .flatten();
+                edges.push(DlopenEdge {
+                    dependency,
+                    library,
+                });
+            }
+            if !edges.is_empty() {
+                closure.dlopen.insert(path.clone(), edges);
+            }
+            closure.needed.insert(path, deps);
+        }
+        frontier = next;
+    }
+
+    for root in roots {
+        let libs = transitive_libraries(&closure, root);
+        closure.roots.insert(root.to_path_buf(), libs);
+    }
+
+    Ok(closure)
+}
+
+/// Analyze `paths`, returning results in input order.
+///
+/// With more than one job the paths are split into contiguous chunks, one
+/// per scoped worker thread, and each chunk is analyzed in batches.
+pub(crate) fn analyze_all(
+    backend: &dyn ElfBackend,
+    paths: &[PathBuf],
+    jobs: usize,
+) -> Result<Vec<ElfFacts>> {
+    let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+    if jobs <= 1 || paths.len() <= 1 {
+        return backend.analyze_batch(&paths);
+    }
+
+    let chunk_size = paths.len().div_ceil(jobs);
+    thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || backend.analyze_batch(chunk)))
+            .collect();
+        let mut results = Vec::with_capacity(paths.len());
+        for worker in workers {
+            results.extend(worker.join().expect("analysis worker panicked")?);
+        }
+        Ok(results)
+    })
+}
+
+/// Analyze those of `paths` that have no fresh entry in `cache`, and record them.
+///
+/// The misses are analyzed together, as [`analyze_all`] does. One bad file
+/// fails a whole batch, so a failed batch is redone one file at a time; files
+/// that still fail are left out of the cache and returned with their error.
+pub(crate) fn analyze_misses<'a>(
+    backend: &dyn ElfBackend,
+    paths: impl IntoIterator<Item = &'a PathBuf>,
+    cache: &mut AnalysisCache,
+    jobs: usize,
+) -> Vec<(PathBuf, anyhow::Error)> {
+    let misses: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| !cache.is_fresh(path, backend.name()))
+        .cloned()
+        .collect();
+    let results = match analyze_all(backend, &misses, jobs) {
+        Ok(results) => results.into_iter().map(Ok).collect(),
+        Err(_) => read_each(&misses, jobs, |path| Ok(backend.analyze(path)))
+            .expect("per-file results carry their own errors"),
+    };
+
+    let mut failures = Vec::new();
+    for (path, result) in misses.into_iter().zip(results) {
+        match result {
+            Ok(facts) => cache.record(path, backend.name(), facts),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    failures
+}
+
+/// Run `read` on every path, returning results in input order.
+///
+/// Uses the same chunking over `jobs` scoped threads as [`analyze_all`], for
+/// per-file reads that have no batch mode.
+pub(crate) fn read_each<T: Send>(
+    paths: &[PathBuf],
+    jobs: usize,
+    read: impl Fn(&Path) -> Result<T> + Sync,
+) -> Result<Vec<T>> {
+    if jobs <= 1 || paths.len() <= 1 {
+        return paths.iter().map(|path| read(path)).collect();
+    }
+
+    let chunk_size = paths.len().div_ceil(jobs);
+    let read = &read;
+    thread::scope(|scope| {
+        let workers: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| read(path))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        let mut results = Vec::with_capacity(paths.len());
+        for worker in workers {
+            results.extend(worker.join().expect("analysis worker panicked")?);
+        }
+        Ok(results)
+    })
+}
+
+/// Walk the analyzed graph from `root` and collect every library it reaches.
+fn transitive_libraries(closure: &DependencyClosure, root: &Path) -> BTreeSet<String> {
+    let mut libs = BTreeSet::new();
+    let mut stack = vec![root];
+
+    while let Some(path) = stack.pop() {
+        let Some(deps) = closure.needed.get(path) else {
+            continue;
+        };
+        let followed = closure
+            .dlopen
+            .get(path)
+            .into_iter()
+            .flatten()
+            .filter_map(|edge| edge.library.as_ref());
+        for lib_name in deps.iter().chain(followed) {
+            if libs.insert(lib_name.clone()) {
+                if let Some(Some(lib_path)) = closure.libraries.get(lib_name) {
+                    stack.push(lib_path);
+                }
+            }
+        }
+    }
+
+    libs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Build a mock rootfs whose analysis results are pre-seeded in the cache.
+    fn mock_graph(root: &Path, cache: &mut AnalysisCache, objects: &[(&str, &[&str])]) {
+        for (rel, needed) in objects {
+            let path = root.join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+            let facts = ElfFacts {
+                needed: needed.iter().map(|s| s.to_string()).collect(),
+                ..ElfFacts::default()
+            };
+            cache.insert(path, facts);
+        }
+    }
+
+    #[test]
+    fn test_resolve_closure_merges_roots() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let mut cache = AnalysisCache::new();
+        mock_graph(
+            root,
+            &mut cache,
+            &[
+                ("usr/bin/a", &["liba.so.1", "libc.so.6"]),
+                ("usr/bin/b", &["libb.so.1"]),
+                ("usr/lib64/liba.so.1", &["libc.so.6"]),
+                ("usr/lib64/libb.so.1", &["libc.so.6", "libgone.so.0"]),
+                ("usr/lib64/libc.so.6", &[]),
+            ],
+        );
+
+        let a = root.join("usr/bin/a");
+        let b = root.join("usr/bin/b");
+        let closure = resolve_closure(root, &[&a, &b], &[], &mut cache).unwrap();
+
+        let names: Vec<_> = closure.libraries.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            ["liba.so.1", "libb.so.1", "libc.so.6", "libgone.so.0"]
+        );
+        assert_eq!(closure.missing().collect::<Vec<_>>(), ["libgone.so.0"]);
+        assert_eq!(closure.needed.len(), 5);
+        // Everything was pre-seeded, so nothing had to be analyzed
+        assert_eq!(cache.analyzed(), 0);
+    }
+
+    #[test]
+    fn test_resolve_closure_per_root_attribution() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let mut cache = AnalysisCache::new();
+        mock_graph(
+            root,
+            &mut cache,
+            &[
+                ("usr/bin/a", &["liba.so.1"]),
+                ("usr/bin/b", &["libb.so.1"]),
+                ("usr/lib64/liba.so.1", &["libc.so.6"]),
+                ("usr/lib64/libb.so.1", &["libc.so.6"]),
+                ("usr/lib64/libc.so.6", &[]),
+            ],
+        );
+
+        let a = root.join("usr/bin/a");
+        let b = root.join("usr/bin/b");
+        let closure = resolve_closure(root, &[&a, &b], &[], &mut cache).unwrap();
+
+        let a_libs: Vec<_> = closure.roots[&a].iter().map(String::as_str).collect();
+        assert_eq!(a_libs, ["liba.so.1", "libc.so.6"]);
+        let b_exclusive: Vec<_> = closure.exclusive_to(&b).into_iter().collect();
+        assert_eq!(b_exclusive, ["libb.so.1"]);
+    }
+
+    #[test]
+    fn test_resolve_closure_dlopen_edges() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let mut cache = AnalysisCache::new();
+        mock_graph(
+            root,
+            &mut cache,
+            &[
+                ("usr/lib64/libfido2.so.1", &["libcbor.so.0"]),
+                ("usr/lib64/libcbor.so.0", &[]),
+                ("usr/lib64/libtss2-esys.so.0", &[]),
+            ],
+        );
+        let dependency = |feature: &str, priority, sonames: &[&str]| DlopenDependency {
+            feature: Some(feature.to_string()),
+            priority,
+            sonames: sonames.iter().map(|s| s.to_string()).collect(),
+            ..DlopenDependency::default()
+        };
+        let tool = root.join("usr/bin/systemd-cryptenroll");
+        fs::create_dir_all(tool.parent().unwrap()).unwrap();
+        fs::write(&tool, "").unwrap();
+        cache.insert(
+            tool.clone(),
+            ElfFacts {
+                dlopen: vec![
+                    dependency(
+                        "tpm",
+                        DlopenPriority::Recommended,
+                        &["libtss2-esys.so.1", "libtss2-esys.so.0"],
+                    ),
+                    dependency("fido2", DlopenPriority::Suggested, &["libfido2.so.1"]),
+                    dependency(
+                        "pwquality",
+                        DlopenPriority::Required,
+                        &["libpwquality.so.1"],
+                    ),
+                ],
+                ..ElfFacts::default()
+            },
+        );
+
+        // By default the edges are only recorded
+        let closure = resolve_closure(root, &[&tool], &[], &mut cache).unwrap();
+        assert!(closure.libraries.is_empty());
+        assert_eq!(closure.dlopen[&tool].len(), 3);
+        assert!(closure.dlopen[&tool]
+            .iter()
+            .all(|edge| edge.library.is_none()));
+
+        let options = ClosureOptions {
+            dlopen: Some(DlopenPriority::Recommended),
+            ..ClosureOptions::default()
+        };
+        let closure =
+            resolve_closure_with_options(root, &[&tool], &[], &mut cache, &options).unwrap();
+        let followed: Vec<_> = closure.dlopen[&tool]
+            .iter()
+            .map(|edge| edge.library.as_deref())
+            .collect();
+        assert_eq!(followed, [Some("libtss2-esys.so.0"), None, None]);
+        let libs: Vec<_> = closure.roots[&tool].iter().map(String::as_str).collect();
+        assert_eq!(libs, ["libtss2-esys.so.0"]);
+        let unresolved: Vec<_> = closure
+            .unresolved_dlopen(DlopenPriority::Recommended)
+            .map(|(_, dependency)| dependency.feature.as_deref().unwrap())
+            .collect();
+        assert_eq!(unresolved, ["pwquality"]);
+
+        // Suggested features pull in their own NEEDED libraries too
+        let options = ClosureOptions {
+            dlopen: Some(DlopenPriority::Suggested),
+            ..ClosureOptions::default()
+        };
+        let closure =
+            resolve_closure_with_options(root, &[&tool], &[], &mut cache, &options).unwrap();
+        let libs: Vec<_> = closure.roots[&tool].iter().map(String::as_str).collect();
+        assert_eq!(libs, ["libcbor.so.0", "libfido2.so.1", "libtss2-esys.so.0"]);
+    }
+
+    /// Fails for files named `bad`, like a tool choking on one file.
+    #[derive(Debug)]
+    struct PickyBackend;
+
+    impl ElfBackend for PickyBackend {
+        fn name(&self) -> &str {
+            "picky"
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn analyze(&self, path: &Path) -> Result<ElfFacts> {
+            if path.ends_with("bad") {
+                anyhow::bail!("Failed to analyze: {}", path.display());
+            }
+            Ok(ElfFacts::default())
+        }
+    }
+
+    #[test]
+    fn test_analyze_misses_isolates_bad_files() {
+        let paths = [
+            PathBuf::from("/usr/bin/good"),
+            PathBuf::from("/usr/bin/bad"),
+        ];
+        let mut cache = AnalysisCache::new();
+
+        let failures = analyze_misses(&PickyBackend, &paths, &mut cache, 2);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, paths[1]);
+        assert!(cache.get(&paths[0]).is_some());
+        assert!(cache.get(&paths[1]).is_none());
+    }
+}
@@ -0,0 +1,536 @@
+//! Target compatibility: symbol versions, kernel ABI tags and x86 ISA levels.
+
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::closure::{read_each, ClosureOptions, DependencyClosure};
+use crate::symbols::SymbolTable;
+
+/// A Linux kernel version, e.g. 3.2.0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KernelVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl KernelVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for KernelVersion {
+    type Err = anyhow::Error;
+
+    /// Parse `MAJOR.MINOR[.PATCH]`, ignoring any `-suffix` (`6.1.0-18-amd64`).
+    fn from_str(s: &str) -> Result<Self> {
+        let numbers = s.split(['-', '+']).next().unwrap_or("");
+        let parts: Vec<&str> = numbers.split('.').collect();
+        let parse = |part: Option<&&str>| part.map_or(Ok(0), |p| p.parse::<u32>());
+        match (
+            parts.len(),
+            parse(parts.first()),
+            parse(parts.get(1)),
+            parse(parts.get(2)),
+        ) {
+            (2 | 3, Ok(major), Ok(minor), Ok(patch)) => Ok(Self::new(major, minor, patch)),
+            _ => bail!("Invalid kernel version: {:?}", s),
+        }
+    }
+}
+
+impl fmt::Display for KernelVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// x86-64 micro-architecture level from the psABI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum X86IsaLevel {
+    Baseline,
+    V2,
+    V3,
+    V4,
+}
+
+impl X86IsaLevel {
+    /// Parse readelf's spelling, e.g. `x86-64-v3` or `x86-64-baseline`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "x86-64-baseline" => Some(Self::Baseline),
+            "x86-64-v2" => Some(Self::V2),
+            "x86-64-v3" => Some(Self::V3),
+            "x86-64-v4" => Some(Self::V4),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for X86IsaLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Baseline => "x86-64-baseline",
+            Self::V2 => "x86-64-v2",
+            Self::V3 => "x86-64-v3",
+            Self::V4 => "x86-64-v4",
+        })
+    }
+}
+
+/// The `.note.ABI-tag` of an object: the oldest kernel it runs on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbiTag {
+    /// Usually `Linux`.
+    pub os: String,
+    pub kernel: KernelVersion,
+}
+
+/// Compatibility-related ELF notes of one object.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElfNotes {
+    pub abi_tag: Option<AbiTag>,
+    /// Highest level in the GNU property `x86 ISA needed` list.
+    pub x86_isa_needed: Option<X86IsaLevel>,
+    /// Built for Intel CET indirect branch tracking (`x86 feature: IBT`).
+    pub ibt: bool,
+    /// Built for Intel CET shadow stacks (`x86 feature: SHSTK`).
+    pub shstk: bool,
+}
+
+/// What the image has to run on. `None` fields are not checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatTarget {
+    /// Oldest kernel the image must boot on.
+    pub min_kernel: Option<KernelVersion>,
+    /// Newest x86-64 level the target CPUs support.
+    pub x86_isa_level: Option<X86IsaLevel>,
+}
+
+/// One way an object is incompatible with its libraries or the target.
+///
+/// `path` is always the host path of the object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompatFinding {
+    /// A version the object requires is not defined by the library that
+    /// should provide it, e.g. `GLIBC_2.38` against a 2.34 libc.
+    SymbolVersion {
+        path: PathBuf,
+        library: String,
+        required: String,
+        /// Newest version of the same family the library does define.
+        newest: Option<String>,
+    },
+    /// The ABI tag asks for a newer kernel than the target's minimum.
+    Kernel {
+        path: PathBuf,
+        required: KernelVersion,
+        target: KernelVersion,
+    },
+    /// Built for a higher x86-64 level than the target CPUs.
+    IsaLevel {
+        path: PathBuf,
+        required: X86IsaLevel,
+        target: X86IsaLevel,
+    },
+}
+
+impl CompatFinding {
+    /// The object this finding is about.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::SymbolVersion { path, .. }
+            | Self::Kernel { path, .. }
+            | Self::IsaLevel { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for CompatFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SymbolVersion {
+                path,
+                library,
+                required,
+                newest,
+            } => {
+                write!(
+                    f,
+                    "{}: requires {} from {}",
+                    path.display(),
+                    required,
+                    library
+                )?;
+                match newest {
+                    Some(newest) => write!(f, ", which only provides up to {}", newest),
+                    None => write!(f, ", which doesn't define it"),
+                }
+            }
+            Self::Kernel {
+                path,
+                required,
+                target,
+            } => write!(
+                f,
+                "{}: requires kernel {} but the target minimum is {}",
+                path.display(),
+                required,
+                target
+            ),
+            Self::IsaLevel {
+                path,
+                required,
+                target,
+            } => write!(
+                f,
+                "{}: requires {} but the target CPU level is {}",
+                path.display(),
+                required,
+                target
+            ),
+        }
+    }
+}
+
+/// Outcome of [`check_compat`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatReport {
+    /// Number of objects checked.
+    pub objects: usize,
+    /// Newest numbered version required from each library across the
+    /// closure, e.g. `libc.so.6` → `GLIBC_2.34`. Markers such as
+    /// `GLIBC_PRIVATE` or `GLIBC_ABI_DT_RELR` are not versions and never count.
+    pub required_versions: BTreeMap<String, String>,
+    /// Incompatibilities, ordered by object.
+    pub findings: Vec<CompatFinding>,
+}
+
+impl CompatReport {
+    /// Returns `true` if nothing was found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Check every object of `closure` against its libraries and `target`.
+///
+/// Shorthand for [`check_compat_with_options`] with default options.
+pub fn check_compat(closure: &DependencyClosure, target: &CompatTarget) -> Result<CompatReport> {
+    check_compat_with_options(closure, target, &ClosureOptions::default())
+}
+
+/// Check every object of `closure` against its libraries and `target`.
+///
+/// - Each `.gnu.version_r` requirement (`GLIBC_2.38` from `libc.so.6`,
+///   `GLIBCXX_3.4.30` from `libstdc++.so.6`, ...) must be defined by the
+///   library the closure resolved it to; weak requirements are ignored, as
+///   are libraries the closure couldn't find.
+/// - The `.note.ABI-tag` kernel version must not exceed `target.min_kernel`.
+/// - The GNU property `x86 ISA needed` level must not exceed
+///   `target.x86_isa_level`.
+///
+/// # Errors
+///
+/// Fails if no backend is available or the backend can't read symbol
+/// versions or notes.
+pub fn check_compat_with_options(
+    closure: &DependencyClosure,
+    target: &CompatTarget,
+    options: &ClosureOptions,
+) -> Result<CompatReport> {
+    let backend = options.backend()?;
+    let objects: Vec<PathBuf> = closure.needed.keys().cloned().collect();
+    let tables = read_each(&objects, options.jobs, |path| backend.symbols(path))?;
+    let notes = read_each(&objects, options.jobs, |path| backend.notes(path))?;
+    let tables = objects.iter().cloned().zip(tables).collect();
+    let notes = objects.into_iter().zip(notes).collect();
+    Ok(check_objects(closure, target, &tables, &notes))
+}
+
+fn check_objects(
+    closure: &DependencyClosure,
+    target: &CompatTarget,
+    tables: &BTreeMap<PathBuf, SymbolTable>,
+    notes: &BTreeMap<PathBuf, ElfNotes>,
+) -> CompatReport {
+    let mut report = CompatReport {
+        objects: tables.len(),
+        ..CompatReport::default()
+    };
+    for (path, table) in tables {
+        for need in table.version_needs.iter().filter(|need| !need.weak) {
+            if is_numbered(&need.version) {
+                let newest = report
+                    .required_versions
+                    .entry(need.library.clone())
+                    .or_insert_with(|| need.version.clone());
+                if compare_versions(&need.version, newest) == Ordering::Greater {
+                    *newest = need.version.clone();
+                }
+            }
+
+            let Some(Some(library)) = closure.libraries.get(&need.library) else {
+                continue;
+            };
+            let Some(defined) = tables.get(library) else {
+                continue;
+            };
+            if defined.version_definitions.contains(&need.version) {
+                continue;
+            }
+            let family = version_family(&need.version);
+            report.findings.push(CompatFinding::SymbolVersion {
+                path: path.clone(),
+                library: need.library.clone(),
+                required: need.version.clone(),
+                newest: defined
+                    .version_definitions
+                    .iter()
+                    .filter(|v| version_family(v) == family)
+                    .max_by(|a, b| compare_versions(a, b))
+                    .cloned(),
+            });
+        }
+
+        let Some(notes) = notes.get(path) else {
+            continue;
+        };
+        if let (Some(tag), Some(min_kernel)) = (&notes.abi_tag, target.min_kernel) {
+            if tag.kernel > min_kernel {
+                report.findings.push(CompatFinding::Kernel {
+                    path: path.clone(),
+                    required: tag.kernel,
+                    target: min_kernel,
+                });
+            }
+        }
+        if let (Some(needed), Some(level)) = (notes.x86_isa_needed, target.x86_isa_level) {
+            if needed > level {
+                report.findings.push(CompatFinding::IsaLevel {
+                    path: path.clone(),
+                    required: needed,
+                    target: level,
+                });
+            }
+        }
+    }
+    report
+}
+
+/// The non-numeric prefix of a version name: `GLIBC_` for `GLIBC_2.34`.
+fn version_family(version: &str) -> &str {
+    let end = version
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(version.len());
+    &version[..end]
+}
+
+/// Returns `true` for `NAME_X.Y` style names, whose numbers can be ordered.
+fn is_numbered(version: &str) -> bool {
+    let numbers = &version[version_family(version).len()..];
+    !numbers.is_empty()
+        && numbers
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == '_')
+}
+
+/// Order version names of one family numerically (`GLIBC_2.9` < `GLIBC_2.34`).
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let numbers = |v: &str| -> Vec<u64> {
+        v[version_family(v).len()..]
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    };
+    version_family(a)
+        .cmp(version_family(b))
+        .then_with(|| numbers(a).cmp(&numbers(b)))
+}
+
+/// Parse readelf -W -n output into [`ElfNotes`].
+///
+/// ```text
+/// Displaying notes found in: .note.gnu.property
+///   Owner                Data size     Description
+///   GNU                  0x00000010    NT_GNU_PROPERTY_TYPE_0          Properties: x86 ISA needed: x86-64-baseline, x86-64-v2
+///
+/// Displaying notes found in: .note.ABI-tag
+///   Owner                Data size     Description
+///   GNU                  0x00000010    NT_GNU_ABI_TAG (ABI version tag)        OS: Linux, ABI: 3.2.0
+/// ```
+///
+/// llvm-readelf prints the descriptions on the following line instead, and
+/// eu-readelf spells the CET property `X86 FEATURE_1_AND: 00000003 IBT SHSTK`.
+pub fn parse_readelf_notes(output: &str) -> ElfNotes {
+    let mut notes = ElfNotes::default();
+
+    for line in output.lines() {
+        if let Some(rest) = line.split_once("x86 ISA needed:").map(|(_, rest)| rest) {
+            let highest = rest
+                .split([',', ' ', '\t'])
+                .filter_map(X86IsaLevel::parse)
+                .max();
+            notes.x86_isa_needed = notes.x86_isa_needed.max(highest);
+        }
+        if let Some((_, rest)) = line
+            .split_once("x86 feature:")
+            .or_else(|| line.split_once("FEATURE_1_AND:"))
+        {
+            // The list runs until the next property, e.g. "x86 ISA needed:"
+            let features = rest.split("x86 ").next().unwrap_or("");
+            for feature in features.split([',', ' ', '\t']) {
+                match feature {
+                    "IBT" => notes.ibt = true,
+                    "SHSTK" => notes.shstk = true,
+                    _ => {}
+                }
+            }
+        }
+        if let Some((os, abi)) = line
+            .split_once("OS: ")
+            .and_then(|(_, rest)| rest.split_once(", ABI: "))
+        {
+            if let Ok(kernel) = abi.trim().parse() {
+                notes.abi_tag = Some(AbiTag {
+                    os: os.trim().to_string(),
+                    kernel,
+                });
+            }
+        }
+    }
+
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols::VersionNeed;
+
+    #[test]
+    fn test_parse_readelf_notes() {
+        let gnu = "Displaying notes found in: .note.gnu.property\n  GNU                  0x00000020\tNT_GNU_PROPERTY_TYPE_0\t      Properties: x86 feature: IBT, SHSTK, x86 ISA needed: x86-64-baseline, x86-64-v3\n\nDisplaying notes found in: .note.ABI-tag\n  GNU                  0x00000010\tNT_GNU_ABI_TAG (ABI version tag)\t    OS: Linux, ABI: 3.2.0\n";
+        let notes = parse_readelf_notes(gnu);
+        assert_eq!(notes.x86_isa_needed, Some(X86IsaLevel::V3));
+        assert!(notes.ibt && notes.shstk);
+        let tag = notes.abi_tag.unwrap();
+        assert_eq!(tag.os, "Linux");
+        assert_eq!(tag.kernel, KernelVersion::new(3, 2, 0));
+
+        let llvm = "  GNU                  0x00000010\tNT_GNU_ABI_TAG (ABI version tag)\n    OS: Linux, ABI: 4.4.0\n";
+        let notes = parse_readelf_notes(llvm);
+        assert_eq!(notes.abi_tag.unwrap().kernel, KernelVersion::new(4, 4, 0));
+        assert_eq!(notes.x86_isa_needed, None);
+    }
+
+    #[test]
+    fn test_versions() {
+        assert_eq!(
+            "6.1.0-18-amd64".parse::<KernelVersion>().unwrap(),
+            KernelVersion::new(6, 1, 0)
+        );
+        assert_eq!(
+            "5.10".parse::<KernelVersion>().unwrap(),
+            KernelVersion::new(5, 10, 0)
+        );
+        assert!("linux".parse::<KernelVersion>().is_err());
+
+        assert_eq!(compare_versions("GLIBC_2.9", "GLIBC_2.34"), Ordering::Less);
+        assert_eq!(
+            compare_versions("GLIBC_2.3.4", "GLIBC_2.3"),
+            Ordering::Greater
+        );
+        assert_eq!(version_family("GLIBCXX_3.4.30"), "GLIBCXX_");
+        assert!(is_numbered("GLIBC_2.2.5"));
+        assert!(!is_numbered("GLIBC_PRIVATE"));
+        assert!(!is_numbered("GLIBC_ABI_DT_RELR"));
+    }
+
+    #[test]
+    fn test_check_objects() {
+        let bin = PathBuf::from("/usr/bin/tool");
+        let libc = PathBuf::from("/usr/lib64/libc.so.6");
+        let need = |version: &str| VersionNeed {
+            library: "libc.so.6".to_string(),
+            version: version.to_string(),
+            weak: false,
+        };
+        let mut closure = DependencyClosure::default();
+        closure
+            .libraries
+            .insert("libc.so.6".into(), Some(libc.clone()));
+        let mut tables = BTreeMap::new();
+        tables.insert(
+            bin.clone(),
+            SymbolTable {
+                version_needs: vec![
+                    need("GLIBC_2.2.5"),
+                    need("GLIBC_PRIVATE"),
+                    need("GLIBC_2.38"),
+                ],
+                ..SymbolTable::default()
+            },
+        );
+        tables.insert(
+            libc.clone(),
+            SymbolTable {
+                version_definitions: vec![
+                    "GLIBC_2.2.5".into(),
+                    "GLIBC_2.34".into(),
+                    "GLIBC_PRIVATE".into(),
+                ],
+                ..SymbolTable::default()
+            },
+        );
+        let mut notes = BTreeMap::new();
+        notes.insert(
+            bin.clone(),
+            ElfNotes {
+                abi_tag: Some(AbiTag {
+                    os: "Linux".into(),
+                    kernel: KernelVersion::new(3, 2, 0),
+                }),
+                x86_isa_needed: Some(X86IsaLevel::V3),
+                ..ElfNotes::default()
+            },
+        );
+        let target = CompatTarget {
+            min_kernel: Some(KernelVersion::new(4, 19, 0)),
+            x86_isa_level: Some(X86IsaLevel::V2),
+        };
+
+        let report = check_objects(&closure, &target, &tables, &notes);
+        assert_eq!(report.objects, 2);
+        assert_eq!(report.required_versions["libc.so.6"], "GLIBC_2.38");
+        assert_eq!(
+            report.findings,
+            vec![
+                CompatFinding::SymbolVersion {
+                    path: bin.clone(),
+                    library: "libc.so.6".into(),
+                    required: "GLIBC_2.38".into(),
+                    newest: Some("GLIBC_2.34".into()),
+                },
+                CompatFinding::IsaLevel {
+                    path: bin.clone(),
+                    required: X86IsaLevel::V3,
+                    target: X86IsaLevel::V2,
+                },
+            ]
+        );
+        assert_eq!(
+            report.findings[0].to_string(),
+            "/usr/bin/tool: requires GLIBC_2.38 from libc.so.6, which only provides up to GLIBC_2.34"
+        );
+    }
+}
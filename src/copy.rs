@@ -1,11 +1,21 @@
 //! File and library copying utilities.
 
-use anyhow::{Context, Result};
-use std::fs;
-use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 
-use crate::paths::find_library;
+use crate::cache::sha256_file;
+use crate::fastcopy::{copy_file_data, CopyMethod, CopyStrategy};
+use crate::filter::FilterRules;
+use crate::metadata::{apply_metadata, PreserveOptions};
+use crate::paths::{find_library, resolve_in_root};
 
 /// Make a file executable (chmod 755).
 pub fn make_executable(path: &Path) -> Result<()> {
@@ -18,60 +28,693 @@ pub fn make_executable(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// What to do with device nodes, FIFOs and sockets found in a source tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpecialFilePolicy {
+    /// Recreate them with `mknod`. Device nodes need root; when that fails
+    /// the file is skipped with a warning.
+    #[default]
+    Recreate,
+    /// Leave them out of the destination, with a warning for each.
+    Skip,
+}
+
+/// What a directory copy does when a destination path already exists.
+///
+/// A source directory meeting a destination directory is never a conflict:
+/// the two are always merged. Every other combination of file, directory,
+/// symlink and special file is resolved by the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// What [`copy_dir_recursive`] has always done: files replace files,
+    /// symlinks and special files, directories are merged (also through
+    /// destination symlinks such as a usr-merged `lib -> usr/lib`), and
+    /// existing destination entries are kept where the source has a symlink.
+    /// A file or special file meeting a destination directory fails the
+    /// copy; the directory is never removed.
+    #[default]
+    Overwrite,
+    /// Remove the destination entry (recursively for directories, read-only
+    /// or not) and copy the source entry in its place.
+    Replace,
+    /// Leave the destination entry alone and skip the source entry. Nothing
+    /// is ever written through a destination symlink.
+    Keep,
+    /// Fail the copy on the first conflict.
+    Error,
+    /// Like `Replace`, except that directories win over symlinks: a source
+    /// directory is merged into the directory a destination symlink points
+    /// to, and a source symlink never replaces a destination directory.
+    /// This is what usr-merged destinations (`lib -> usr/lib`) need.
+    MergeIntoDirectory,
+}
+
+/// How an existing destination file is checked against its source before
+/// being skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FreshnessPolicy {
+    /// Assume an existing file is up to date without looking at it.
+    #[default]
+    Trust,
+    /// Up to date if size and modification time match the source. Only
+    /// useful with `PreserveOptions::timestamps`, otherwise every earlier
+    /// copy looks stale.
+    SizeAndMtime,
+    /// Up to date if the SHA-256 of the contents matches the source.
+    ContentHash,
+}
+
+/// What to do with an existing destination file that is not up to date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StalePolicy {
+    /// Copy the source over it.
+    #[default]
+    Update,
+    /// Fail the copy.
+    Error,
+}
+
+/// Type of a source or destination entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// Device node, FIFO or socket.
+    Special,
+}
+
+impl EntryKind {
+    pub(crate) fn of(meta: &Metadata) -> Self {
+        let file_type = meta.file_type();
+        if file_type.is_dir() {
+            Self::Directory
+        } else if file_type.is_symlink() {
+            Self::Symlink
+        } else if file_type.is_file() {
+            Self::File
+        } else {
+            Self::Special
+        }
+    }
+}
+
+/// How a conflict was resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// The destination entry was removed and replaced by the source entry.
+    Replaced,
+    /// The destination entry was kept and the source entry skipped.
+    Kept,
+    /// The source directory was merged into the directory behind a
+    /// destination symlink.
+    MergedIntoDirectory,
+    /// The copy failed; returned as the error of the copy.
+    Failed,
+}
+
+/// A destination path that already existed with a conflicting type or content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Conflict {
+    /// Destination path.
+    pub path: PathBuf,
+    pub source: EntryKind,
+    pub destination: EntryKind,
+    pub resolution: ConflictResolution,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Destination already exists: {} is a {:?}, source is a {:?}",
+            self.path.display(),
+            self.destination,
+            self.source
+        )
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+/// What a copy did with one entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyAction {
+    /// Created a new file, directory or special file.
+    Copied,
+    /// Created a new symlink.
+    Symlinked,
+    /// Created a hardlink to an earlier copy of the same source inode.
+    Hardlinked,
+    /// Replaced an existing destination entry.
+    Replaced,
+    /// Left an existing destination entry alone without checking it
+    /// ([`FreshnessPolicy::Trust`], or a conflict resolved by keeping it).
+    SkippedExisting,
+    /// Left an existing destination file alone after checking that it
+    /// matches the source.
+    UpToDate,
+    /// Replaced an existing destination file that no longer matched the source.
+    Updated,
+    /// Left out by a filter rule.
+    Excluded,
+    /// Left out by the special file policy or for lack of privilege; the
+    /// report's warnings say which.
+    Skipped,
+}
+
+/// One destination entry in a [`CopyReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CopyEntry {
+    pub destination: PathBuf,
+    pub source: PathBuf,
+    /// Type of the source entry.
+    pub kind: EntryKind,
+    pub action: CopyAction,
+    /// Size in bytes of regular files, 0 for everything else.
+    pub size: u64,
+    /// How regular file data was copied, if any was.
+    pub method: Option<CopyMethod>,
+}
+
+/// Options for directory and library copies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyOptions {
+    /// What to do with destination entries that already exist.
+    pub conflicts: ConflictPolicy,
+    /// Metadata carried over from the source.
+    pub preserve: PreserveOptions,
+    /// Handling of device nodes, FIFOs and sockets.
+    pub special_files: SpecialFilePolicy,
+    /// Recreate hardlinked source files as hardlinks instead of separate copies.
+    pub preserve_hardlinks: bool,
+    /// How file data is copied (reflink, `copy_file_range`, plain, or hardlinked).
+    pub strategy: CopyStrategy,
+    /// Include/exclude rules for directory copies, matched against paths
+    /// relative to the source directory.
+    pub filter: FilterRules,
+    /// How existing destination files are checked before being skipped.
+    pub freshness: FreshnessPolicy,
+    /// What to do with existing destination files that fail the check.
+    pub stale: StalePolicy,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            conflicts: ConflictPolicy::default(),
+            preserve: PreserveOptions::default(),
+            special_files: SpecialFilePolicy::default(),
+            preserve_hardlinks: true,
+            strategy: CopyStrategy::default(),
+            filter: FilterRules::default(),
+            freshness: FreshnessPolicy::default(),
+            stale: StalePolicy::default(),
+        }
+    }
+}
+
+/// Outcome of a copy operation.
+///
+/// Serializes to JSON for build logs and size accounting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CopyReport {
+    /// Every entry visited, in the order it was handled.
+    pub entries: Vec<CopyEntry>,
+    /// Total size in bytes of regular files copied.
+    pub bytes_copied: u64,
+    /// Number of files recreated as hardlinks to an earlier copy.
+    pub hardlinks: u64,
+    /// Number of regular files copied with each method.
+    pub methods: BTreeMap<CopyMethod, u64>,
+    /// Destination entries that already existed, and what was done about them.
+    pub conflicts: Vec<Conflict>,
+    /// Non-fatal problems, such as metadata that could not be preserved.
+    pub warnings: Vec<String>,
+}
+
+impl CopyReport {
+    /// Fold another report into this one.
+    pub fn merge(&mut self, other: CopyReport) {
+        self.entries.extend(other.entries);
+        self.bytes_copied += other.bytes_copied;
+        self.hardlinks += other.hardlinks;
+        for (method, count) in other.methods {
+            *self.methods.entry(method).or_default() += count;
+        }
+        self.conflicts.extend(other.conflicts);
+        self.warnings.extend(other.warnings);
+    }
+
+    /// Entries that had the given action.
+    pub fn entries_with(&self, action: CopyAction) -> impl Iterator<Item = &CopyEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.action == action)
+    }
+
+    /// Add an entry for `source` (described by `meta`) copied to `destination`.
+    pub(crate) fn record(
+        &mut self,
+        source: &Path,
+        destination: &Path,
+        meta: &Metadata,
+        action: CopyAction,
+        method: Option<CopyMethod>,
+    ) {
+        self.entries.push(CopyEntry {
+            destination: destination.to_path_buf(),
+            source: source.to_path_buf(),
+            kind: EntryKind::of(meta),
+            action,
+            size: if meta.is_file() { meta.len() } else { 0 },
+            method,
+        });
+    }
+}
+
+/// Where libraries are looked up in the source root and placed in the destination.
+///
+/// The fields mirror the parameters of [`copy_library_to`].
+#[derive(Debug, Clone, Copy)]
+pub struct LibraryLayout<'a> {
+    /// Destination for libraries found in a lib64 directory (e.g. "usr/lib64").
+    pub dest_lib64_path: &'a str,
+    /// Destination for libraries found in a lib directory (e.g. "usr/lib").
+    pub dest_lib_path: &'a str,
+    /// Extra source directories passed to `find_library`.
+    pub extra_lib_paths: &'a [&'a str],
+    /// Private library subdirectories to preserve (e.g. `&["systemd"]`).
+    pub private_lib_dirs: &'a [&'a str],
+}
+
 /// Copy a directory recursively, handling symlinks.
 ///
-/// Skips existing symlinks (additive copy). Use [`copy_dir_recursive_overwrite`]
-/// if you need to replace existing files.
+/// Existing files are overwritten and existing directories merged into,
+/// including through usr-merge symlinks; existing symlinks are left alone
+/// ([`ConflictPolicy::Overwrite`]). Use [`copy_dir_recursive_overwrite`] if
+/// you need to replace existing symlinks too, or [`copy_dir_with_options`]
+/// with [`ConflictPolicy::Keep`] for a purely additive copy.
 ///
-/// Returns the total size in bytes of all files copied.
-pub fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<u64> {
-    copy_dir_recursive_impl(src, dst, false)
+/// Returns a report of every entry; `bytes_copied` is the total size of
+/// all files copied.
+pub fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<CopyReport> {
+    copy_dir_with_options(src, dst, &CopyOptions::default())
 }
 
 /// Copy a directory recursively, overwriting existing files and symlinks.
 ///
-/// Unlike [`copy_dir_recursive`], this will remove and replace existing symlinks.
-/// Useful for overlay creation where you want to replace the destination contents.
+/// Unlike [`copy_dir_recursive`], this replaces existing files and symlinks,
+/// read-only ones included. Directories are merged rather than replaced, also
+/// through destination symlinks such as a usr-merged `lib -> usr/lib`
+/// ([`ConflictPolicy::MergeIntoDirectory`]). Useful for overlay creation where
+/// you want to replace the destination contents.
 ///
-/// Returns the total size in bytes of all files copied.
-pub fn copy_dir_recursive_overwrite(src: &Path, dst: &Path) -> Result<u64> {
-    copy_dir_recursive_impl(src, dst, true)
+/// Returns a report of every entry, as [`copy_dir_recursive`] does.
+pub fn copy_dir_recursive_overwrite(src: &Path, dst: &Path) -> Result<CopyReport> {
+    let options = CopyOptions {
+        conflicts: ConflictPolicy::MergeIntoDirectory,
+        ..CopyOptions::default()
+    };
+    copy_dir_with_options(src, dst, &options)
 }
 
-/// Internal implementation for recursive directory copy.
-fn copy_dir_recursive_impl(src: &Path, dst: &Path, overwrite: bool) -> Result<u64> {
-    let mut total_size: u64 = 0;
+/// Copy a directory recursively with explicit options.
+///
+/// Symlinks are recreated as symlinks, never followed. Metadata selected by
+/// `options.preserve` is applied to every file, symlink and directory;
+/// directories get theirs after their contents so that timestamps stick.
+/// Device nodes, FIFOs and sockets follow `options.special_files`, and files
+/// sharing an inode in the source share one in the destination.
+///
+/// A regular file whose destination is already a regular file is checked
+/// with `options.freshness` first: up-to-date files are skipped and stale
+/// ones handled by `options.stale`. All other existing destination entries
+/// are handled by `options.conflicts`.
+///
+/// Entries matching an exclude rule in `options.filter` are skipped while
+/// walking (excluded directories are never descended into); use
+/// [`FilterRules::excluded_paths`] for a dry run.
+pub fn copy_dir_with_options(src: &Path, dst: &Path, options: &CopyOptions) -> Result<CopyReport> {
+    let mut ctx = CopyContext {
+        options,
+        root: src,
+        dest_root: dst,
+        report: CopyReport::default(),
+        links: HashMap::new(),
+    };
 
     if !src.is_dir() {
-        return Ok(0);
+        return Ok(ctx.report);
     }
 
+    // An existing destination root keeps its own metadata, like any
+    // directory the copy merges into
+    let created = fs::symlink_metadata(dst).is_err();
+    copy_dir_recursive_impl(src, dst, &mut ctx)?;
+    if created {
+        let meta = fs::symlink_metadata(src)?;
+        apply_metadata(src, dst, &meta, &options.preserve, &mut ctx.report.warnings)?;
+    }
+
+    Ok(ctx.report)
+}
+
+/// State threaded through one recursive copy.
+struct CopyContext<'a> {
+    options: &'a CopyOptions,
+    /// Source directory the copy started from, for filter matching.
+    root: &'a Path,
+    /// Destination directory the copy started from; destination symlinks
+    /// are resolved inside it.
+    dest_root: &'a Path,
+    report: CopyReport,
+    /// First destination path of each multiply-linked source inode, by (dev, ino).
+    links: HashMap<(u64, u64), PathBuf>,
+}
+
+/// Internal implementation for recursive directory copy.
+fn copy_dir_recursive_impl(src: &Path, dst: &Path, ctx: &mut CopyContext) -> Result<()> {
+    let options = ctx.options;
     fs::create_dir_all(dst)?;
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
+    // Sorted, so reports and manifests come out the same on every run
+    let mut entries = fs::read_dir(src)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
         let path = entry.path();
         let dest_path = dst.join(entry.file_name());
+        let meta = fs::symlink_metadata(&path)?;
+        let file_type = meta.file_type();
+
+        let rel = path.strip_prefix(ctx.root).unwrap_or(&path);
+        if options.filter.is_excluded(rel, file_type.is_dir()) {
+            ctx.report
+                .record(&path, &dest_path, &meta, CopyAction::Excluded, None);
+            continue;
+        }
 
-        if path.is_dir() {
-            total_size += copy_dir_recursive_impl(&path, &dest_path, overwrite)?;
-        } else if path.is_symlink() {
+        if file_type.is_file()
+            && options.freshness != FreshnessPolicy::Trust
+            && dest_path.symlink_metadata().is_ok_and(|m| m.is_file())
+        {
+            if is_up_to_date(&path, &dest_path, options.freshness)? {
+                ctx.report
+                    .record(&path, &dest_path, &meta, CopyAction::UpToDate, None);
+            } else {
+                update_stale(&path, &dest_path, options, &mut ctx.report)?;
+            }
+            continue;
+        }
+
+        let existed = dest_path.symlink_metadata().is_ok();
+        let replaced = match resolve_conflict(&dest_path, &meta, ctx)? {
+            None => false,
+            Some(ConflictResolution::Replaced) => true,
+            Some(ConflictResolution::Kept) => {
+                ctx.report
+                    .record(&path, &dest_path, &meta, CopyAction::SkippedExisting, None);
+                continue;
+            }
+            Some(ConflictResolution::Failed) => unreachable!("returned as an error"),
+            Some(ConflictResolution::MergedIntoDirectory) => {
+                // The symlink and the directory behind it keep their metadata
+                if let Some(target) = directory_in_root(&dest_path, ctx.dest_root)? {
+                    copy_dir_recursive_impl(&path, &target, ctx)?;
+                }
+                continue;
+            }
+        };
+        let created = |action| {
+            if replaced {
+                CopyAction::Replaced
+            } else {
+                action
+            }
+        };
+
+        if file_type.is_dir() {
+            let merged = existed && !replaced;
+            if !merged {
+                ctx.report
+                    .record(&path, &dest_path, &meta, created(CopyAction::Copied), None);
+            }
+            copy_dir_recursive_impl(&path, &dest_path, ctx)?;
+            if merged {
+                continue; // The existing directory keeps its metadata
+            }
+        } else if file_type.is_symlink() {
             let target = fs::read_link(&path)?;
-            if overwrite && (dest_path.exists() || dest_path.is_symlink()) {
-                fs::remove_file(&dest_path)?;
+            std::os::unix::fs::symlink(&target, &dest_path)?;
+            ctx.report.record(
+                &path,
+                &dest_path,
+                &meta,
+                created(CopyAction::Symlinked),
+                None,
+            );
+        } else if file_type.is_file() {
+            let inode = (meta.dev(), meta.ino());
+            if options.preserve_hardlinks && meta.nlink() > 1 {
+                if let Some(first) = ctx.links.get(&inode) {
+                    // Metadata is shared with the first link, which already has it
+                    replace_with_hardlink(first, &dest_path)?;
+                    ctx.report.hardlinks += 1;
+                    ctx.report.record(
+                        &path,
+                        &dest_path,
+                        &meta,
+                        created(CopyAction::Hardlinked),
+                        None,
+                    );
+                    continue;
+                }
+                ctx.links.insert(inode, dest_path.clone());
             }
-            if !dest_path.exists() && !dest_path.is_symlink() {
-                std::os::unix::fs::symlink(&target, &dest_path)?;
+            let method =
+                copy_file_data(&path, &dest_path, options.strategy).with_context(|| {
+                    format!(
+                        "Failed to copy {} to {}",
+                        path.display(),
+                        dest_path.display()
+                    )
+                })?;
+            *ctx.report.methods.entry(method).or_default() += 1;
+            ctx.report.record(
+                &path,
+                &dest_path,
+                &meta,
+                created(CopyAction::Copied),
+                Some(method),
+            );
+            if method == CopyMethod::Hardlink {
+                continue; // Shares the source inode, metadata included
             }
+            ctx.report.bytes_copied += meta.len();
+        } else if copy_special_file(&path, &dest_path, &meta, ctx)? {
+            ctx.report
+                .record(&path, &dest_path, &meta, created(CopyAction::Copied), None);
         } else {
-            fs::copy(&path, &dest_path)?;
-            if let Ok(meta) = fs::metadata(&dest_path) {
-                total_size += meta.len();
-            }
+            ctx.report
+                .record(&path, &dest_path, &meta, CopyAction::Skipped, None);
+            continue;
+        }
+
+        apply_metadata(
+            &path,
+            &dest_path,
+            &meta,
+            &options.preserve,
+            &mut ctx.report.warnings,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Apply the conflict policy if something already exists at `dest`.
+///
+/// Returns `None` when there is no conflict (nothing there, or a directory
+/// meeting a directory). On [`ConflictResolution::Replaced`] the destination
+/// entry has already been removed. A failed conflict is returned as the
+/// error, as a [`Conflict`].
+fn resolve_conflict(
+    dest: &Path,
+    src_meta: &Metadata,
+    ctx: &mut CopyContext,
+) -> Result<Option<ConflictResolution>> {
+    let Ok(dest_meta) = fs::symlink_metadata(dest) else {
+        return Ok(None);
+    };
+    let source = EntryKind::of(src_meta);
+    let destination = EntryKind::of(&dest_meta);
+    if source == EntryKind::Directory && destination == EntryKind::Directory {
+        return Ok(None);
+    }
+
+    // Follows the symlink inside the destination root: does the
+    // destination lead to a directory there?
+    let dest_is_dir = directory_in_root(dest, ctx.dest_root)?.is_some();
+    let resolution = match ctx.options.conflicts {
+        ConflictPolicy::Keep => ConflictResolution::Kept,
+        ConflictPolicy::Error => ConflictResolution::Failed,
+        ConflictPolicy::Overwrite | ConflictPolicy::MergeIntoDirectory
+            if source == EntryKind::Directory && dest_is_dir =>
+        {
+            ConflictResolution::MergedIntoDirectory
+        }
+        ConflictPolicy::Overwrite if source == EntryKind::Symlink => ConflictResolution::Kept,
+        // Files and special files never take the place of a whole directory tree
+        ConflictPolicy::Overwrite if destination == EntryKind::Directory => {
+            ConflictResolution::Failed
+        }
+        ConflictPolicy::MergeIntoDirectory if source == EntryKind::Symlink && dest_is_dir => {
+            ConflictResolution::Kept
+        }
+        ConflictPolicy::Replace
+        | ConflictPolicy::Overwrite
+        | ConflictPolicy::MergeIntoDirectory => {
+            remove_entry(dest, &dest_meta)?;
+            ConflictResolution::Replaced
+        }
+    };
+
+    let conflict = Conflict {
+        path: dest.to_path_buf(),
+        source,
+        destination,
+        resolution,
+    };
+    if resolution == ConflictResolution::Failed {
+        return Err(conflict.into());
+    }
+    ctx.report.conflicts.push(conflict);
+    Ok(Some(resolution))
+}
+
+/// The directory `dest` leads to inside `dest_root`, if it leads to one.
+///
+/// Destination symlinks are followed as they resolve in the image, so an
+/// absolute link such as `usr/lib -> /usr/lib` leads to `dest_root/usr/lib`
+/// and never to the host's `/usr/lib`.
+fn directory_in_root(dest: &Path, dest_root: &Path) -> Result<Option<PathBuf>> {
+    let Ok(rel) = dest.strip_prefix(dest_root) else {
+        bail!(
+            "Refusing to merge outside destination root {}: {}",
+            dest_root.display(),
+            dest.display()
+        );
+    };
+    let resolved = resolve_in_root(dest_root, rel)?;
+    Ok(fs::symlink_metadata(&resolved)
+        .is_ok_and(|meta| meta.is_dir())
+        .then_some(resolved))
+}
+
+/// Remove a file, symlink or whole directory tree, including read-only ones.
+fn remove_entry(path: &Path, meta: &Metadata) -> Result<()> {
+    if !meta.is_dir() {
+        return fs::remove_file(path)
+            .with_context(|| format!("Failed to remove existing file: {}", path.display()));
+    }
+    if fs::remove_dir_all(path).is_ok() {
+        return Ok(());
+    }
+    // Read-only directories can't have entries removed: open them up first
+    make_tree_writable(path)?;
+    fs::remove_dir_all(path)
+        .with_context(|| format!("Failed to remove existing directory: {}", path.display()))
+}
+
+fn make_tree_writable(dir: &Path) -> Result<()> {
+    let mode = fs::symlink_metadata(dir)?.permissions().mode();
+    fs::set_permissions(dir, fs::Permissions::from_mode(mode | 0o700))
+        .with_context(|| format!("Failed to make directory writable: {}", dir.display()))?;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            make_tree_writable(&entry.path())?;
         }
     }
+    Ok(())
+}
 
-    Ok(total_size)
+/// Make `dest` a hardlink to `first`, replacing whatever file is there.
+fn replace_with_hardlink(first: &Path, dest: &Path) -> Result<()> {
+    if let Ok(existing) = fs::symlink_metadata(dest) {
+        let first_meta = fs::metadata(first)?;
+        if existing.dev() == first_meta.dev() && existing.ino() == first_meta.ino() {
+            return Ok(()); // Already linked by a previous run
+        }
+        fs::remove_file(dest)?;
+    }
+    fs::hard_link(first, dest).with_context(|| {
+        format!(
+            "Failed to hardlink {} to {}",
+            dest.display(),
+            first.display()
+        )
+    })
+}
+
+/// Recreate a device node, FIFO or socket at `dest`.
+///
+/// Returns `Ok(false)` if the file was skipped (by policy or for lack of
+/// privilege), with a warning recorded in the report.
+fn copy_special_file(
+    src: &Path,
+    dest: &Path,
+    meta: &Metadata,
+    ctx: &mut CopyContext,
+) -> Result<bool> {
+    let file_type = meta.file_type();
+    let kind = if file_type.is_block_device() {
+        "block device"
+    } else if file_type.is_char_device() {
+        "character device"
+    } else if file_type.is_fifo() {
+        "FIFO"
+    } else {
+        "socket"
+    };
+
+    if ctx.options.special_files == SpecialFilePolicy::Skip {
+        ctx.report
+            .warnings
+            .push(format!("Skipped {}: {}", kind, src.display()));
+        return Ok(false);
+    }
+
+    if dest.symlink_metadata().is_ok() {
+        fs::remove_file(dest)?;
+    }
+    let cpath = CString::new(dest.as_os_str().as_bytes())?;
+    // SAFETY: cpath is NUL-terminated; mode and rdev come straight from lstat
+    let ret = unsafe {
+        libc::mknod(
+            cpath.as_ptr(),
+            meta.mode() as libc::mode_t,
+            meta.rdev() as libc::dev_t,
+        )
+    };
+    if ret < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EPERM) {
+            ctx.report.warnings.push(format!(
+                "Missing privilege to create {}, skipped: {}",
+                kind,
+                src.display()
+            ));
+            return Ok(false);
+        }
+        return Err(err).with_context(|| format!("Failed to create {}: {}", kind, dest.display()));
+    }
+
+    Ok(true)
 }
 
 /// Copy a library from source to destination, handling symlinks.
@@ -82,6 +725,9 @@ fn copy_dir_recursive_impl(src: &Path, dst: &Path, overwrite: bool) -> Result<u6
 /// The `private_lib_dirs` parameter specifies subdirectories that should preserve
 /// their structure (e.g., `&["systemd"]` for LevitateOS, `&["openrc"]` for AcornOS,
 /// or `&[]` if no private library directories are needed).
+///
+/// Returns a report of the files and symlinks created, or a
+/// `SkippedExisting` entry if the library was already there.
 pub fn copy_library_to(
     source_root: &Path,
     lib_name: &str,
@@ -90,8 +736,35 @@ pub fn copy_library_to(
     dest_lib_path: &str,
     extra_lib_paths: &[&str],
     private_lib_dirs: &[&str],
-) -> Result<()> {
-    let src = find_library(source_root, lib_name, extra_lib_paths).with_context(|| {
+) -> Result<CopyReport> {
+    let layout = LibraryLayout {
+        dest_lib64_path,
+        dest_lib_path,
+        extra_lib_paths,
+        private_lib_dirs,
+    };
+    copy_library_with_options(
+        source_root,
+        lib_name,
+        dest_root,
+        &layout,
+        &CopyOptions::default(),
+    )
+}
+
+/// Copy a library from source to destination with explicit options.
+///
+/// Same placement rules as [`copy_library_to`]; `options.preserve` controls
+/// which metadata is carried over to the copied file and symlink.
+pub fn copy_library_with_options(
+    source_root: &Path,
+    lib_name: &str,
+    dest_root: &Path,
+    layout: &LibraryLayout,
+    options: &CopyOptions,
+) -> Result<CopyReport> {
+    let mut report = CopyReport::default();
+    let src = find_library(source_root, lib_name, layout.extra_lib_paths).with_context(|| {
         format!(
             "Could not find library '{}' in source (searched lib64, lib, extra paths)",
             lib_name
@@ -100,23 +773,29 @@ pub fn copy_library_to(
 
     // Check if this is a private library (e.g., systemd, openrc)
     let src_str = src.to_string_lossy();
-    let private_dir = private_lib_dirs.iter().find(|dir| {
+    let private_dir = layout.private_lib_dirs.iter().find(|dir| {
         src_str.contains(&format!("lib64/{}", dir)) || src_str.contains(&format!("lib/{}", dir))
     });
 
     let dest_path = if let Some(dir) = private_dir {
         // Private libraries stay in their own subdirectory
-        let dest_dir = dest_root.join(dest_lib64_path).join(dir);
+        let dest_dir = dest_root.join(layout.dest_lib64_path).join(dir);
         fs::create_dir_all(&dest_dir)?;
         dest_dir.join(lib_name)
     } else if src_str.contains("lib64") {
-        dest_root.join(dest_lib64_path).join(lib_name)
+        dest_root.join(layout.dest_lib64_path).join(lib_name)
     } else {
-        dest_root.join(dest_lib_path).join(lib_name)
+        dest_root.join(layout.dest_lib_path).join(lib_name)
     };
 
-    if dest_path.exists() {
-        return Ok(()); // Already copied
+    // Destination symlinks are followed inside the image, never out to the
+    // host, and written through rather than replaced
+    let real_dest = resolve_dest(dest_root, &dest_path)?;
+    if real_dest.exists() {
+        // Already copied: the real file behind a library symlink is what may be stale
+        let real_src = fs::canonicalize(&src).unwrap_or_else(|_| src.clone());
+        skip_or_update(&real_src, &real_dest, options, &mut report)?;
+        return Ok(report);
     }
 
     // Handle symlinks - copy both the symlink target and create the symlink
@@ -135,23 +814,143 @@ pub fn copy_library_to(
         if actual_src.exists() {
             // Copy the actual file first
             let target_name = link_target.file_name().unwrap_or(link_target.as_os_str());
-            let target_dest = dest_path.parent().unwrap().join(target_name);
-            if !target_dest.exists() {
-                fs::copy(&actual_src, &target_dest)?;
+            let target_dest = resolve_dest(dest_root, &dest_path.with_file_name(target_name))?;
+            if target_dest.exists() {
+                skip_or_update(&actual_src, &target_dest, options, &mut report)?;
+            } else {
+                copy_file(&actual_src, &target_dest, options, &mut report)?;
             }
             // Create symlink
-            if !dest_path.exists() {
+            if dest_path.symlink_metadata().is_err() {
                 std::os::unix::fs::symlink(&link_target, &dest_path)?;
+                let meta = fs::symlink_metadata(&src)?;
+                report.record(&src, &dest_path, &meta, CopyAction::Symlinked, None);
+                apply_metadata(
+                    &src,
+                    &dest_path,
+                    &meta,
+                    &options.preserve,
+                    &mut report.warnings,
+                )?;
             }
         } else {
             // Symlink target not found, copy the symlink itself
-            fs::copy(&src, &dest_path)?;
+            copy_file(&src, &real_dest, options, &mut report)?;
         }
     } else {
-        fs::copy(&src, &dest_path)?;
+        copy_file(&src, &real_dest, options, &mut report)?;
     }
 
-    Ok(())
+    Ok(report)
+}
+
+/// Where `path`, a host path under `dest_root`, leads inside the image.
+pub(crate) fn resolve_dest(dest_root: &Path, path: &Path) -> Result<PathBuf> {
+    resolve_in_root(dest_root, path.strip_prefix(dest_root).unwrap_or(path))
+}
+
+/// Handle a destination file that already exists, following `options.freshness`.
+///
+/// Records the skip, or copies `src` over a stale `dst` (or fails, with
+/// [`StalePolicy::Error`]).
+pub(crate) fn skip_or_update(
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+    report: &mut CopyReport,
+) -> Result<()> {
+    let meta = fs::metadata(src)?;
+    match options.freshness {
+        FreshnessPolicy::Trust => {
+            report.record(src, dst, &meta, CopyAction::SkippedExisting, None);
+            Ok(())
+        }
+        policy if is_up_to_date(src, dst, policy)? => {
+            report.record(src, dst, &meta, CopyAction::UpToDate, None);
+            Ok(())
+        }
+        _ => update_stale(src, dst, options, report),
+    }
+}
+
+/// Compare an existing destination file with its source.
+fn is_up_to_date(src: &Path, dst: &Path, policy: FreshnessPolicy) -> Result<bool> {
+    let src_meta = fs::metadata(src)?;
+    let dst_meta = fs::metadata(dst)?;
+    if src_meta.len() != dst_meta.len() {
+        return Ok(false);
+    }
+    Ok(match policy {
+        FreshnessPolicy::Trust => true,
+        FreshnessPolicy::SizeAndMtime => {
+            src_meta.mtime() == dst_meta.mtime() && src_meta.mtime_nsec() == dst_meta.mtime_nsec()
+        }
+        FreshnessPolicy::ContentHash => {
+            let hash = |path: &Path| {
+                sha256_file(path).with_context(|| format!("Failed to hash: {}", path.display()))
+            };
+            hash(src)? == hash(dst)?
+        }
+    })
+}
+
+fn update_stale(
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+    report: &mut CopyReport,
+) -> Result<()> {
+    if options.stale == StalePolicy::Error {
+        bail!(
+            "Stale destination file {} does not match source {} ({:?})",
+            dst.display(),
+            src.display(),
+            options.freshness
+        );
+    }
+    copy_file_as(src, dst, options, report, CopyAction::Updated)
+}
+
+/// Copy one regular file (following symlinks) and apply preserved metadata.
+pub(crate) fn copy_file(
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+    report: &mut CopyReport,
+) -> Result<()> {
+    let action = if dst.symlink_metadata().is_ok() {
+        CopyAction::Replaced
+    } else {
+        CopyAction::Copied
+    };
+    copy_file_as(src, dst, options, report, action)
+}
+
+fn copy_file_as(
+    src: &Path,
+    dst: &Path,
+    options: &CopyOptions,
+    report: &mut CopyReport,
+    action: CopyAction,
+) -> Result<()> {
+    // Copy from the file itself, not an intermediate symlink, so metadata matches
+    let real_src = fs::canonicalize(src).unwrap_or_else(|_| src.to_path_buf());
+    let method = copy_file_data(&real_src, dst, options.strategy)
+        .with_context(|| format!("Failed to copy {} to {}", src.display(), dst.display()))?;
+    let meta = fs::metadata(&real_src)?;
+    *report.methods.entry(method).or_default() += 1;
+    report.record(&real_src, dst, &meta, action, Some(method));
+    if method == CopyMethod::Hardlink {
+        return Ok(());
+    }
+    report.bytes_copied += meta.len();
+    apply_metadata(
+        &real_src,
+        dst,
+        &meta,
+        &options.preserve,
+        &mut report.warnings,
+    )
 }
 
 /// Create a symlink if it doesn't already exist.
@@ -290,4 +1089,451 @@ mod tests {
             "file.txt"
         );
     }
+
+    #[test]
+    fn test_copy_dir_with_options_preserves_metadata() {
+        let temp = TempDir::new().unwrap();
+        let base = temp.path();
+
+        let src = base.join("src");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("bin/ping"), "ping").unwrap();
+        fs::set_permissions(src.join("bin/ping"), fs::Permissions::from_mode(0o4755)).unwrap();
+        fs::set_permissions(src.join("bin"), fs::Permissions::from_mode(0o750)).unwrap();
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_234_567);
+        fs::File::open(src.join("bin"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let dst = base.join("dst");
+        let options = CopyOptions {
+            preserve: PreserveOptions::all(),
+            ..CopyOptions::default()
+        };
+        copy_dir_with_options(&src, &dst, &options).unwrap();
+
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&dst.join("bin/ping")), 0o4755);
+        assert_eq!(mode(&dst.join("bin")), 0o750);
+        assert_eq!(
+            fs::metadata(dst.join("bin")).unwrap().modified().unwrap(),
+            mtime
+        );
+    }
+
+    #[test]
+    fn test_copy_dir_with_options_keeps_existing_directory_metadata() {
+        let temp = TempDir::new().unwrap();
+        let base = temp.path();
+
+        let src = base.join("src");
+        fs::create_dir_all(src.join("etc")).unwrap();
+        fs::write(src.join("etc/hostname"), "host").unwrap();
+        fs::set_permissions(src.join("etc"), fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o700)).unwrap();
+
+        let dst = base.join("dst");
+        fs::create_dir_all(dst.join("etc")).unwrap();
+        fs::set_permissions(dst.join("etc"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(&dst, fs::Permissions::from_mode(0o755)).unwrap();
+        copy_dir_with_options(&src, &dst, &CopyOptions::default()).unwrap();
+
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&dst), 0o755);
+        assert_eq!(mode(&dst.join("etc")), 0o755);
+        assert_eq!(
+            fs::read_to_string(dst.join("etc/hostname")).unwrap(),
+            "host"
+        );
+
+        // A root the copy creates takes the source's mode
+        let fresh = base.join("fresh");
+        copy_dir_with_options(&src, &fresh, &CopyOptions::default()).unwrap();
+        assert_eq!(mode(&fresh), 0o700);
+    }
+
+    #[test]
+    fn test_copy_dir_preserves_hardlinks() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("bin/coreutils"), "multicall").unwrap();
+        fs::hard_link(src.join("bin/coreutils"), src.join("bin/ls")).unwrap();
+        fs::hard_link(src.join("bin/coreutils"), src.join("bin/cp")).unwrap();
+
+        let dst = temp.path().join("dst");
+        let report = copy_dir_with_options(&src, &dst, &CopyOptions::default()).unwrap();
+
+        let ino = |p: &Path| fs::metadata(p).unwrap().ino();
+        assert_eq!(ino(&dst.join("bin/ls")), ino(&dst.join("bin/coreutils")));
+        assert_eq!(ino(&dst.join("bin/cp")), ino(&dst.join("bin/coreutils")));
+        assert_eq!(report.hardlinks, 2);
+        assert_eq!(report.bytes_copied, "multicall".len() as u64);
+    }
+
+    #[test]
+    fn test_copy_dir_recreates_fifo_and_skip_policy_skips_all() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir_all(src.join("run")).unwrap();
+        let fifo = CString::new(src.join("run/initctl").as_os_str().as_bytes()).unwrap();
+        // SAFETY: fifo is a valid NUL-terminated path
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+        let _socket = std::os::unix::net::UnixListener::bind(src.join("run/sock")).unwrap();
+
+        let dst = temp.path().join("dst");
+        let options = CopyOptions {
+            special_files: SpecialFilePolicy::Recreate,
+            ..CopyOptions::default()
+        };
+        copy_dir_with_options(&src, &dst, &options).unwrap();
+        assert!(fs::symlink_metadata(dst.join("run/initctl"))
+            .unwrap()
+            .file_type()
+            .is_fifo());
+
+        let skipped = temp.path().join("skipped");
+        let options = CopyOptions {
+            special_files: SpecialFilePolicy::Skip,
+            ..CopyOptions::default()
+        };
+        let report = copy_dir_with_options(&src, &skipped, &options).unwrap();
+        assert!(!skipped.join("run/initctl").exists());
+        assert!(!skipped.join("run/sock").exists());
+        assert_eq!(report.warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_copy_dir_reports_copy_methods() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("a"), "a").unwrap();
+        fs::write(src.join("b"), "b").unwrap();
+
+        let options = CopyOptions {
+            strategy: CopyStrategy::HardlinkFromSource,
+            ..CopyOptions::default()
+        };
+        let report = copy_dir_with_options(&src, &temp.path().join("dst"), &options).unwrap();
+        assert_eq!(report.methods.get(&CopyMethod::Hardlink), Some(&2));
+        assert_eq!(report.bytes_copied, 0);
+
+        let options = CopyOptions {
+            strategy: CopyStrategy::ReadWrite,
+            ..CopyOptions::default()
+        };
+        let report = copy_dir_with_options(&src, &temp.path().join("dst2"), &options).unwrap();
+        assert_eq!(report.methods.get(&CopyMethod::ReadWrite), Some(&2));
+    }
+
+    #[test]
+    fn test_copy_dir_applies_filter_rules() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir_all(src.join("usr/include")).unwrap();
+        fs::create_dir_all(src.join("usr/lib")).unwrap();
+        fs::write(src.join("usr/include/foo.h"), "").unwrap();
+        fs::write(src.join("usr/lib/libfoo.a"), "").unwrap();
+        fs::write(src.join("usr/lib/libfoo.so.1"), "").unwrap();
+
+        let mut options = CopyOptions::default();
+        options.filter.exclude("/usr/include/").exclude("*.a");
+        let dst = temp.path().join("dst");
+        let report = copy_dir_with_options(&src, &dst, &options).unwrap();
+
+        assert!(dst.join("usr/lib/libfoo.so.1").exists());
+        assert!(!dst.join("usr/lib/libfoo.a").exists());
+        assert!(!dst.join("usr/include").exists());
+        let mut excluded: Vec<_> = report
+            .entries_with(CopyAction::Excluded)
+            .map(|entry| entry.source.strip_prefix(&src).unwrap().to_path_buf())
+            .collect();
+        excluded.sort();
+        assert_eq!(excluded, options.filter.excluded_paths(&src).unwrap());
+    }
+
+    fn conflict_fixture(base: &Path) -> (PathBuf, PathBuf) {
+        // Source: real lib/ directory, a file, and a symlink
+        let src = base.join("src");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("lib/libfoo.so"), "new").unwrap();
+        fs::write(src.join("config"), "new").unwrap();
+        std::os::unix::fs::symlink("usr/share", src.join("share")).unwrap();
+
+        // Destination: usr-merged lib symlink, read-only file, real share/ directory
+        let dst = base.join("dst");
+        fs::create_dir_all(dst.join("usr/lib")).unwrap();
+        fs::create_dir_all(dst.join("share")).unwrap();
+        std::os::unix::fs::symlink("usr/lib", dst.join("lib")).unwrap();
+        fs::write(dst.join("config"), "old").unwrap();
+        fs::set_permissions(dst.join("config"), fs::Permissions::from_mode(0o444)).unwrap();
+        (src, dst)
+    }
+
+    fn copy_with_policy(src: &Path, dst: &Path, conflicts: ConflictPolicy) -> Result<CopyReport> {
+        let options = CopyOptions {
+            conflicts,
+            ..CopyOptions::default()
+        };
+        copy_dir_with_options(src, dst, &options)
+    }
+
+    #[test]
+    fn test_conflict_policy_merge_into_directory() {
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = conflict_fixture(temp.path());
+        let report = copy_with_policy(&src, &dst, ConflictPolicy::MergeIntoDirectory).unwrap();
+
+        assert!(dst.join("lib").is_symlink());
+        assert_eq!(fs::read(dst.join("usr/lib/libfoo.so")).unwrap(), b"new");
+        assert_eq!(fs::read(dst.join("config")).unwrap(), b"new");
+        assert!(dst.join("share").is_dir() && !dst.join("share").is_symlink());
+
+        let resolutions: Vec<_> = report
+            .conflicts
+            .iter()
+            .map(|c| (c.path.file_name().unwrap().to_str().unwrap(), c.resolution))
+            .collect();
+        assert_eq!(resolutions.len(), 3);
+        assert!(resolutions.contains(&("lib", ConflictResolution::MergedIntoDirectory)));
+        assert!(resolutions.contains(&("config", ConflictResolution::Replaced)));
+        assert!(resolutions.contains(&("share", ConflictResolution::Kept)));
+    }
+
+    #[test]
+    fn test_copy_dir_recursive_overwrites_and_merges() {
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = conflict_fixture(temp.path());
+        let report = copy_dir_recursive(&src, &dst).unwrap();
+
+        // Same outcome as before conflict policies existed
+        assert_eq!(report.bytes_copied, 6);
+        assert_eq!(fs::read(dst.join("config")).unwrap(), b"new");
+        assert!(dst.join("usr/lib/libfoo.so").exists());
+        assert!(dst.join("lib").is_symlink());
+        assert!(dst.join("share").is_dir() && !dst.join("share").is_symlink());
+    }
+
+    #[test]
+    fn test_merge_follows_absolute_symlinks_inside_root() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let name = format!("libleviso-merge-{}.so", std::process::id());
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("lib").join(&name), "new").unwrap();
+
+        let dst = temp.path().join("dst");
+        fs::create_dir_all(dst.join("usr/lib")).unwrap();
+        std::os::unix::fs::symlink("/usr/lib", dst.join("lib")).unwrap();
+
+        let report = copy_dir_recursive_overwrite(&src, &dst).unwrap();
+        assert!(dst.join("usr/lib").join(&name).exists());
+        assert!(!Path::new("/usr/lib").join(&name).exists());
+        assert_eq!(
+            report.conflicts[0].resolution,
+            ConflictResolution::MergedIntoDirectory
+        );
+    }
+
+    #[test]
+    fn test_conflict_policy_replace_keep_error() {
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = conflict_fixture(temp.path());
+        copy_with_policy(&src, &dst, ConflictPolicy::Replace).unwrap();
+        assert!(dst.join("lib").is_dir() && !dst.join("lib").is_symlink());
+        assert!(!dst.join("usr/lib/libfoo.so").exists());
+        assert_eq!(
+            fs::read_link(dst.join("share")).unwrap(),
+            Path::new("usr/share")
+        );
+
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = conflict_fixture(temp.path());
+        let report = copy_with_policy(&src, &dst, ConflictPolicy::Keep).unwrap();
+        assert!(dst.join("lib").is_symlink());
+        assert!(!dst.join("usr/lib/libfoo.so").exists());
+        assert_eq!(fs::read(dst.join("config")).unwrap(), b"old");
+        assert!(report
+            .conflicts
+            .iter()
+            .all(|c| c.resolution == ConflictResolution::Kept));
+
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = conflict_fixture(temp.path());
+        assert!(copy_with_policy(&src, &dst, ConflictPolicy::Error).is_err());
+    }
+
+    #[test]
+    fn test_overwrite_never_removes_a_directory() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("data"), "file").unwrap();
+
+        let dst = temp.path().join("dst");
+        fs::create_dir_all(dst.join("data/precious")).unwrap();
+        fs::write(dst.join("data/precious/keep.txt"), "keep").unwrap();
+
+        let err = copy_dir_recursive(&src, &dst).unwrap_err();
+        let conflict = err.downcast_ref::<Conflict>().unwrap();
+        assert_eq!(conflict.path, dst.join("data"));
+        assert_eq!(conflict.source, EntryKind::File);
+        assert_eq!(conflict.destination, EntryKind::Directory);
+        assert_eq!(conflict.resolution, ConflictResolution::Failed);
+        assert_eq!(
+            fs::read(dst.join("data/precious/keep.txt")).unwrap(),
+            b"keep"
+        );
+    }
+
+    #[test]
+    fn test_copy_report_lists_entries() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("lib/libfoo.so.1"), "12345").unwrap();
+        std::os::unix::fs::symlink("libfoo.so.1", src.join("lib/libfoo.so")).unwrap();
+        fs::create_dir_all(&dst).unwrap();
+        fs::write(dst.join("keep"), "").unwrap();
+        fs::write(src.join("keep"), "").unwrap();
+
+        let report = copy_with_policy(&src, &dst, ConflictPolicy::Keep).unwrap();
+        let action_of = |rel: &str| {
+            report
+                .entries
+                .iter()
+                .find(|entry| entry.destination == dst.join(rel))
+                .map(|entry| entry.action)
+        };
+        assert_eq!(action_of("lib"), Some(CopyAction::Copied));
+        assert_eq!(action_of("lib/libfoo.so"), Some(CopyAction::Symlinked));
+        assert_eq!(action_of("keep"), Some(CopyAction::SkippedExisting));
+
+        let lib = report
+            .entries
+            .iter()
+            .find(|entry| entry.destination == dst.join("lib/libfoo.so.1"))
+            .unwrap();
+        assert_eq!(lib.action, CopyAction::Copied);
+        assert_eq!(lib.source, src.join("lib/libfoo.so.1"));
+        assert_eq!(lib.size, 5);
+        assert!(lib.method.is_some());
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains("\"skipped_existing\""));
+    }
+
+    fn library_fixture(base: &Path) -> (PathBuf, PathBuf) {
+        let src = base.join("src");
+        let dst = base.join("dst");
+        fs::create_dir_all(src.join("usr/lib64")).unwrap();
+        fs::create_dir_all(dst.join("usr/lib64")).unwrap();
+        fs::write(src.join("usr/lib64/libfoo.so.1"), "new build").unwrap();
+        fs::write(dst.join("usr/lib64/libfoo.so.1"), "old build").unwrap();
+        (src, dst)
+    }
+
+    fn copy_libfoo(src: &Path, dst: &Path, options: &CopyOptions) -> Result<CopyReport> {
+        let layout = LibraryLayout {
+            dest_lib64_path: "usr/lib64",
+            dest_lib_path: "usr/lib",
+            extra_lib_paths: &[],
+            private_lib_dirs: &[],
+        };
+        copy_library_with_options(src, "libfoo.so.1", dst, &layout, options)
+    }
+
+    #[test]
+    fn test_copy_library_writes_through_destination_symlinks() {
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = library_fixture(temp.path());
+        let lib = dst.join("usr/lib64/libfoo.so.1");
+        fs::create_dir_all(dst.join("opt")).unwrap();
+        fs::rename(&lib, dst.join("opt/libfoo.so.1")).unwrap();
+        // Absolute, so following it on the host would leave the image
+        std::os::unix::fs::symlink("/opt/libfoo.so.1", &lib).unwrap();
+
+        let options = CopyOptions {
+            freshness: FreshnessPolicy::ContentHash,
+            ..CopyOptions::default()
+        };
+        let report = copy_libfoo(&src, &dst, &options).unwrap();
+        assert_eq!(report.entries[0].action, CopyAction::Updated);
+        assert_eq!(report.entries[0].destination, dst.join("opt/libfoo.so.1"));
+        assert!(lib.is_symlink());
+        assert_eq!(fs::read(dst.join("opt/libfoo.so.1")).unwrap(), b"new build");
+    }
+
+    #[test]
+    fn test_freshness_policy_detects_stale_library() {
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = library_fixture(temp.path());
+        let lib = dst.join("usr/lib64/libfoo.so.1");
+
+        // Trust: skipped, and flagged as an unchecked skip
+        let report = copy_libfoo(&src, &dst, &CopyOptions::default()).unwrap();
+        assert_eq!(report.entries[0].action, CopyAction::SkippedExisting);
+        assert_eq!(fs::read(&lib).unwrap(), b"old build");
+
+        let mut options = CopyOptions {
+            freshness: FreshnessPolicy::ContentHash,
+            stale: StalePolicy::Error,
+            ..CopyOptions::default()
+        };
+        assert!(copy_libfoo(&src, &dst, &options).is_err());
+
+        options.stale = StalePolicy::Update;
+        let report = copy_libfoo(&src, &dst, &options).unwrap();
+        assert_eq!(report.entries[0].action, CopyAction::Updated);
+        assert_eq!(fs::read(&lib).unwrap(), b"new build");
+
+        let report = copy_libfoo(&src, &dst, &options).unwrap();
+        assert_eq!(report.entries[0].action, CopyAction::UpToDate);
+    }
+
+    #[test]
+    fn test_freshness_size_and_mtime_in_dir_copy() {
+        let temp = TempDir::new().unwrap();
+        let (src, dst) = library_fixture(temp.path());
+        let options = CopyOptions {
+            freshness: FreshnessPolicy::SizeAndMtime,
+            preserve: PreserveOptions::all(),
+            ..CopyOptions::default()
+        };
+
+        // Same size, different mtime: stale
+        fs::File::options()
+            .write(true)
+            .open(dst.join("usr/lib64/libfoo.so.1"))
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH)
+            .unwrap();
+        let report = copy_dir_with_options(&src, &dst, &options).unwrap();
+        assert_eq!(report.entries_with(CopyAction::Updated).count(), 1);
+        // Timestamps were preserved, so the second run finds it up to date
+        let report = copy_dir_with_options(&src, &dst, &options).unwrap();
+        assert_eq!(report.entries_with(CopyAction::UpToDate).count(), 1);
+        assert_eq!(report.entries_with(CopyAction::Updated).count(), 0);
+    }
+
+    #[test]
+    fn test_copy_dir_visits_entries_in_sorted_order() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir_all(&src).unwrap();
+        for name in ["zeta", "alpha", "mu", "beta"] {
+            fs::write(src.join(name), name).unwrap();
+        }
+
+        let report = copy_dir_recursive(&src, &temp.path().join("dst")).unwrap();
+        let names: Vec<_> = report
+            .entries
+            .iter()
+            .map(|entry| entry.destination.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(names, ["alpha", "beta", "mu", "zeta"]);
+    }
 }
@@ -0,0 +1,171 @@
+//! Optional `dlopen()` dependencies declared in `.note.dlopen` ELF notes.
+//!
+//! The note format is described at
+//! <https://systemd.io/ELF_DLOPEN_METADATA/>: an `FDO` note of type
+//! `0x407c0c0a` whose payload is a JSON array such as
+//! `[{"feature":"fido2","priority":"suggested","soname":["libfido2.so.1"]}]`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Note type of `FDO_DLOPEN_METADATA`, as printed for unknown notes.
+const DLOPEN_NOTE_TYPE: &str = "0x407c0c0a";
+
+/// How much a feature matters, from the note's `priority` field.
+///
+/// Ordered from most to least important, so `priority <= max` selects
+/// everything at least as important as `max`.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum DlopenPriority {
+    /// Core functionality breaks without it.
+    Required,
+    /// Should be installed unless there's a reason not to.
+    #[default]
+    Recommended,
+    /// Nice to have.
+    Suggested,
+}
+
+/// One entry of a `.note.dlopen` note.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DlopenDependency {
+    /// Short feature name, e.g. `fido2`.
+    #[serde(default)]
+    pub feature: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub priority: DlopenPriority,
+    /// Alternative sonames, in order of preference; any one of them provides
+    /// the feature.
+    #[serde(rename = "soname")]
+    pub sonames: Vec<String>,
+}
+
+/// Parse the JSON payload of one `.note.dlopen` note.
+///
+/// # Errors
+///
+/// Fails if the payload isn't a JSON array of dependency objects.
+pub fn parse_dlopen_metadata(payload: &str) -> Result<Vec<DlopenDependency>> {
+    serde_json::from_str(payload.trim_end_matches('\0'))
+        .context("Failed to parse .note.dlopen metadata")
+}
+
+/// Extract dlopen dependencies from `readelf -W -n` output.
+///
+/// Newer binutils decode the note (`Dlopen Metadata: [...]`); older readelf
+/// and llvm-readelf print it as an unknown note type followed by a hex dump
+/// of the payload. Both are understood. Malformed notes are skipped, since a
+/// broken optional dependency list shouldn't fail the analysis of the file.
+pub fn parse_readelf_dlopen(output: &str) -> Vec<DlopenDependency> {
+    let mut dependencies = Vec::new();
+    let mut lines = output.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if let Some((_, json)) = line.split_once("Dlopen Metadata: ") {
+            dependencies.extend(parse_dlopen_metadata(json).unwrap_or_default());
+            continue;
+        }
+        if !line.contains(DLOPEN_NOTE_TYPE) {
+            continue;
+        }
+
+        let mut dump = line
+            .split_once("description data:")
+            .map_or("", |(_, data)| data)
+            .to_string();
+        // The dump may start or continue on the following lines
+        while let Some(next) = lines.peek() {
+            let next = next.trim();
+            let data = next.strip_prefix("description data:").unwrap_or(next);
+            if data.trim().is_empty() || hex_bytes(data).is_none() {
+                break;
+            }
+            dump.push(' ');
+            dump.push_str(data);
+            lines.next();
+        }
+        if let Some(json) = hex_bytes(&dump).and_then(|bytes| String::from_utf8(bytes).ok()) {
+            dependencies.extend(parse_dlopen_metadata(&json).unwrap_or_default());
+        }
+    }
+
+    dependencies
+}
+
+/// Decode a line of space-separated hex byte pairs; `None` if it isn't one.
+fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    text.split_whitespace()
+        .map(|pair| match pair.len() {
+            2 => u8::from_str_radix(pair, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOAD: &str = r#"[{"feature":"fido2","description":"Support fido2 for encryption and authentication.","priority":"suggested","soname":["libfido2.so.1"]},{"feature":"tpm","soname":["libtss2-esys.so.0","libtss2-esys.so.1"]}]"#;
+
+    fn hex_dump(text: &str) -> String {
+        text.bytes()
+            .chain([0, 0])
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_parse_dlopen_metadata() {
+        let deps = parse_dlopen_metadata(PAYLOAD).unwrap();
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[0].feature.as_deref(), Some("fido2"));
+        assert_eq!(deps[0].priority, DlopenPriority::Suggested);
+        assert_eq!(deps[0].sonames, ["libfido2.so.1"]);
+        // Priority defaults to recommended
+        assert_eq!(deps[1].priority, DlopenPriority::Recommended);
+        assert_eq!(deps[1].description, None);
+        assert!(parse_dlopen_metadata("{}").is_err());
+        assert!(DlopenPriority::Required < DlopenPriority::Suggested);
+    }
+
+    #[test]
+    fn test_parse_readelf_dlopen() {
+        let decoded = format!(
+            "Displaying notes found in: .note.dlopen\n  Owner                Data size \tDescription\n  FDO                  0x000000c0\tFDO_DLOPEN_METADATA\t    Dlopen Metadata: {}\n",
+            PAYLOAD
+        );
+        assert_eq!(parse_readelf_dlopen(&decoded).len(), 2);
+
+        // GNU readelf before the note was known: one long line
+        let unknown = format!(
+            "Displaying notes found in: .note.dlopen\n  Owner                Data size \tDescription\n  FDO                  0x000000c0\tUnknown note type: (0x407c0c0a)\t   description data: {}\n\nDisplaying notes found in: .note.package\n",
+            hex_dump(PAYLOAD)
+        );
+        let deps = parse_readelf_dlopen(&unknown);
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[1].sonames, ["libtss2-esys.so.0", "libtss2-esys.so.1"]);
+
+        // Dump on its own lines, as llvm-readelf prints it
+        let dump = hex_dump(PAYLOAD);
+        let words: Vec<&str> = dump.split(' ').collect();
+        let wrapped: Vec<String> = words.chunks(16).map(|c| c.join(" ")).collect();
+        let llvm = format!(
+            "  FDO                  0x000000c0\tUnknown (0x407c0c0a)\n   description data: {}\n",
+            wrapped.join("\n   ")
+        );
+        assert_eq!(
+            parse_readelf_dlopen(&llvm),
+            parse_dlopen_metadata(PAYLOAD).unwrap()
+        );
+
+        let broken = "  FDO   0x00000004\tUnknown note type: (0x407c0c0a)\t   description data: 5b 7b 00 00\n";
+        assert!(parse_readelf_dlopen(broken).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! Native parsing of the ELF file header.
+//!
+//! Only the identification bytes and the fixed-position fields are read;
+//! everything else goes through the analysis backends.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// `e_type` of an executable.
+pub const ET_EXEC: u16 = 2;
+/// `e_type` of a shared object or PIE executable.
+pub const ET_DYN: u16 = 3;
+
+/// 32- or 64-bit ELF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+/// The fields of an ELF header that identify what can load it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ElfHeader {
+    pub class: ElfClass,
+    pub little_endian: bool,
+    /// `EI_OSABI` byte (0 for System V, 3 for GNU/Linux).
+    pub os_abi: u8,
+    /// `e_type`: [`ET_EXEC`], [`ET_DYN`], ...
+    pub elf_type: u16,
+    /// `e_machine`, e.g. 62 for x86-64.
+    pub machine: u16,
+}
+
+impl ElfHeader {
+    /// Returns `true` for executables and shared objects, the files the
+    /// dynamic loader deals with (not relocatable objects or core dumps).
+    pub fn is_loadable(&self) -> bool {
+        self.elf_type == ET_EXEC || self.elf_type == ET_DYN
+    }
+
+    /// Returns `true` if an object with this header can be loaded into a
+    /// process described by `other` (same class, byte order and machine).
+    pub fn is_compatible(&self, other: &ElfHeader) -> bool {
+        self.class == other.class
+            && self.little_endian == other.little_endian
+            && self.machine == other.machine
+    }
+
+    /// Short human-readable name of the machine, e.g. "x86-64".
+    pub fn machine_name(&self) -> &'static str {
+        match self.machine {
+            3 => "i386",
+            8 => "MIPS",
+            20 => "PowerPC",
+            21 => "PowerPC64",
+            22 => "S/390",
+            40 => "ARM",
+            62 => "x86-64",
+            183 => "AArch64",
+            243 => "RISC-V",
+            258 => "LoongArch",
+            _ => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for ElfHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits = match self.class {
+            ElfClass::Elf32 => 32,
+            ElfClass::Elf64 => 64,
+        };
+        write!(f, "ELF{} {}", bits, self.machine_name())
+    }
+}
+
+/// Parse the ELF header at the start of `bytes`.
+///
+/// Returns `None` if `bytes` is not the start of an ELF file.
+pub fn parse_elf_header(bytes: &[u8]) -> Option<ElfHeader> {
+    if bytes.len() < 20 || bytes[..4] != *b"\x7fELF" {
+        return None;
+    }
+    let class = match bytes[4] {
+        1 => ElfClass::Elf32,
+        2 => ElfClass::Elf64,
+        _ => return None,
+    };
+    let little_endian = match bytes[5] {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let half = |offset: usize| {
+        let raw = [bytes[offset], bytes[offset + 1]];
+        if little_endian {
+            u16::from_le_bytes(raw)
+        } else {
+            u16::from_be_bytes(raw)
+        }
+    };
+    Some(ElfHeader {
+        class,
+        little_endian,
+        os_abi: bytes[7],
+        elf_type: half(16),
+        machine: half(18),
+    })
+}
+
+/// Read the ELF header of `path`.
+///
+/// Returns `Ok(None)` for files that are not ELF (scripts, data, short files).
+pub fn read_elf_header(path: &Path) -> Result<Option<ElfHeader>> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open: {}", path.display()))?;
+    let mut bytes = [0u8; 20];
+    match file.read_exact(&mut bytes) {
+        Ok(()) => Ok(parse_elf_header(&bytes)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read: {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_elf_header() {
+        let mut bytes = [0u8; 20];
+        bytes[..4].copy_from_slice(b"\x7fELF");
+        bytes[4] = 2; // ELFCLASS64
+        bytes[5] = 1; // little endian
+        bytes[16] = 3; // ET_DYN
+        bytes[18] = 62; // x86-64
+
+        let header = parse_elf_header(&bytes).unwrap();
+        assert_eq!(header.class, ElfClass::Elf64);
+        assert_eq!(header.elf_type, ET_DYN);
+        assert_eq!(header.to_string(), "ELF64 x86-64");
+        assert!(header.is_loadable());
+
+        let i386 = ElfHeader {
+            class: ElfClass::Elf32,
+            machine: 3,
+            ..header
+        };
+        assert!(!header.is_compatible(&i386));
+        assert!(parse_elf_header(b"#!/bin/sh\nexec true\n").is_none());
+    }
+}
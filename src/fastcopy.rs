@@ -0,0 +1,307 @@
+//! File data copying with reflink, copy_file_range and hardlink fast paths.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use serde::Serialize;
+
+/// How a file's data ended up in the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyMethod {
+    /// Shared extents via the `FICLONE` ioctl (btrfs, XFS, bcachefs).
+    Reflink,
+    /// In-kernel copy via `copy_file_range(2)`.
+    CopyFileRange,
+    /// Plain userspace read/write.
+    ReadWrite,
+    /// Hardlink to the source file; no data was copied.
+    Hardlink,
+}
+
+/// How file data should be copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyStrategy {
+    /// Try a reflink, then `copy_file_range`, then a plain copy.
+    #[default]
+    Auto,
+    /// Always use a plain read/write copy.
+    ReadWrite,
+    /// Hardlink destination files to the source instead of copying.
+    ///
+    /// Only suitable for throwaway staging trees: the destination shares
+    /// inodes (and therefore contents and metadata) with the source, so no
+    /// metadata is applied to it. Falls back to [`CopyStrategy::Auto`] when
+    /// source and destination are on different filesystems.
+    HardlinkFromSource,
+}
+
+/// Copy the contents of regular file `src` to `dst`, replacing `dst`.
+///
+/// Holes in sparse source files are preserved. Like `fs::copy`, the
+/// destination gets the source's permission bits.
+pub(crate) fn copy_file_data(
+    src: &Path,
+    dst: &Path,
+    strategy: CopyStrategy,
+) -> io::Result<CopyMethod> {
+    if strategy == CopyStrategy::HardlinkFromSource {
+        if dst.symlink_metadata().is_ok() {
+            fs::remove_file(dst)?;
+        }
+        match fs::hard_link(src, dst) {
+            Ok(()) => return Ok(CopyMethod::Hardlink),
+            Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut reader = File::open(src)?;
+    let meta = reader.metadata()?;
+    // Never truncate in place: dst may share an inode with the source
+    // (e.g. left over from a HardlinkFromSource staging run)
+    if dst.symlink_metadata().is_ok() {
+        fs::remove_file(dst)?;
+    }
+    let mut writer = File::create(dst)?;
+    writer.set_permissions(fs::Permissions::from_mode(meta.permissions().mode()))?;
+
+    if strategy != CopyStrategy::ReadWrite && try_reflink(&reader, &writer) {
+        return Ok(CopyMethod::Reflink);
+    }
+
+    let method = if strategy == CopyStrategy::ReadWrite {
+        CopyMethod::ReadWrite
+    } else {
+        CopyMethod::CopyFileRange
+    };
+    let method = copy_extents(
+        &mut reader,
+        &mut writer,
+        meta.len(),
+        is_sparse(&meta),
+        method,
+    )?;
+    Ok(method)
+}
+
+/// Returns `true` if fewer blocks are allocated than the size implies.
+fn is_sparse(meta: &fs::Metadata) -> bool {
+    meta.blocks() * 512 < meta.len()
+}
+
+fn try_reflink(reader: &File, writer: &File) -> bool {
+    // SAFETY: both descriptors are open for the duration of the call
+    unsafe { libc::ioctl(writer.as_raw_fd(), libc::FICLONE, reader.as_raw_fd()) == 0 }
+}
+
+/// Copy every data extent of `reader`, skipping holes when `sparse`.
+///
+/// Starts with `method` and degrades to [`CopyMethod::ReadWrite`] if the
+/// kernel refuses `copy_file_range` (old kernels, cross-filesystem on some
+/// versions, special filesystems). Returns the method actually used, which
+/// is a plain copy when there was no data to hand to the kernel.
+fn copy_extents(
+    reader: &mut File,
+    writer: &mut File,
+    len: u64,
+    sparse: bool,
+    mut method: CopyMethod,
+) -> io::Result<CopyMethod> {
+    let mut pos = 0;
+    let mut copied_in_kernel = false;
+    while pos < len {
+        let (start, end) = if sparse {
+            match next_extent(reader, pos, len)? {
+                Some(extent) => extent,
+                None => break,
+            }
+        } else {
+            (pos, len)
+        };
+
+        if method == CopyMethod::CopyFileRange {
+            match copy_range_kernel(reader, writer, start, end) {
+                Ok(()) => {
+                    copied_in_kernel = true;
+                    pos = end;
+                    continue;
+                }
+                Err(e) if is_unsupported(&e) => method = CopyMethod::ReadWrite,
+                Err(e) => return Err(e),
+            }
+        }
+        copy_range_userspace(reader, writer, start, end)?;
+        pos = end;
+    }
+
+    // Trailing holes only exist as file length
+    writer.set_len(len)?;
+    if method == CopyMethod::CopyFileRange && !copied_in_kernel {
+        method = CopyMethod::ReadWrite;
+    }
+    Ok(method)
+}
+
+/// The next data extent at or after `pos`, as a half-open byte range.
+fn next_extent(reader: &File, pos: u64, len: u64) -> io::Result<Option<(u64, u64)>> {
+    let fd = reader.as_raw_fd();
+    // SAFETY: lseek on an open descriptor has no memory-safety preconditions
+    let data = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+    if data < 0 {
+        let err = io::Error::last_os_error();
+        // ENXIO: no data after pos; EINVAL: filesystem without SEEK_DATA
+        return match err.raw_os_error() {
+            Some(libc::ENXIO) => Ok(None),
+            Some(libc::EINVAL) => Ok(Some((pos, len))),
+            _ => Err(err),
+        };
+    }
+    // SAFETY: as above
+    let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+    if hole < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some((data as u64, (hole as u64).min(len))))
+}
+
+fn copy_range_kernel(reader: &File, writer: &File, start: u64, end: u64) -> io::Result<()> {
+    let mut off_in = start as libc::loff_t;
+    let mut off_out = start as libc::loff_t;
+    while (off_in as u64) < end {
+        let remaining = (end - off_in as u64).min(1 << 30) as usize;
+        // SAFETY: offsets point to valid loff_t values owned by this frame
+        let copied = unsafe {
+            libc::copy_file_range(
+                reader.as_raw_fd(),
+                &mut off_in,
+                writer.as_raw_fd(),
+                &mut off_out,
+                remaining,
+                0,
+            )
+        };
+        if copied < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if copied == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "source file shrank during copy",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn copy_range_userspace(
+    reader: &mut File,
+    writer: &mut File,
+    start: u64,
+    end: u64,
+) -> io::Result<()> {
+    reader.seek(SeekFrom::Start(start))?;
+    writer.seek(SeekFrom::Start(start))?;
+    let copied = io::copy(&mut Read::take(&mut *reader, end - start), writer)?;
+    if copied < end - start {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "source file shrank during copy",
+        ));
+    }
+    writer.flush()
+}
+
+fn is_unsupported(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::ENOSYS) | Some(libc::EXDEV) | Some(libc::EOPNOTSUPP) | Some(libc::EINVAL)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_copy_file_data_auto() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::write(&src, "payload").unwrap();
+
+        let method = copy_file_data(&src, &dst, CopyStrategy::Auto).unwrap();
+        assert_ne!(method, CopyMethod::Hardlink);
+        assert_eq!(fs::read(&dst).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_copy_file_data_empty_file() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::write(&src, "").unwrap();
+
+        let method = copy_file_data(&src, &dst, CopyStrategy::Auto).unwrap();
+        assert_ne!(method, CopyMethod::CopyFileRange);
+        assert!(fs::read(&dst).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_copy_range_kernel_short_source() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::write(&src, "abc").unwrap();
+        let reader = File::open(&src).unwrap();
+        let writer = File::create(temp.path().join("dst")).unwrap();
+
+        // The source ends before the range does: an error, not a silent
+        // zero-filled tail
+        let err = copy_range_kernel(&reader, &writer, 0, 10).unwrap_err();
+        if !is_unsupported(&err) {
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn test_copy_file_data_preserves_holes() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("sparse");
+        let dst = temp.path().join("dst");
+        let mut file = File::create(&src).unwrap();
+        file.write_all(b"head").unwrap();
+        file.seek(SeekFrom::Start(16 << 20)).unwrap();
+        file.write_all(b"tail").unwrap();
+        drop(file);
+
+        for strategy in [CopyStrategy::Auto, CopyStrategy::ReadWrite] {
+            copy_file_data(&src, &dst, strategy).unwrap();
+            let meta = fs::metadata(&dst).unwrap();
+            assert_eq!(meta.len(), (16 << 20) + 4);
+            assert_eq!(fs::read(&dst).unwrap()[..4], *b"head");
+            if is_sparse(&fs::metadata(&src).unwrap()) {
+                assert!(is_sparse(&meta), "{:?} filled in holes", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_copy_file_data_hardlink_from_source() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::write(&src, "payload").unwrap();
+        fs::write(&dst, "stale").unwrap();
+
+        let method = copy_file_data(&src, &dst, CopyStrategy::HardlinkFromSource).unwrap();
+        assert_eq!(method, CopyMethod::Hardlink);
+        assert_eq!(
+            fs::metadata(&src).unwrap().ino(),
+            fs::metadata(&dst).unwrap().ino()
+        );
+    }
+}
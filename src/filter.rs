@@ -0,0 +1,312 @@
+//! rsync-style include/exclude rules for directory copies.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether a matching rule keeps or drops an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Copy the entry (`+` in filter files).
+    Include,
+    /// Leave the entry, and anything beneath it, out of the copy (`-`).
+    Exclude,
+}
+
+/// One include or exclude pattern.
+///
+/// Patterns follow rsync conventions:
+/// - a leading `/` anchors the pattern to the root of the copy
+/// - a trailing `/` matches directories only
+/// - a pattern without any other `/` matches the entry name at any depth,
+///   one with a `/` matches the trailing components of the relative path
+/// - `*` matches within a component, `**` across components, `?` one
+///   character and `[...]` a character class (`[!...]` negated)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterRule {
+    action: FilterAction,
+    pattern: String,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl FilterRule {
+    /// A rule with the given action and rsync-style pattern.
+    pub fn new(action: FilterAction, pattern: &str) -> Self {
+        let anchored = pattern.starts_with('/');
+        let dir_only = pattern.len() > 1 && pattern.ends_with('/');
+        let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+        Self {
+            action,
+            pattern: pattern.to_string(),
+            anchored,
+            dir_only,
+        }
+    }
+
+    /// Shorthand for an [`FilterAction::Include`] rule.
+    pub fn include(pattern: &str) -> Self {
+        Self::new(FilterAction::Include, pattern)
+    }
+
+    /// Shorthand for an [`FilterAction::Exclude`] rule.
+    pub fn exclude(pattern: &str) -> Self {
+        Self::new(FilterAction::Exclude, pattern)
+    }
+
+    /// Check whether this rule matches `rel`, a path relative to the copy root.
+    pub fn matches(&self, rel: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let rel = rel.to_string_lossy();
+        let pattern = self.pattern.as_bytes();
+        if self.anchored {
+            return glob_match(pattern, rel.as_bytes());
+        }
+        if !self.pattern.contains('/') {
+            let name = rel.rsplit('/').next().unwrap_or(&rel);
+            return glob_match(pattern, name.as_bytes());
+        }
+        // Unanchored with a slash: try every suffix starting at a component boundary
+        std::iter::once(0)
+            .chain(rel.match_indices('/').map(|(i, _)| i + 1))
+            .any(|start| glob_match(pattern, &rel.as_bytes()[start..]))
+    }
+}
+
+/// An ordered list of filter rules; the first matching rule wins.
+///
+/// Entries no rule matches are included. An excluded directory is not
+/// descended into, so nothing beneath it is copied even if a later rule
+/// would include it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterRules {
+    /// Rules in the order they are tried.
+    pub rules: Vec<FilterRule>,
+}
+
+impl FilterRules {
+    /// An empty rule list, which includes everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse rules in rsync filter-file syntax, one per line.
+    ///
+    /// Lines look like `- *.la` or `+ /usr/share/man/man1/`. Blank lines and
+    /// lines starting with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Fails on a line that doesn't start with `+ ` or `- `.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = if let Some(pattern) = line.strip_prefix("+ ") {
+                FilterRule::include(pattern.trim())
+            } else if let Some(pattern) = line.strip_prefix("- ") {
+                FilterRule::exclude(pattern.trim())
+            } else {
+                bail!(
+                    "Invalid filter rule on line {}: {:?} (expected '+ PATTERN' or '- PATTERN')",
+                    number + 1,
+                    line
+                );
+            };
+            rules.push(rule);
+        }
+        Ok(Self { rules })
+    }
+
+    /// Append an include rule.
+    pub fn include(&mut self, pattern: &str) -> &mut Self {
+        self.rules.push(FilterRule::include(pattern));
+        self
+    }
+
+    /// Append an exclude rule.
+    pub fn exclude(&mut self, pattern: &str) -> &mut Self {
+        self.rules.push(FilterRule::exclude(pattern));
+        self
+    }
+
+    /// Check whether `rel` (relative to the copy root) should be skipped.
+    pub fn is_excluded(&self, rel: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(rel, is_dir))
+            .is_some_and(|rule| rule.action == FilterAction::Exclude)
+    }
+
+    /// Dry run: list what a copy of `src` would leave out.
+    ///
+    /// Returns paths relative to `src` in sorted order. Only the top-most
+    /// excluded entry of a subtree is listed, since its contents are never
+    /// visited.
+    pub fn excluded_paths(&self, src: &Path) -> Result<Vec<PathBuf>> {
+        let mut excluded = Vec::new();
+        if src.is_dir() {
+            self.collect_excluded(src, Path::new(""), &mut excluded)?;
+        }
+        Ok(excluded)
+    }
+
+    fn collect_excluded(&self, dir: &Path, rel: &Path, excluded: &mut Vec<PathBuf>) -> Result<()> {
+        let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let entry_rel = rel.join(entry.file_name());
+            // file_type() does not follow symlinks, matching the copy walk
+            let is_dir = entry.file_type()?.is_dir();
+            if self.is_excluded(&entry_rel, is_dir) {
+                excluded.push(entry_rel);
+            } else if is_dir {
+                self.collect_excluded(&entry.path(), &entry_rel, excluded)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Match `text` against a glob `pattern` where `*` and `?` stop at `/`.
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            // "**/" also matches zero directories
+            if rest.first() == Some(&b'/') && glob_match(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => {
+            matches!(text.first(), Some(&c) if c != b'/') && glob_match(&pattern[1..], &text[1..])
+        }
+        Some(b'[') => match (
+            text.first(),
+            class_match(&pattern[1..], text.first().copied()),
+        ) {
+            (Some(_), Some((true, len))) => glob_match(&pattern[1 + len..], &text[1..]),
+            (_, Some((false, _))) | (None, _) => false,
+            // Unterminated class: treat '[' literally
+            (Some(&c), None) => c == b'[' && glob_match(&pattern[1..], &text[1..]),
+        },
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..])
+        }
+        Some(&p) => text.first() == Some(&p) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Match one character against a `[...]` class body (after the `[`).
+///
+/// Returns whether it matched and how many pattern bytes the class used,
+/// including the closing `]`, or `None` if the class is unterminated.
+fn class_match(class: &[u8], c: Option<u8>) -> Option<(bool, usize)> {
+    let negated = matches!(class.first(), Some(b'!') | Some(b'^'));
+    let mut i = usize::from(negated);
+    let mut matched = false;
+    let mut first = true;
+    while i < class.len() {
+        let lo = class[i];
+        if lo == b']' && !first {
+            let hit = c.is_some_and(|c| c != b'/') && matched != negated;
+            return Some((hit, i + 1));
+        }
+        first = false;
+        if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|&hi| hi != b']') {
+            let hi = class[i + 2];
+            matched |= c.is_some_and(|c| lo <= c && c <= hi);
+            i += 3;
+        } else {
+            matched |= c == Some(lo);
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.la", b"libfoo.la"));
+        assert!(!glob_match(b"*.la", b"usr/libfoo.la"));
+        assert!(glob_match(b"usr/**/*.a", b"usr/lib/x/libc.a"));
+        assert!(glob_match(b"**/man", b"man"));
+        assert!(glob_match(b"lib?.so", b"libc.so"));
+        assert!(glob_match(b"lib[a-c].so", b"libb.so"));
+        assert!(!glob_match(b"lib[!a-c].so", b"libb.so"));
+        assert!(!glob_match(b"lib?.so", b"lib/.so"));
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let rel = Path::new("usr/share/man/man1/ls.1");
+        assert!(FilterRule::exclude("*.1").matches(rel, false));
+        assert!(FilterRule::exclude("man1/*.1").matches(rel, false));
+        assert!(!FilterRule::exclude("/man1/*.1").matches(rel, false));
+        assert!(FilterRule::exclude("/usr/share/**").matches(rel, false));
+
+        let include = FilterRule::exclude("include/");
+        assert!(include.matches(Path::new("usr/include"), true));
+        assert!(!include.matches(Path::new("usr/include"), false));
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let rules =
+            FilterRules::parse("# keep libc's static stub\n+ libc_nonshared.a\n- *.a\n").unwrap();
+        assert!(!rules.is_excluded(Path::new("usr/lib/libc_nonshared.a"), false));
+        assert!(rules.is_excluded(Path::new("usr/lib/libm.a"), false));
+        assert!(!rules.is_excluded(Path::new("usr/lib/libm.so"), false));
+
+        assert!(FilterRules::parse("*.a").is_err());
+    }
+
+    #[test]
+    fn test_excluded_paths_dry_run() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path();
+        fs::create_dir_all(src.join("usr/include/sys")).unwrap();
+        fs::create_dir_all(src.join("usr/lib/python3/__pycache__")).unwrap();
+        fs::write(src.join("usr/include/sys/types.h"), "").unwrap();
+        fs::write(src.join("usr/lib/libfoo.la"), "").unwrap();
+        fs::write(src.join("usr/lib/libfoo.so"), "").unwrap();
+
+        let mut rules = FilterRules::new();
+        rules
+            .exclude("include/")
+            .exclude("*.la")
+            .exclude("__pycache__/");
+        assert_eq!(
+            rules.excluded_paths(src).unwrap(),
+            vec![
+                PathBuf::from("usr/include"),
+                PathBuf::from("usr/lib/libfoo.la"),
+                PathBuf::from("usr/lib/python3/__pycache__"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! checksec-style hardening report for ELF files, with policy thresholds.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audit::find_elf_files;
+use crate::closure::{read_each, ClosureOptions, DependencyClosure};
+use crate::compat::parse_readelf_notes;
+use crate::symbols::parse_readelf_symbols;
+
+/// Relocation read-only protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Relro {
+    /// No PT_GNU_RELRO segment.
+    #[default]
+    None,
+    /// PT_GNU_RELRO, but the GOT stays writable for lazy binding.
+    Partial,
+    /// PT_GNU_RELRO with BIND_NOW, so the whole GOT is read-only.
+    Full,
+}
+
+/// Whether an object is position independent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pie {
+    /// A fixed-address (`ET_EXEC`) executable.
+    #[default]
+    Disabled,
+    /// A position-independent executable.
+    Enabled,
+    /// A shared library, which is always position independent.
+    SharedObject,
+}
+
+/// Hardening features of one ELF file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Hardening {
+    pub relro: Relro,
+    pub pie: Pie,
+    /// PT_GNU_STACK present and not executable.
+    pub nx: bool,
+    /// PT_GNU_STACK explicitly asks for an executable stack.
+    pub executable_stack: bool,
+    /// Imports or defines `__stack_chk_fail` / `__stack_chk_guard`.
+    pub stack_protector: bool,
+    /// Number of `__*_chk` FORTIFY_SOURCE functions used.
+    pub fortified: usize,
+    /// Symbols are bound at load time (`BIND_NOW`, `FLAGS BIND_NOW` or `FLAGS_1 NOW`).
+    pub bind_now: bool,
+    /// CET indirect branch tracking, from the GNU property note.
+    pub ibt: bool,
+    /// CET shadow stack, from the GNU property note.
+    pub shstk: bool,
+    /// Has text relocations, which make code pages writable at load time.
+    pub textrel: bool,
+}
+
+/// Minimum hardening every ELF file must have. The default requires nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HardeningPolicy {
+    pub min_relro: Relro,
+    /// Executables must be PIE; shared libraries always pass.
+    pub require_pie: bool,
+    pub require_nx: bool,
+    pub require_stack_protector: bool,
+    pub require_fortify: bool,
+    pub require_bind_now: bool,
+    /// Require both IBT and SHSTK.
+    pub require_cet: bool,
+    pub forbid_textrel: bool,
+    pub forbid_executable_stack: bool,
+}
+
+impl HardeningPolicy {
+    /// Names of the checks `hardening` fails.
+    fn violations(&self, hardening: &Hardening) -> Vec<&'static str> {
+        let mut failed = Vec::new();
+        if hardening.relro < self.min_relro {
+            failed.push("relro");
+        }
+        if self.require_pie && hardening.pie == Pie::Disabled {
+            failed.push("pie");
+        }
+        if self.require_nx && !hardening.nx {
+            failed.push("nx");
+        }
+        if self.require_stack_protector && !hardening.stack_protector {
+            failed.push("stack_protector");
+        }
+        if self.require_fortify && hardening.fortified == 0 {
+            failed.push("fortify");
+        }
+        if self.require_bind_now && !hardening.bind_now {
+            failed.push("bind_now");
+        }
+        if self.require_cet && !(hardening.ibt && hardening.shstk) {
+            failed.push("cet");
+        }
+        if self.forbid_textrel && hardening.textrel {
+            failed.push("textrel");
+        }
+        if self.forbid_executable_stack && hardening.executable_stack {
+            failed.push("executable_stack");
+        }
+        failed
+    }
+}
+
+/// An ELF file that fails the policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HardeningViolation {
+    pub path: PathBuf,
+    /// Names of the failed checks (`relro`, `pie`, `nx`, `stack_protector`,
+    /// `fortify`, `bind_now`, `cet`, `textrel`, `executable_stack`).
+    pub checks: Vec<&'static str>,
+}
+
+impl fmt::Display for HardeningViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: fails hardening checks: {}",
+            self.path.display(),
+            self.checks.join(", ")
+        )
+    }
+}
+
+/// Outcome of [`check_hardening`] and [`check_closure_hardening`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HardeningReport {
+    /// Hardening of every ELF file, keyed by host path.
+    pub objects: BTreeMap<PathBuf, Hardening>,
+    /// Files failing the policy, ordered by path.
+    pub violations: Vec<HardeningViolation>,
+}
+
+impl HardeningReport {
+    /// Returns `true` if every file meets the policy.
+    pub fn passes(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Write the report as pretty-printed JSON.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        fs::write(path, data)
+            .with_context(|| format!("Failed to write hardening report: {}", path.display()))
+    }
+}
+
+/// Report the hardening of every executable and shared object under `dest_root`.
+///
+/// Uses the auto-detected backend, one file at a time.
+pub fn check_hardening(dest_root: &Path, policy: &HardeningPolicy) -> Result<HardeningReport> {
+    check_hardening_with_options(dest_root, policy, &ClosureOptions::default())
+}
+
+/// Report the hardening of every executable and shared object under `dest_root`.
+///
+/// # Errors
+///
+/// Fails if the tree can't be walked, no backend is available, or the
+/// backend can't read the needed sections. Policy violations are not errors;
+/// check [`HardeningReport::passes`].
+pub fn check_hardening_with_options(
+    dest_root: &Path,
+    policy: &HardeningPolicy,
+    options: &ClosureOptions,
+) -> Result<HardeningReport> {
+    let paths: Vec<PathBuf> = find_elf_files(dest_root)?.into_keys().collect();
+    build_report(paths, policy, options)
+}
+
+/// Report the hardening of every root and library in `closure`.
+pub fn check_closure_hardening(
+    closure: &DependencyClosure,
+    policy: &HardeningPolicy,
+    options: &ClosureOptions,
+) -> Result<HardeningReport> {
+    let paths: Vec<PathBuf> = closure.needed.keys().cloned().collect();
+    build_report(paths, policy, options)
+}
+
+fn build_report(
+    paths: Vec<PathBuf>,
+    policy: &HardeningPolicy,
+    options: &ClosureOptions,
+) -> Result<HardeningReport> {
+    let backend = options.backend()?;
+    let results = read_each(&paths, options.jobs, |path| backend.hardening(path))?;
+
+    let mut report = HardeningReport::default();
+    for (path, hardening) in paths.into_iter().zip(results) {
+        let checks = policy.violations(&hardening);
+        if !checks.is_empty() {
+            report.violations.push(HardeningViolation {
+                path: path.clone(),
+                checks,
+            });
+        }
+        report.objects.insert(path, hardening);
+    }
+    Ok(report)
+}
+
+/// Parse readelf -W -l -d -n --dyn-syms output into [`Hardening`].
+///
+/// ```text
+/// Elf file type is DYN (Position-Independent Executable file)
+///   INTERP         0x000318 0x0000000000000318 0x0000000000000318 0x00001c 0x00001c R   0x1
+///   GNU_STACK      0x000000 0x0000000000000000 0x0000000000000000 0x000000 0x000000 RW  0x10
+///   GNU_RELRO      0x0232b0 0x00000000000232b0 0x00000000000232b0 0x000d50 0x000d50 R   0x1
+///  0x000000000000001e (FLAGS)              BIND_NOW
+///  0x000000006ffffffb (FLAGS_1)            Flags: NOW PIE
+/// ```
+///
+/// eu-readelf output is accepted too: the file type comes from its `-h`
+/// `Type:` line and dynamic entries are listed by tag (`FLAGS_1  NOW PIE`).
+///
+/// Symbols are read with [`parse_readelf_symbols`](crate::parse_readelf_symbols)
+/// and CET features with [`parse_readelf_notes`](crate::parse_readelf_notes).
+pub fn parse_readelf_hardening(output: &str) -> Hardening {
+    let mut hardening = Hardening::default();
+    let mut is_dyn = false;
+    let mut has_interp = false;
+    let mut flags_1_pie = false;
+    let mut has_relro = false;
+
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+        if let Some(kind) = trimmed
+            .strip_prefix("Elf file type is ")
+            .or_else(|| trimmed.strip_prefix("Type:"))
+        {
+            is_dyn = kind.trim_start().starts_with("DYN");
+        } else if trimmed.starts_with("INTERP ") {
+            has_interp = true;
+        } else if trimmed.starts_with("GNU_RELRO ") {
+            has_relro = true;
+        } else if trimmed.starts_with("GNU_STACK ") {
+            // Type, offset, vaddr, paddr, filesz, memsz, then flags like "RW" or "RWE"
+            let fields: Vec<&str> = trimmed.split_whitespace().collect();
+            let flags = fields
+                .get(6..fields.len().saturating_sub(1))
+                .unwrap_or_default()
+                .concat();
+            hardening.executable_stack = flags.contains('E');
+            hardening.nx = !hardening.executable_stack;
+        } else if let Some((tag, value)) = dynamic_entry(trimmed) {
+            let words: Vec<&str> = value.split_whitespace().collect();
+            match tag {
+                "FLAGS_1" => {
+                    hardening.bind_now |= words.contains(&"NOW");
+                    flags_1_pie = words.contains(&"PIE");
+                }
+                "FLAGS" => {
+                    hardening.bind_now |= words.contains(&"BIND_NOW");
+                    hardening.textrel |= words.contains(&"TEXTREL");
+                }
+                "BIND_NOW" => hardening.bind_now = true,
+                "TEXTREL" => hardening.textrel = true,
+                _ => {}
+            }
+        }
+    }
+
+    hardening.relro = match (has_relro, hardening.bind_now) {
+        (false, _) => Relro::None,
+        (true, false) => Relro::Partial,
+        (true, true) => Relro::Full,
+    };
+    hardening.pie = match (is_dyn, has_interp || flags_1_pie) {
+        (false, _) => Pie::Disabled,
+        (true, true) => Pie::Enabled,
+        (true, false) => Pie::SharedObject,
+    };
+
+    for sym in parse_readelf_symbols(output).symbols {
+        if sym.name == "__stack_chk_fail" || sym.name == "__stack_chk_guard" {
+            hardening.stack_protector = true;
+        } else if sym.name.starts_with("__") && sym.name.ends_with("_chk") && !sym.defined {
+            hardening.fortified += 1;
+        }
+    }
+
+    let notes = parse_readelf_notes(output);
+    hardening.ibt = notes.ibt;
+    hardening.shstk = notes.shstk;
+
+    hardening
+}
+
+/// The tag and value of a dynamic entry, from readelf's
+/// ` 0x000000000000001e (FLAGS)  BIND_NOW` or eu-readelf's `  FLAGS  BIND_NOW`.
+fn dynamic_entry(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    if line.starts_with("0x") {
+        let (_, rest) = line.split_once('(')?;
+        return rest.split_once(')');
+    }
+    Some(line.split_once(char::is_whitespace).unwrap_or((line, "")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HARDENED: &str = r#"
+Elf file type is DYN (Position-Independent Executable file)
+Program Headers:
+  Type           Offset   VirtAddr           PhysAddr           FileSiz  MemSiz   Flg Align
+  INTERP         0x000318 0x0000000000000318 0x0000000000000318 0x00001c 0x00001c R   0x1
+  GNU_STACK      0x000000 0x0000000000000000 0x0000000000000000 0x000000 0x000000 RW  0x10
+  GNU_RELRO      0x0232b0 0x00000000000232b0 0x00000000000232b0 0x000d50 0x000d50 R   0x1
+
+Dynamic section at offset 0x23d98 contains 27 entries:
+  Tag        Type                         Name/Value
+ 0x0000000000000001 (NEEDED)             Shared library: [libc.so.6]
+ 0x000000000000001e (FLAGS)              BIND_NOW
+ 0x000000006ffffffb (FLAGS_1)            Flags: NOW PIE
+
+Symbol table '.dynsym' contains 4 entries:
+   Num:    Value          Size Type    Bind   Vis      Ndx Name
+     0: 0000000000000000     0 NOTYPE  LOCAL  DEFAULT  UND
+     1: 0000000000000000     0 FUNC    GLOBAL DEFAULT  UND __stack_chk_fail@GLIBC_2.4 (2)
+     2: 0000000000000000     0 FUNC    GLOBAL DEFAULT  UND __printf_chk@GLIBC_2.3.4 (3)
+     3: 0000000000000000     0 FUNC    GLOBAL DEFAULT  UND __memcpy_chk@GLIBC_2.3.4 (3)
+
+Displaying notes found in: .note.gnu.property
+  Owner                Data size        Description
+  GNU                  0x00000030       NT_GNU_PROPERTY_TYPE_0        Properties: x86 feature: IBT, SHSTK, x86 ISA needed: x86-64-baseline
+"#;
+
+    #[test]
+    fn test_parse_hardened_executable() {
+        let hardening = parse_readelf_hardening(HARDENED);
+        assert_eq!(
+            hardening,
+            Hardening {
+                relro: Relro::Full,
+                pie: Pie::Enabled,
+                nx: true,
+                executable_stack: false,
+                stack_protector: true,
+                fortified: 2,
+                bind_now: true,
+                ibt: true,
+                shstk: true,
+                textrel: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_weak_executable_and_policy() {
+        let output = r#"
+Elf file type is EXEC (Executable file)
+  GNU_STACK      0x000000 0x0000000000000000 0x0000000000000000 0x000000 0x000000 RWE 0x10
+  GNU_RELRO      0x0232b0 0x00000000000232b0 0x00000000000232b0 0x000d50 0x000d50 R   0x1
+ 0x0000000000000016 (TEXTREL)            0x0
+"#;
+        let hardening = parse_readelf_hardening(output);
+        assert_eq!(hardening.relro, Relro::Partial);
+        assert_eq!(hardening.pie, Pie::Disabled);
+        assert!(hardening.executable_stack && !hardening.nx);
+        assert!(hardening.textrel);
+
+        let policy = HardeningPolicy {
+            min_relro: Relro::Full,
+            require_pie: true,
+            forbid_textrel: true,
+            ..HardeningPolicy::default()
+        };
+        assert_eq!(policy.violations(&hardening), ["relro", "pie", "textrel"]);
+        assert!(HardeningPolicy::default().violations(&hardening).is_empty());
+
+        let library = "Elf file type is DYN (Shared object file)\n";
+        assert_eq!(parse_readelf_hardening(library).pie, Pie::SharedObject);
+        assert!(policy
+            .violations(&parse_readelf_hardening(library))
+            .iter()
+            .all(|check| *check != "pie"));
+    }
+}
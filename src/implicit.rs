@@ -0,0 +1,449 @@
+//! Heuristic discovery of implicit dependencies by string scanning.
+//!
+//! Binaries often `execve()` helpers by absolute path or `dlopen()` hardcoded
+//! sonames without declaring either anywhere. Scanning `.rodata` and
+//! `.dynstr` for such strings finds candidates; an allowlist promotes the
+//! real ones to required.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::AnalysisCache;
+use crate::closure::{analyze_misses, read_each, ClosureOptions};
+use crate::filter::glob_match;
+use crate::paths::{find_library, resolve_in_root};
+
+/// One string from a `readelf -p` section dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionString {
+    /// Section name, e.g. `.rodata`.
+    pub section: String,
+    /// Offset of the string within the section.
+    pub offset: u64,
+    pub text: String,
+}
+
+/// Parse `readelf -p SECTION` output.
+///
+/// Example readelf output:
+/// ```text
+/// String dump of section '.rodata':
+///   [  1c2e]  /usr/share/locale
+///   [  1c40]  /sbin/modprobe
+/// ```
+///
+/// eu-readelf heads each dump `String section [16] '.rodata' contains ...`
+/// instead; the rows are the same.
+pub fn parse_readelf_strings(output: &str) -> Vec<SectionString> {
+    let mut strings = Vec::new();
+    let mut section = None;
+
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("String dump of section '") {
+            section = rest.strip_suffix("':").map(str::to_string);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("String section [") {
+            section = rest.split('\'').nth(1).map(str::to_string);
+            continue;
+        }
+        let Some(section) = &section else {
+            continue;
+        };
+        let Some((offset, text)) = line
+            .trim_start()
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+        else {
+            continue;
+        };
+        if let Ok(offset) = u64::from_str_radix(offset.trim(), 16) {
+            strings.push(SectionString {
+                section: section.clone(),
+                offset,
+                text: text.trim_start().to_string(),
+            });
+        }
+    }
+
+    strings
+}
+
+/// What kind of target a string names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImplicitKind {
+    /// An absolute path to a file in the source root, e.g. `/sbin/modprobe`.
+    Path,
+    /// A `lib*.so*` name not among the object's NEEDED entries.
+    Library,
+}
+
+impl fmt::Display for ImplicitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Path => "path",
+            Self::Library => "library",
+        })
+    }
+}
+
+/// A string that looks like a dependency the object doesn't declare.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImplicitDependency {
+    /// Host path of the object containing the string.
+    pub object: PathBuf,
+    /// Section and offset of the (first) occurrence.
+    pub section: String,
+    pub offset: u64,
+    /// The string as found: an image path or a soname.
+    pub target: String,
+    pub kind: ImplicitKind,
+    /// Host path of the target in the source root, if it was found there.
+    pub resolved: Option<PathBuf>,
+}
+
+impl fmt::Display for ImplicitDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}+{:#x}): {} {}",
+            self.object.display(),
+            self.section,
+            self.offset,
+            self.kind,
+            self.target
+        )
+    }
+}
+
+/// Targets to promote from possible to required.
+///
+/// Patterns are globs matched against the whole target, e.g.
+/// `/usr/libexec/*` or `libidn2.so.*`; `*` and `?` stop at `/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImplicitAllowlist {
+    pub patterns: Vec<String>,
+}
+
+impl ImplicitAllowlist {
+    /// An empty allowlist, which promotes nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse an allowlist, one target pattern per line.
+    ///
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> Self {
+        let patterns = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect();
+        Self { patterns }
+    }
+
+    /// Read and parse an allowlist file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read allowlist: {}", path.display()))?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Append a pattern.
+    pub fn promote(&mut self, pattern: &str) -> &mut Self {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Check whether `target` is promoted.
+    pub fn promotes(&self, target: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), target.as_bytes()))
+    }
+}
+
+/// Options for [`scan_implicit_dependencies`].
+#[derive(Debug, Clone, Default)]
+pub struct ImplicitOptions {
+    /// Targets that are known to be real dependencies.
+    pub allowlist: ImplicitAllowlist,
+    /// How objects are analyzed (backend and worker threads).
+    pub analysis: ClosureOptions,
+}
+
+/// Outcome of [`scan_implicit_dependencies`], ordered by object, in string
+/// order within an object.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImplicitReport {
+    /// Number of objects scanned.
+    pub objects: usize,
+    /// Hits promoted by the allowlist.
+    pub required: Vec<ImplicitDependency>,
+    /// Possible implicit dependencies, for review.
+    pub possible: Vec<ImplicitDependency>,
+    /// Objects that failed to analyze, with the error; they are not scanned.
+    pub failed: BTreeMap<PathBuf, String>,
+}
+
+impl ImplicitReport {
+    /// Host paths of required path targets, ready to pass to
+    /// [`install_binaries`](crate::install_binaries) as extra binaries.
+    pub fn required_paths(&self) -> Vec<&Path> {
+        self.required_of(ImplicitKind::Path)
+    }
+
+    /// Host paths of required libraries that were found in the source root.
+    pub fn required_libraries(&self) -> Vec<&Path> {
+        self.required_of(ImplicitKind::Library)
+    }
+
+    /// Required targets that aren't in the source root.
+    pub fn unresolved(&self) -> impl Iterator<Item = &ImplicitDependency> {
+        self.required.iter().filter(|dep| dep.resolved.is_none())
+    }
+
+    fn required_of(&self, kind: ImplicitKind) -> Vec<&Path> {
+        let mut paths: Vec<&Path> = self
+            .required
+            .iter()
+            .filter(|dep| dep.kind == kind)
+            .filter_map(|dep| dep.resolved.as_deref())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+/// Scan `objects` (host paths under `source_root`) for strings that look like
+/// undeclared dependencies.
+///
+/// Strings in `.rodata` and `.dynstr` are reported when they are an absolute
+/// path to a file (not a directory) that exists in `source_root`, or a whole
+/// `lib*.so*` name that isn't one of the object's NEEDED entries or its own
+/// soname. Library names are looked up like NEEDED entries. Each target is
+/// reported once per object. This is a heuristic: expect false positives,
+/// and use `options.allowlist` to promote the real dependencies.
+///
+/// # Errors
+///
+/// Fails if no backend is available or the backend can't dump strings.
+/// Objects that fail to analyze are listed in [`ImplicitReport::failed`].
+pub fn scan_implicit_dependencies(
+    source_root: &Path,
+    objects: &[&Path],
+    cache: &mut AnalysisCache,
+    options: &ImplicitOptions,
+) -> Result<ImplicitReport> {
+    let backend = options.analysis.backend()?;
+
+    let mut objects: Vec<PathBuf> = objects.iter().map(|path| path.to_path_buf()).collect();
+    let failed: BTreeMap<PathBuf, String> =
+        analyze_misses(backend, &objects, cache, options.analysis.jobs)
+            .into_iter()
+            .map(|(path, e)| (path, format!("{:#}", e)))
+            .collect();
+    objects.retain(|path| !failed.contains_key(path));
+    let dumps = read_each(&objects, options.analysis.jobs, |path| {
+        backend.strings(path)
+    })?;
+
+    let mut report = ImplicitReport {
+        objects: objects.len(),
+        failed,
+        ..ImplicitReport::default()
+    };
+    for (object, strings) in objects.iter().zip(dumps) {
+        let declared: HashSet<&str> = cache
+            .get(object)
+            .map(|facts| {
+                facts
+                    .needed
+                    .iter()
+                    .chain(&facts.soname)
+                    .map(String::as_str)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut seen = HashSet::new();
+
+        for string in strings {
+            let text = string.text.as_str();
+            let (kind, resolved) = if is_path_candidate(text) {
+                let resolved = resolve_in_root(source_root, Path::new(text))?;
+                if !resolved.is_file() {
+                    continue;
+                }
+                (ImplicitKind::Path, Some(resolved))
+            } else if is_library_candidate(text) && !declared.contains(text) {
+                (ImplicitKind::Library, find_library(source_root, text, &[]))
+            } else {
+                continue;
+            };
+            if !seen.insert(string.text.clone()) {
+                continue;
+            }
+
+            let dependency = ImplicitDependency {
+                object: object.clone(),
+                section: string.section,
+                offset: string.offset,
+                target: string.text,
+                kind,
+                resolved,
+            };
+            if options.allowlist.promotes(&dependency.target) {
+                report.required.push(dependency);
+            } else {
+                report.possible.push(dependency);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// An absolute path without whitespace or format directives.
+fn is_path_candidate(text: &str) -> bool {
+    text.len() > 1
+        && text.starts_with('/')
+        && !text.contains(|c: char| c.is_whitespace() || c == '%')
+}
+
+/// A bare `lib*.so*` name, e.g. `libidn2.so.0`.
+fn is_library_candidate(text: &str) -> bool {
+    text.starts_with("lib")
+        && text.contains(".so")
+        && !text.contains(|c: char| c.is_whitespace() || c == '/' || c == '%')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::ElfFacts;
+    use crate::backend::ElfBackend;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    const DUMP: &str = "
+String dump of section '.dynstr':
+  [     1]  libkmod.so.2
+  [     e]  libc.so.6
+  [    18]  execv
+
+String dump of section '.rodata':
+  [    10]  /sbin/modprobe
+  [    1f]  /usr/share/locale
+  [    31]  /etc/missing.conf
+  [    43]  usage: %s /path
+  [    53]  libcrypto.so.3
+  [    62]  lib%s.so
+  [    6b]  /sbin/modprobe
+  [    7a]  libzstd.so.1
+";
+
+    /// Serves a fixed string dump for every file.
+    #[derive(Debug)]
+    struct FixedStrings;
+
+    impl ElfBackend for FixedStrings {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn analyze(&self, _path: &Path) -> Result<ElfFacts> {
+            Ok(ElfFacts::default())
+        }
+
+        fn strings(&self, _path: &Path) -> Result<Vec<SectionString>> {
+            Ok(parse_readelf_strings(DUMP))
+        }
+    }
+
+    #[test]
+    fn test_parse_readelf_strings() {
+        let strings = parse_readelf_strings(DUMP);
+        assert_eq!(strings.len(), 11);
+        assert_eq!(
+            strings[3],
+            SectionString {
+                section: ".rodata".to_string(),
+                offset: 0x10,
+                text: "/sbin/modprobe".to_string(),
+            }
+        );
+        assert_eq!(strings[6].text, "usage: %s /path");
+
+        // llvm-readelf indents less
+        let llvm =
+            parse_readelf_strings("String dump of section '.rodata':\n[    10] /sbin/modprobe\n");
+        assert_eq!(llvm[0].offset, 0x10);
+        assert_eq!(llvm[0].text, "/sbin/modprobe");
+    }
+
+    #[test]
+    fn test_scan_implicit_dependencies() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        for rel in ["usr/bin/kmod", "sbin/modprobe", "usr/lib64/libcrypto.so.3"] {
+            fs::create_dir_all(root.join(rel).parent().unwrap()).unwrap();
+            fs::write(root.join(rel), "").unwrap();
+        }
+        fs::create_dir_all(root.join("usr/share/locale")).unwrap();
+
+        let kmod = root.join("usr/bin/kmod");
+        let mut cache = AnalysisCache::new();
+        cache.insert(
+            kmod.clone(),
+            ElfFacts {
+                needed: vec!["libkmod.so.2".to_string(), "libc.so.6".to_string()],
+                ..ElfFacts::default()
+            },
+        );
+        let mut options = ImplicitOptions {
+            analysis: ClosureOptions {
+                backend: Some(Arc::new(FixedStrings)),
+                ..ClosureOptions::default()
+            },
+            ..ImplicitOptions::default()
+        };
+        options.allowlist = ImplicitAllowlist::parse("# helpers\n/sbin/*\nlibzstd.so.*\n");
+
+        let report = scan_implicit_dependencies(root, &[&kmod], &mut cache, &options).unwrap();
+        let targets = |deps: &[ImplicitDependency]| {
+            deps.iter()
+                .map(|dep| (dep.target.clone(), dep.kind))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            targets(&report.required),
+            [
+                ("/sbin/modprobe".to_string(), ImplicitKind::Path),
+                ("libzstd.so.1".to_string(), ImplicitKind::Library),
+            ]
+        );
+        assert_eq!(
+            targets(&report.possible),
+            [("libcrypto.so.3".to_string(), ImplicitKind::Library)]
+        );
+        assert_eq!(report.required[0].offset, 0x10);
+        assert_eq!(report.required_paths(), [root.join("sbin/modprobe")]);
+        assert!(report.required_libraries().is_empty());
+        let unresolved: Vec<_> = report.unresolved().map(|dep| dep.target.as_str()).collect();
+        assert_eq!(unresolved, ["libzstd.so.1"]);
+        assert_eq!(
+            report.possible[0].resolved.as_deref(),
+            Some(root.join("usr/lib64/libcrypto.so.3").as_path())
+        );
+    }
+}
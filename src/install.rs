@@ -0,0 +1,337 @@
+//! Installing binaries together with their library closure.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::AnalysisCache;
+use crate::closure::{read_each, resolve_closure_with_options, ClosureOptions, DependencyClosure};
+use crate::copy::{
+    copy_file, copy_library_with_options, resolve_dest, skip_or_update, CopyOptions, CopyReport,
+    LibraryLayout,
+};
+use crate::libc_flavor::{LibcMixPolicy, LibcReport};
+
+/// Options for [`install_binaries`].
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// How the dependency closure is resolved. `closure.dlopen` decides
+    /// which `.note.dlopen` dependencies are installed along with NEEDED ones.
+    pub closure: ClosureOptions,
+    /// How binaries and libraries are copied.
+    pub copy: CopyOptions,
+    /// What to do when the closure mixes glibc and musl objects.
+    pub libc_mixing: LibcMixPolicy,
+}
+
+/// Outcome of [`install_binaries`].
+#[derive(Debug, Clone, Default)]
+pub struct InstallReport {
+    /// The resolved dependency closure, for logging and attribution.
+    pub closure: DependencyClosure,
+    /// What was copied.
+    pub copy: CopyReport,
+}
+
+/// Copy binaries and every library they need from `source_root` to `dest_root`.
+///
+/// Binaries keep their path relative to `source_root`. The closure is resolved
+/// through `cache`, so a persistent [`AnalysisCache`] makes repeated installs
+/// of an unchanged source root skip dependency analysis. The libc check still
+/// reads the symbol versions of every closure object.
+///
+/// Returns the resolved closure together with a report of what was copied.
+///
+/// # Errors
+///
+/// Fails if a binary lies outside `source_root`, if any needed library can't
+/// be found, if the closure mixes glibc and musl objects (unless
+/// `options.libc_mixing` allows it), if the symbol versions needed for that
+/// check can't be read, or if a copy fails.
+pub fn install_binaries(
+    source_root: &Path,
+    binaries: &[&Path],
+    dest_root: &Path,
+    layout: &LibraryLayout,
+    cache: &mut AnalysisCache,
+    options: &InstallOptions,
+) -> Result<InstallReport> {
+    let closure = resolve_closure_with_options(
+        source_root,
+        binaries,
+        layout.extra_lib_paths,
+        cache,
+        &options.closure,
+    )?;
+    let mut report = CopyReport::default();
+
+    let missing: Vec<&str> = closure.missing().collect();
+    if !missing.is_empty() {
+        bail!(
+            "Could not find libraries in source (searched lib64, lib, extra paths): {}",
+            missing.join(", ")
+        );
+    }
+
+    // dlopen dependencies are optional: a missing one loses a feature, not the binary
+    if let Some(priority) = options.closure.dlopen {
+        for (path, dependency) in closure.unresolved_dlopen(priority) {
+            report.warnings.push(format!(
+                "{}: dlopen dependency {} not found in source: {}",
+                path.display(),
+                dependency.feature.as_deref().unwrap_or("(unnamed)"),
+                dependency.sonames.join(", ")
+            ));
+        }
+    }
+
+    if options.libc_mixing != LibcMixPolicy::Allow {
+        let backend = options.closure.backend()?;
+        let objects: Vec<PathBuf> = closure.needed.keys().cloned().collect();
+        let tables = read_each(&objects, options.closure.jobs, |path| backend.symbols(path))?;
+        let libc =
+            LibcReport::from_facts(objects.iter().zip(&tables).filter_map(|(path, table)| {
+                Some((
+                    path.as_path(),
+                    cache.get(path)?,
+                    table.version_needs.as_slice(),
+                ))
+            }));
+        if libc.is_mixed() {
+            let offenders = libc.describe_offenders();
+            if options.libc_mixing == LibcMixPolicy::Refuse {
+                bail!("Closure mixes glibc and musl objects: {}", offenders);
+            }
+            report.warnings.push(format!(
+                "Closure mixes glibc and musl objects: {}",
+                offenders
+            ));
+        }
+    }
+
+    for binary in binaries {
+        let rel = binary.strip_prefix(source_root).with_context(|| {
+            format!(
+                "Binary {} is not inside source root {}",
+                binary.display(),
+                source_root.display()
+            )
+        })?;
+        // A destination symlink is kept and written through, inside the root
+        let dest = resolve_dest(dest_root, &dest_root.join(rel))?;
+        if dest.exists() {
+            // Already installed; options.copy.freshness decides whether to trust it
+            skip_or_update(binary, &dest, &options.copy, &mut report)?;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        copy_file(binary, &dest, &options.copy, &mut report)
+            .with_context(|| format!("Failed to copy binary: {}", binary.display()))?;
+    }
+
+    // copy_library_to expects the library directories to exist
+    fs::create_dir_all(dest_root.join(layout.dest_lib64_path))?;
+    fs::create_dir_all(dest_root.join(layout.dest_lib_path))?;
+    for lib_name in closure.libraries.keys() {
+        report.merge(copy_library_with_options(
+            source_root,
+            lib_name,
+            dest_root,
+            layout,
+            &options.copy,
+        )?);
+    }
+
+    Ok(InstallReport {
+        closure,
+        copy: report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::ElfFacts;
+    use crate::copy::FreshnessPolicy;
+    use crate::dlopen::{DlopenDependency, DlopenPriority};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    const LAYOUT: LibraryLayout<'static> = LibraryLayout {
+        dest_lib64_path: "usr/lib64",
+        dest_lib_path: "usr/lib",
+        extra_lib_paths: &[],
+        private_lib_dirs: &["systemd"],
+    };
+
+    fn seed(root: &Path, cache: &mut AnalysisCache, rel: &str, needed: &[&str]) {
+        let path = root.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, rel).unwrap();
+        let facts = ElfFacts {
+            needed: needed.iter().map(|s| s.to_string()).collect(),
+            ..ElfFacts::default()
+        };
+        cache.insert(path, facts);
+    }
+
+    #[test]
+    fn test_install_binaries_copies_closure() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        let mut cache = AnalysisCache::new();
+        seed(&src, &mut cache, "usr/bin/tool", &["libfoo.so.1"]);
+        seed(
+            &src,
+            &mut cache,
+            "usr/lib64/libfoo.so.1",
+            &["libshared.so.0"],
+        );
+        seed(&src, &mut cache, "usr/lib64/systemd/libshared.so.0", &[]);
+
+        let tool = src.join("usr/bin/tool");
+        install_binaries(
+            &src,
+            &[&tool],
+            &dst,
+            &LAYOUT,
+            &mut cache,
+            &InstallOptions::default(),
+        )
+        .unwrap();
+
+        assert!(dst.join("usr/bin/tool").exists());
+        assert!(dst.join("usr/lib64/libfoo.so.1").exists());
+        assert!(dst.join("usr/lib64/systemd/libshared.so.0").exists());
+    }
+
+    #[test]
+    fn test_install_binaries_keeps_destination_symlinks() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        let mut cache = AnalysisCache::new();
+        seed(&src, &mut cache, "usr/bin/tool", &[]);
+        fs::create_dir_all(dst.join("usr/bin")).unwrap();
+        fs::create_dir_all(dst.join("usr/libexec")).unwrap();
+        fs::write(dst.join("usr/libexec/tool"), "old").unwrap();
+        std::os::unix::fs::symlink("/usr/libexec/tool", dst.join("usr/bin/tool")).unwrap();
+
+        let options = InstallOptions {
+            copy: CopyOptions {
+                freshness: FreshnessPolicy::ContentHash,
+                ..CopyOptions::default()
+            },
+            ..InstallOptions::default()
+        };
+        let tool = src.join("usr/bin/tool");
+        install_binaries(&src, &[&tool], &dst, &LAYOUT, &mut cache, &options).unwrap();
+
+        assert!(dst.join("usr/bin/tool").is_symlink());
+        assert_eq!(
+            fs::read(dst.join("usr/libexec/tool")).unwrap(),
+            b"usr/bin/tool"
+        );
+    }
+
+    #[test]
+    fn test_install_binaries_reports_missing_libraries() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let mut cache = AnalysisCache::new();
+        seed(&src, &mut cache, "usr/bin/tool", &["libgone.so.3"]);
+
+        let tool = src.join("usr/bin/tool");
+        let err = install_binaries(
+            &src,
+            &[&tool],
+            &temp.path().join("dst"),
+            &LAYOUT,
+            &mut cache,
+            &InstallOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("libgone.so.3"));
+    }
+
+    #[test]
+    fn test_install_binaries_guards_libc_mixing() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let mut cache = AnalysisCache::new();
+        seed(&src, &mut cache, "usr/bin/ls", &["libc.so.6"]);
+        seed(&src, &mut cache, "usr/bin/cat", &["libc.so.6"]);
+        seed(&src, &mut cache, "usr/bin/apk", &["libc.musl-x86_64.so.1"]);
+        seed(&src, &mut cache, "usr/lib64/libc.so.6", &[]);
+        seed(&src, &mut cache, "usr/lib64/libc.musl-x86_64.so.1", &[]);
+
+        let binaries = ["usr/bin/ls", "usr/bin/cat", "usr/bin/apk"].map(|b| src.join(b));
+        let binaries: Vec<&Path> = binaries.iter().map(PathBuf::as_path).collect();
+        let mut options = InstallOptions::default();
+        let err = install_binaries(
+            &src,
+            &binaries,
+            &temp.path().join("dst"),
+            &LAYOUT,
+            &mut cache,
+            &options,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("usr/bin/apk (musl: NEEDED libc.musl-x86_64.so.1)"));
+        assert!(!err.contains("usr/bin/ls"));
+
+        options.libc_mixing = LibcMixPolicy::Warn;
+        let report = install_binaries(
+            &src,
+            &binaries,
+            &temp.path().join("dst"),
+            &LAYOUT,
+            &mut cache,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.copy.warnings.len(), 1);
+        assert!(temp.path().join("dst/usr/bin/apk").exists());
+    }
+
+    #[test]
+    fn test_install_binaries_follows_dlopen_policy() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        let mut cache = AnalysisCache::new();
+        seed(&src, &mut cache, "usr/lib64/libtss2-esys.so.0", &[]);
+        seed(&src, &mut cache, "usr/lib64/libfido2.so.1", &[]);
+        let tool = src.join("usr/bin/systemd-cryptenroll");
+        seed(&src, &mut cache, "usr/bin/systemd-cryptenroll", &[]);
+        let dependency = |priority, soname: &str| DlopenDependency {
+            priority,
+            sonames: vec![soname.to_string()],
+            ..DlopenDependency::default()
+        };
+        cache.insert(
+            tool.clone(),
+            ElfFacts {
+                dlopen: vec![
+                    dependency(DlopenPriority::Recommended, "libtss2-esys.so.0"),
+                    dependency(DlopenPriority::Suggested, "libfido2.so.1"),
+                    dependency(DlopenPriority::Required, "libpwquality.so.1"),
+                ],
+                ..ElfFacts::default()
+            },
+        );
+
+        let mut options = InstallOptions::default();
+        options.closure.dlopen = Some(DlopenPriority::Recommended);
+        let report = install_binaries(&src, &[&tool], &dst, &LAYOUT, &mut cache, &options).unwrap();
+
+        assert!(dst.join("usr/lib64/libtss2-esys.so.0").exists());
+        assert!(!dst.join("usr/lib64/libfido2.so.1").exists());
+        assert_eq!(report.copy.warnings.len(), 1);
+        assert!(report.copy.warnings[0].contains("libpwquality.so.1"));
+    }
+}
@@ -3,14 +3,89 @@
 //! Uses `readelf -d` instead of `ldd` to extract library dependencies.
 //! This works for cross-compilation since readelf reads ELF headers directly
 //! without executing the binary (which ldd does via the host dynamic linker).
+//! llvm-readelf, eu-readelf or objdump can stand in for it; see [`ElfBackend`].
 
 mod analyze;
+mod audit;
+mod backend;
+mod cache;
+mod closure;
+mod compat;
 mod copy;
+mod dlopen;
+mod elf;
+mod fastcopy;
+mod filter;
+mod hardening;
+mod implicit;
+mod install;
+mod libc_flavor;
+mod manifest;
+mod metadata;
 mod paths;
+mod providers;
+mod rpath;
+mod security;
+mod symbols;
+mod symlinks;
 
-pub use analyze::{get_all_dependencies, get_library_dependencies, parse_readelf_output};
+pub use analyze::{
+    get_all_dependencies, get_elf_facts, get_elf_facts_batch, get_library_dependencies,
+    get_library_dependencies_batch, parse_readelf_batch_output, parse_readelf_facts,
+    parse_readelf_output, ElfFacts,
+};
+pub use audit::{audit_root, audit_root_with_options, AuditFinding, AuditOptions, AuditReport};
+pub use backend::{
+    default_backend, detect_backend, ElfBackend, EuReadelf, GnuReadelf, LlvmReadelf, Objdump,
+};
+pub use cache::{AnalysisCache, CacheKeyMode};
+pub use closure::{
+    resolve_closure, resolve_closure_with_options, ClosureOptions, DependencyClosure, DlopenEdge,
+};
+pub use compat::{
+    check_compat, check_compat_with_options, parse_readelf_notes, AbiTag, CompatFinding,
+    CompatReport, CompatTarget, ElfNotes, KernelVersion, X86IsaLevel,
+};
 pub use copy::{
-    copy_dir_recursive, copy_dir_recursive_overwrite, copy_library_to, create_symlink_if_missing,
-    make_executable,
+    copy_dir_recursive, copy_dir_recursive_overwrite, copy_dir_with_options, copy_library_to,
+    copy_library_with_options, create_symlink_if_missing, make_executable, Conflict,
+    ConflictPolicy, ConflictResolution, CopyAction, CopyEntry, CopyOptions, CopyReport, EntryKind,
+    FreshnessPolicy, LibraryLayout, SpecialFilePolicy, StalePolicy,
+};
+pub use dlopen::{parse_dlopen_metadata, parse_readelf_dlopen, DlopenDependency, DlopenPriority};
+pub use elf::{parse_elf_header, read_elf_header, ElfClass, ElfHeader, ET_DYN, ET_EXEC};
+pub use fastcopy::{CopyMethod, CopyStrategy};
+pub use filter::{FilterAction, FilterRule, FilterRules};
+pub use hardening::{
+    check_closure_hardening, check_hardening, check_hardening_with_options,
+    parse_readelf_hardening, Hardening, HardeningPolicy, HardeningReport, HardeningViolation, Pie,
+    Relro,
+};
+pub use implicit::{
+    parse_readelf_strings, scan_implicit_dependencies, ImplicitAllowlist, ImplicitDependency,
+    ImplicitKind, ImplicitOptions, ImplicitReport, SectionString,
+};
+pub use install::{install_binaries, InstallOptions, InstallReport};
+pub use libc_flavor::{
+    check_libc, check_libc_with_options, classify_libc, LibcEvidence, LibcFlavor, LibcMixPolicy,
+    LibcReport,
+};
+pub use manifest::{verify_manifest, Manifest, ManifestDiff, ManifestEntry, ModifiedEntry};
+pub use metadata::{clamp_tree_mtimes, source_date_epoch, PreserveOptions};
+pub use paths::{find_binary, find_library, find_sbin_binary, resolve_in_root};
+pub use providers::{demangle, SymbolIndex, SymbolIndexOptions, SymbolProvider};
+pub use rpath::{
+    lint_rpaths, lint_rpaths_with_options, RpathFinding, RpathIssue, RpathLintOptions, RpathReport,
+    SearchPathTag,
+};
+pub use security::{
+    audit_security, AllowRule, SecurityAllowlist, SecurityFinding, SecurityIssue, SecurityReport,
+};
+pub use symbols::{
+    check_symbols, check_symbols_with_options, parse_readelf_symbols, DynamicSymbol,
+    MissingVersion, SymbolBinding, SymbolReport, SymbolTable, UnresolvedSymbol, VersionNeed,
+};
+pub use symlinks::{
+    validate_symlinks, DanglingSymlink, RewrittenSymlink, SymlinkOptions, SymlinkReport,
+    SymlinkStyle,
 };
-pub use paths::{find_binary, find_library, find_sbin_binary};
@@ -0,0 +1,322 @@
+//! C library flavor detection (glibc or musl) and mixing checks.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::analyze::ElfFacts;
+use crate::closure::{analyze_all, read_each, ClosureOptions, DependencyClosure};
+use crate::symbols::VersionNeed;
+
+/// Which C library an object was built against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LibcFlavor {
+    Glibc,
+    Musl,
+}
+
+impl fmt::Display for LibcFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Glibc => "glibc",
+            Self::Musl => "musl",
+        })
+    }
+}
+
+/// One hint about an object's C library, e.g. its interpreter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibcEvidence {
+    pub flavor: LibcFlavor,
+    /// Where the hint came from, e.g. `NEEDED libc.so.6` or `version GLIBC_2.34`.
+    pub reason: String,
+}
+
+impl fmt::Display for LibcEvidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.flavor, self.reason)
+    }
+}
+
+/// What the installer does when a closure mixes glibc and musl objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LibcMixPolicy {
+    /// Fail the install, naming the offending objects.
+    #[default]
+    Refuse,
+    /// Install anyway, with a warning naming the offending objects.
+    Warn,
+    /// Don't check.
+    Allow,
+}
+
+/// Classify an object from its interpreter, NEEDED entries, soname and
+/// `.gnu.version_r` requirements.
+///
+/// Returns every hint found, so an object built against one libc and linked
+/// against the other yields evidence for both. Static binaries and objects
+/// that don't link a libc yield none.
+pub fn classify_libc(facts: &ElfFacts, version_needs: &[VersionNeed]) -> Vec<LibcEvidence> {
+    let mut evidence = Vec::new();
+    let mut push = |flavor: Option<LibcFlavor>, reason: String| {
+        if let Some(flavor) = flavor {
+            evidence.push(LibcEvidence { flavor, reason });
+        }
+    };
+
+    if let Some(interpreter) = &facts.interpreter {
+        let name = interpreter.rsplit('/').next().unwrap_or(interpreter);
+        push(
+            flavor_of_soname(name),
+            format!("interpreter {}", interpreter),
+        );
+    }
+    for library in &facts.needed {
+        let name = library.rsplit('/').next().unwrap_or(library);
+        push(flavor_of_soname(name), format!("NEEDED {}", library));
+    }
+    if let Some(soname) = &facts.soname {
+        push(flavor_of_soname(soname), format!("SONAME {}", soname));
+    }
+    // Only glibc versions its symbols; one GLIBC_ requirement is enough
+    if let Some(need) = version_needs
+        .iter()
+        .find(|need| need.version.starts_with("GLIBC_"))
+    {
+        push(
+            Some(LibcFlavor::Glibc),
+            format!("version {} from {}", need.version, need.library),
+        );
+    }
+    evidence
+}
+
+/// Flavor implied by the file name of a loader or C library.
+fn flavor_of_soname(name: &str) -> Option<LibcFlavor> {
+    if name.starts_with("ld-musl-") || name.starts_with("libc.musl-") || name == "libc.so" {
+        Some(LibcFlavor::Musl)
+    } else if name.starts_with("ld-linux")
+        || name.starts_with("ld64.so.")
+        || name == "ld.so.1"
+        || name == "libc.so.6"
+    {
+        Some(LibcFlavor::Glibc)
+    } else {
+        None
+    }
+}
+
+/// C library evidence for a set of objects, keyed by host path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LibcReport {
+    /// Evidence per object; objects with none are left out.
+    pub objects: BTreeMap<PathBuf, Vec<LibcEvidence>>,
+}
+
+impl LibcReport {
+    /// Classify already analyzed objects from their facts and
+    /// `.gnu.version_r` requirements, as read by [`ElfBackend::symbols`](crate::ElfBackend::symbols).
+    pub fn from_facts<'a>(
+        objects: impl IntoIterator<Item = (&'a Path, &'a ElfFacts, &'a [VersionNeed])>,
+    ) -> Self {
+        let objects = objects
+            .into_iter()
+            .map(|(path, facts, needs)| (path.to_path_buf(), classify_libc(facts, needs)))
+            .filter(|(_, evidence)| !evidence.is_empty())
+            .collect();
+        Self { objects }
+    }
+
+    /// The flavor of `path`, if it has evidence for exactly one.
+    pub fn flavor(&self, path: &Path) -> Option<LibcFlavor> {
+        let flavors = Self::flavors_of(self.objects.get(path)?);
+        (flavors.len() == 1).then(|| *flavors.first().unwrap())
+    }
+
+    /// Every flavor seen.
+    pub fn flavors(&self) -> BTreeSet<LibcFlavor> {
+        self.objects
+            .values()
+            .flat_map(|e| Self::flavors_of(e))
+            .collect()
+    }
+
+    /// Returns `true` if both glibc and musl objects were seen.
+    pub fn is_mixed(&self) -> bool {
+        self.flavors().len() > 1
+    }
+
+    /// Objects that don't fit in, in path order.
+    ///
+    /// These are the objects with evidence for both flavors, plus those of
+    /// the flavor fewer objects use (both, on a tie). Empty unless
+    /// [`is_mixed`](Self::is_mixed).
+    pub fn offenders(&self) -> Vec<(&Path, &[LibcEvidence])> {
+        if !self.is_mixed() {
+            return Vec::new();
+        }
+        let mut counts: BTreeMap<LibcFlavor, usize> = BTreeMap::new();
+        for path in self.objects.keys() {
+            if let Some(flavor) = self.flavor(path) {
+                *counts.entry(flavor).or_default() += 1;
+            }
+        }
+        let majority = counts.values().max().copied().unwrap_or(0);
+        let dominant: Vec<LibcFlavor> = counts
+            .iter()
+            .filter(|(_, &count)| count == majority)
+            .map(|(&flavor, _)| flavor)
+            .collect();
+
+        self.objects
+            .iter()
+            .filter(|(path, _)| match self.flavor(path) {
+                Some(flavor) => dominant.len() > 1 || !dominant.contains(&flavor),
+                None => true,
+            })
+            .map(|(path, evidence)| (path.as_path(), evidence.as_slice()))
+            .collect()
+    }
+
+    /// One-line description of the offenders, for errors and warnings.
+    pub(crate) fn describe_offenders(&self) -> String {
+        self.offenders()
+            .iter()
+            .map(|(path, evidence)| {
+                let reasons: Vec<String> = evidence.iter().map(|e| e.to_string()).collect();
+                format!("{} ({})", path.display(), reasons.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    fn flavors_of(evidence: &[LibcEvidence]) -> BTreeSet<LibcFlavor> {
+        evidence.iter().map(|e| e.flavor).collect()
+    }
+}
+
+/// Classify every object in `closure`.
+///
+/// Shorthand for [`check_libc_with_options`] with default options.
+pub fn check_libc(closure: &DependencyClosure) -> Result<LibcReport> {
+    check_libc_with_options(closure, &ClosureOptions::default())
+}
+
+/// Classify every object in `closure`, including `.gnu.version_r`
+/// requirements. Use [`LibcReport::is_mixed`] and [`LibcReport::offenders`]
+/// to find glibc and musl objects in the same closure.
+///
+/// # Errors
+///
+/// Fails if no backend is available, the backend can't read symbol tables,
+/// or any object fails to analyze.
+pub fn check_libc_with_options(
+    closure: &DependencyClosure,
+    options: &ClosureOptions,
+) -> Result<LibcReport> {
+    let backend = options.backend()?;
+    let objects: Vec<PathBuf> = closure.needed.keys().cloned().collect();
+    let facts = analyze_all(backend, &objects, options.jobs)?;
+    let tables = read_each(&objects, options.jobs, |path| backend.symbols(path))?;
+
+    Ok(LibcReport::from_facts(
+        objects
+            .iter()
+            .zip(&facts)
+            .zip(&tables)
+            .map(|((path, facts), table)| (path.as_path(), facts, table.version_needs.as_slice())),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts(interpreter: Option<&str>, needed: &[&str]) -> ElfFacts {
+        ElfFacts {
+            interpreter: interpreter.map(String::from),
+            needed: needed.iter().map(|s| s.to_string()).collect(),
+            ..ElfFacts::default()
+        }
+    }
+
+    #[test]
+    fn test_classify_libc() {
+        let glibc = facts(Some("/lib64/ld-linux-x86-64.so.2"), &["libc.so.6"]);
+        let flavors: Vec<_> = classify_libc(&glibc, &[])
+            .iter()
+            .map(|e| e.flavor)
+            .collect();
+        assert_eq!(flavors, [LibcFlavor::Glibc, LibcFlavor::Glibc]);
+
+        let musl = facts(Some("/lib/ld-musl-x86_64.so.1"), &["libc.musl-x86_64.so.1"]);
+        let evidence = classify_libc(&musl, &[]);
+        assert!(evidence.iter().all(|e| e.flavor == LibcFlavor::Musl));
+        assert_eq!(evidence[0].reason, "interpreter /lib/ld-musl-x86_64.so.1");
+
+        // A library with no libc in NEEDED, caught by its symbol versions
+        let need = VersionNeed {
+            library: "libm.so.6".to_string(),
+            version: "GLIBC_2.29".to_string(),
+            weak: false,
+        };
+        let evidence = classify_libc(&facts(None, &["libm.so.6"]), &[need]);
+        assert_eq!(evidence.len(), 1);
+        assert_eq!(evidence[0].reason, "version GLIBC_2.29 from libm.so.6");
+
+        assert!(classify_libc(&facts(None, &["libz.so.1"]), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_offenders() {
+        let glibc_tool = facts(Some("/lib64/ld-linux-x86-64.so.2"), &["libc.so.6"]);
+        let glibc_lib = facts(None, &["libc.so.6"]);
+        let musl_tool = facts(Some("/lib/ld-musl-x86_64.so.1"), &["libc.so"]);
+        let crossed = facts(Some("/lib/ld-musl-x86_64.so.1"), &["libc.so.6"]);
+
+        let report = LibcReport::from_facts([
+            (Path::new("/src/usr/bin/a"), &glibc_tool, &[][..]),
+            (Path::new("/src/usr/lib64/liba.so"), &glibc_lib, &[][..]),
+            (
+                Path::new("/src/usr/lib64/libz.so"),
+                &ElfFacts::default(),
+                &[],
+            ),
+        ]);
+        assert!(!report.is_mixed());
+        assert!(report.offenders().is_empty());
+        assert_eq!(report.objects.len(), 2);
+
+        let report = LibcReport::from_facts([
+            (Path::new("/src/usr/bin/a"), &glibc_tool, &[][..]),
+            (Path::new("/src/usr/bin/b"), &musl_tool, &[][..]),
+            (Path::new("/src/usr/bin/c"), &crossed, &[][..]),
+            (Path::new("/src/usr/lib64/liba.so"), &glibc_lib, &[][..]),
+        ]);
+        assert!(report.is_mixed());
+        assert_eq!(report.flavor(Path::new("/src/usr/bin/c")), None);
+        let offenders: Vec<&Path> = report.offenders().iter().map(|(p, _)| *p).collect();
+        assert_eq!(
+            offenders,
+            [Path::new("/src/usr/bin/b"), Path::new("/src/usr/bin/c")]
+        );
+
+        // A library whose only glibc hint is a symbol version
+        let need = VersionNeed {
+            library: "libm.so.6".to_string(),
+            version: "GLIBC_2.29".to_string(),
+            weak: false,
+        };
+        let plain = facts(None, &["libm.so.6"]);
+        let report = LibcReport::from_facts([
+            (Path::new("/src/usr/bin/b"), &musl_tool, &[][..]),
+            (Path::new("/src/usr/lib/libm.so"), &plain, &[need][..]),
+        ]);
+        assert!(report.is_mixed());
+        assert_eq!(
+            report.flavor(Path::new("/src/usr/lib/libm.so")),
+            Some(LibcFlavor::Glibc)
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! Manifests of installed files, and verification of a tree against one.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::cache::sha256_file;
+use crate::copy::{ConflictResolution, CopyAction, CopyReport, EntryKind};
+use crate::paths::resolve_in_root;
+
+/// Bump when the on-disk manifest format changes.
+const MANIFEST_FORMAT_VERSION: u32 = 1;
+
+/// What is recorded about one path in the image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the destination root.
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// Permission bits, including setuid/setgid/sticky.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Size in bytes of regular files, 0 for everything else.
+    pub size: u64,
+    /// Hex SHA-256 of regular file contents.
+    pub sha256: Option<String>,
+    pub symlink_target: Option<PathBuf>,
+    /// Where the entry was copied from, if known.
+    pub source: Option<PathBuf>,
+}
+
+impl ManifestEntry {
+    /// Describe what is currently at `root/rel`.
+    fn read(root: &Path, rel: &Path, source: Option<PathBuf>) -> Result<Self> {
+        let path = root.join(rel);
+        let meta = fs::symlink_metadata(&path)
+            .with_context(|| format!("Failed to read metadata: {}", path.display()))?;
+        let kind = EntryKind::of(&meta);
+        let sha256 = if kind == EntryKind::File {
+            Some(
+                sha256_file(&path)
+                    .with_context(|| format!("Failed to hash: {}", path.display()))?,
+            )
+        } else {
+            None
+        };
+        let symlink_target = if kind == EntryKind::Symlink {
+            Some(fs::read_link(&path)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            path: rel.to_path_buf(),
+            kind,
+            mode: meta.mode() & 0o7777,
+            uid: meta.uid(),
+            gid: meta.gid(),
+            size: if kind == EntryKind::File {
+                meta.len()
+            } else {
+                0
+            },
+            sha256,
+            symlink_target,
+            source,
+        })
+    }
+
+    /// Names of the recorded fields that differ from `other` (source excluded).
+    fn differences(&self, other: &ManifestEntry) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.kind != other.kind {
+            fields.push("kind");
+        }
+        // Symlink permissions are meaningless on Linux
+        if self.mode != other.mode && self.kind != EntryKind::Symlink {
+            fields.push("mode");
+        }
+        if self.uid != other.uid || self.gid != other.gid {
+            fields.push("owner");
+        }
+        if self.size != other.size {
+            fields.push("size");
+        }
+        if self.sha256 != other.sha256 {
+            fields.push("sha256");
+        }
+        if self.symlink_target != other.symlink_target {
+            fields.push("symlink_target");
+        }
+        fields
+    }
+}
+
+/// Every path installed into a destination root, keyed by relative path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestFile {
+    version: u32,
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// An empty manifest.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot every path under `dest_root` (without sources).
+    pub fn from_tree(dest_root: &Path) -> Result<Self> {
+        let mut manifest = Self::new();
+        for rel in walk_tree(dest_root)? {
+            manifest.record_path(dest_root, &rel, None)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Append what a copy or install put under `dest_root`.
+    ///
+    /// Every entry of `report` that exists in the destination is recorded as
+    /// it is now on disk, along with its source; excluded and skipped entries
+    /// are not. Parent directories up to `dest_root` are recorded too, since
+    /// copies create them implicitly, and so are destination symlinks a copy
+    /// merged through (a usr-merged `lib -> usr/lib`). Entries reached
+    /// through such a symlink are recorded where they really are, as
+    /// [`verify_manifest`] finds them.
+    ///
+    /// # Errors
+    ///
+    /// Fails if an entry lies outside `dest_root` or can't be read.
+    pub fn record_report(&mut self, dest_root: &Path, report: &CopyReport) -> Result<()> {
+        for conflict in &report.conflicts {
+            if conflict.resolution == ConflictResolution::MergedIntoDirectory {
+                self.record_destination(dest_root, &conflict.path, None)?;
+            }
+        }
+        for entry in &report.entries {
+            if matches!(entry.action, CopyAction::Excluded | CopyAction::Skipped) {
+                continue;
+            }
+            self.record_destination(dest_root, &entry.destination, Some(entry.source.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Record a destination host path, its parents, and its source.
+    fn record_destination(
+        &mut self,
+        dest_root: &Path,
+        destination: &Path,
+        source: Option<PathBuf>,
+    ) -> Result<()> {
+        let Ok(rel) = destination.strip_prefix(dest_root) else {
+            bail!(
+                "Copied path {} is not inside destination root {}",
+                destination.display(),
+                dest_root.display()
+            );
+        };
+        // Resolve the parent only: a symlink entry is recorded as itself
+        let rel = match (rel.parent(), rel.file_name()) {
+            (Some(parent), Some(name)) => resolve_in_root(dest_root, parent)?
+                .strip_prefix(dest_root)?
+                .join(name),
+            _ => rel.to_path_buf(),
+        };
+        let mut parent = rel.parent();
+        while let Some(dir) = parent.filter(|dir| !dir.as_os_str().is_empty()) {
+            if !self.entries.contains_key(dir) {
+                self.record_path(dest_root, dir, None)?;
+            }
+            parent = dir.parent();
+        }
+        if source.is_some() || !self.entries.contains_key(&rel) {
+            self.record_path(dest_root, &rel, source)?;
+        }
+        Ok(())
+    }
+
+    /// Record the current state of `dest_root/rel`, replacing any earlier entry.
+    pub fn record_path(
+        &mut self,
+        dest_root: &Path,
+        rel: &Path,
+        source: Option<PathBuf>,
+    ) -> Result<()> {
+        let entry = ManifestEntry::read(dest_root, rel, source)?;
+        self.entries.insert(rel.to_path_buf(), entry);
+        Ok(())
+    }
+
+    /// Read a manifest written by [`save`](Self::save).
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path)
+            .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let file: ManifestFile = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse manifest: {}", path.display()))?;
+        if file.version != MANIFEST_FORMAT_VERSION {
+            bail!(
+                "Unsupported manifest version {} in {} (expected {})",
+                file.version,
+                path.display(),
+                MANIFEST_FORMAT_VERSION
+            );
+        }
+        let entries = file
+            .entries
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        Ok(Self { entries })
+    }
+
+    /// Write the manifest as JSON, entries sorted by path.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = ManifestFile {
+            version: MANIFEST_FORMAT_VERSION,
+            entries: self.entries.values().cloned().collect(),
+        };
+        let data = serde_json::to_vec_pretty(&file)?;
+        fs::write(path, data)
+            .with_context(|| format!("Failed to write manifest: {}", path.display()))
+    }
+}
+
+/// A path whose current state differs from its manifest entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifiedEntry {
+    pub path: PathBuf,
+    /// Names of the differing fields (`kind`, `mode`, `owner`, `size`,
+    /// `sha256`, `symlink_target`).
+    pub fields: Vec<&'static str>,
+}
+
+/// Outcome of [`verify_manifest`]. Paths are relative to the destination root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    /// In the manifest but not on disk.
+    pub missing: Vec<PathBuf>,
+    /// On disk but not in the manifest.
+    pub extra: Vec<PathBuf>,
+    pub modified: Vec<ModifiedEntry>,
+}
+
+impl ManifestDiff {
+    /// Returns `true` if the tree matches the manifest exactly.
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Check the tree under `dest_root` against `manifest`.
+///
+/// Every path on disk is compared by type, mode, owner, size, content hash
+/// and symlink target.
+pub fn verify_manifest(dest_root: &Path, manifest: &Manifest) -> Result<ManifestDiff> {
+    let mut diff = ManifestDiff::default();
+    let on_disk = walk_tree(dest_root)?;
+
+    for rel in &on_disk {
+        match manifest.entries.get(rel) {
+            None => diff.extra.push(rel.clone()),
+            Some(expected) => {
+                let actual = ManifestEntry::read(dest_root, rel, None)?;
+                let fields = expected.differences(&actual);
+                if !fields.is_empty() {
+                    diff.modified.push(ModifiedEntry {
+                        path: rel.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+
+    let on_disk: BTreeSet<_> = on_disk.into_iter().collect();
+    diff.missing = manifest
+        .entries
+        .keys()
+        .filter(|rel| !on_disk.contains(*rel))
+        .cloned()
+        .collect();
+
+    Ok(diff)
+}
+
+/// Every path under `root`, relative and sorted, without following symlinks.
+fn walk_tree(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(dir) = pending.pop() {
+        let full = root.join(&dir);
+        for entry in fs::read_dir(&full)
+            .with_context(|| format!("Failed to read directory: {}", full.display()))?
+        {
+            let entry = entry?;
+            let rel = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(rel.clone());
+            }
+            paths.push(rel);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::copy::{copy_dir_recursive, copy_library_to};
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn copied_tree(base: &Path) -> (PathBuf, Manifest) {
+        let src = base.join("src");
+        let dst = base.join("dst");
+        fs::create_dir_all(src.join("usr/bin")).unwrap();
+        fs::write(src.join("usr/bin/tool"), "tool").unwrap();
+        std::os::unix::fs::symlink("tool", src.join("usr/bin/alias")).unwrap();
+
+        let report = copy_dir_recursive(&src, &dst).unwrap();
+        let mut manifest = Manifest::new();
+        manifest.record_report(&dst, &report).unwrap();
+        (dst, manifest)
+    }
+
+    #[test]
+    fn test_manifest_records_copy_report() {
+        let temp = TempDir::new().unwrap();
+        let (_, manifest) = copied_tree(temp.path());
+
+        let tool = &manifest.entries[Path::new("usr/bin/tool")];
+        assert_eq!(tool.kind, EntryKind::File);
+        assert_eq!(tool.size, 4);
+        assert_eq!(tool.source, Some(temp.path().join("src/usr/bin/tool")));
+        assert_eq!(tool.sha256.as_ref().unwrap().len(), 64);
+        let alias = &manifest.entries[Path::new("usr/bin/alias")];
+        assert_eq!(alias.symlink_target, Some(PathBuf::from("tool")));
+        assert!(manifest.entries.contains_key(Path::new("usr")));
+    }
+
+    #[test]
+    fn test_manifest_of_copy_through_usr_merge_symlink() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("lib/libfoo.so.1"), "foo").unwrap();
+        fs::create_dir_all(src.join("usr/lib")).unwrap();
+        fs::write(src.join("usr/lib/libbar.so.1.0"), "bar").unwrap();
+        std::os::unix::fs::symlink("libbar.so.1.0", src.join("usr/lib/libbar.so.1")).unwrap();
+        fs::create_dir_all(dst.join("usr/lib")).unwrap();
+        std::os::unix::fs::symlink("usr/lib", dst.join("lib")).unwrap();
+
+        let mut manifest = Manifest::new();
+        let report = copy_dir_recursive(&src.join("lib"), &dst.join("lib")).unwrap();
+        manifest.record_report(&dst, &report).unwrap();
+        let report = copy_dir_recursive(&src, &dst).unwrap();
+        manifest.record_report(&dst, &report).unwrap();
+        // The library symlink is created as dst/lib/libbar.so.1
+        let report = copy_library_to(&src, "libbar.so.1", &dst, "lib64", "lib", &[], &[]).unwrap();
+        manifest.record_report(&dst, &report).unwrap();
+
+        assert!(manifest
+            .entries
+            .contains_key(Path::new("usr/lib/libfoo.so.1")));
+        assert!(manifest
+            .entries
+            .contains_key(Path::new("usr/lib/libbar.so.1")));
+        assert_eq!(manifest.entries[Path::new("lib")].kind, EntryKind::Symlink);
+        assert!(!manifest.entries.contains_key(Path::new("lib/libfoo.so.1")));
+        let diff = verify_manifest(&dst, &manifest).unwrap();
+        assert!(diff.is_clean(), "{:?}", diff);
+    }
+
+    #[test]
+    fn test_verify_manifest_reports_changes() {
+        let temp = TempDir::new().unwrap();
+        let (dst, manifest) = copied_tree(temp.path());
+
+        let saved = temp.path().join("manifest.json");
+        manifest.save(&saved).unwrap();
+        let manifest = Manifest::load(&saved).unwrap();
+        assert!(verify_manifest(&dst, &manifest).unwrap().is_clean());
+
+        fs::write(dst.join("usr/bin/tool"), "evil").unwrap();
+        fs::set_permissions(dst.join("usr/bin/tool"), fs::Permissions::from_mode(0o4755)).unwrap();
+        fs::remove_file(dst.join("usr/bin/alias")).unwrap();
+        fs::write(dst.join("usr/bin/dropped"), "").unwrap();
+
+        let diff = verify_manifest(&dst, &manifest).unwrap();
+        assert_eq!(diff.missing, vec![PathBuf::from("usr/bin/alias")]);
+        assert_eq!(diff.extra, vec![PathBuf::from("usr/bin/dropped")]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].path, PathBuf::from("usr/bin/tool"));
+        assert_eq!(diff.modified[0].fields, vec!["mode", "sha256"]);
+    }
+}
@@ -0,0 +1,453 @@
+//! Preservation of file metadata: modes, timestamps, ownership and xattrs.
+
+use anyhow::{Context, Result};
+use std::ffi::{CStr, CString};
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+/// Which metadata a copy carries over from source to destination.
+///
+/// The default preserves permission bits only, as `fs::copy` does; the rest
+/// is opt-in, e.g. with [`all`](Self::all). Ownership is only attempted when
+/// running as root; other missing privileges are reported as warnings
+/// rather than errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreserveOptions {
+    /// Permission bits, including setuid/setgid/sticky, for files and directories.
+    pub mode: bool,
+    /// Access and modification times.
+    pub timestamps: bool,
+    /// Owner and group (root only).
+    pub ownership: bool,
+    /// Extended attributes other than ACLs and capabilities (`user.*`, `security.selinux`, ...).
+    pub xattrs: bool,
+    /// POSIX ACLs (`system.posix_acl_access`, `system.posix_acl_default`).
+    pub acls: bool,
+    /// File capabilities (`security.capability`), e.g. `cap_net_raw` on `ping`.
+    pub capabilities: bool,
+    /// Clamp access and modification times to at most this many seconds
+    /// since the epoch, for reproducible trees. Applies whether or not
+    /// `timestamps` is set; see [`source_date_epoch`].
+    pub clamp_mtime: Option<i64>,
+}
+
+impl PreserveOptions {
+    /// Preserve all supported metadata.
+    pub const fn all() -> Self {
+        Self {
+            mode: true,
+            timestamps: true,
+            ownership: true,
+            xattrs: true,
+            acls: true,
+            capabilities: true,
+            clamp_mtime: None,
+        }
+    }
+
+    /// Preserve nothing beyond what `fs::copy` does (regular file permissions).
+    pub const fn none() -> Self {
+        Self {
+            mode: false,
+            timestamps: false,
+            ownership: false,
+            xattrs: false,
+            acls: false,
+            capabilities: false,
+            clamp_mtime: None,
+        }
+    }
+
+    fn wants_xattr(&self, name: &[u8]) -> bool {
+        match name {
+            b"security.capability" => self.capabilities,
+            b"system.posix_acl_access" | b"system.posix_acl_default" => self.acls,
+            _ => self.xattrs,
+        }
+    }
+}
+
+impl Default for PreserveOptions {
+    fn default() -> Self {
+        Self {
+            mode: true,
+            ..Self::none()
+        }
+    }
+}
+
+/// The `SOURCE_DATE_EPOCH` environment variable, if set to a valid timestamp.
+///
+/// Use as [`PreserveOptions::clamp_mtime`] to follow the
+/// reproducible-builds.org convention.
+pub fn source_date_epoch() -> Option<i64> {
+    std::env::var("SOURCE_DATE_EPOCH").ok()?.trim().parse().ok()
+}
+
+/// Clamp the times of everything under `root`, `root` included, to `epoch`.
+///
+/// For trees assembled by other means than this crate's copies, such as
+/// directories created along the way, so that the whole tree is reproducible.
+/// Symlinks are updated themselves, never their targets.
+pub fn clamp_tree_mtimes(root: &Path, epoch: i64) -> Result<()> {
+    let meta = fs::symlink_metadata(root)
+        .with_context(|| format!("Failed to read metadata: {}", root.display()))?;
+    if meta.is_dir() {
+        let mut entries = fs::read_dir(root)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            clamp_tree_mtimes(&entry.path(), epoch)?;
+        }
+    }
+    let (atime, mtime) = clamp_times(file_times(&meta), epoch);
+    set_times(root, atime, mtime)
+        .with_context(|| format!("Failed to set timestamps: {}", root.display()))
+}
+
+/// Copy the metadata selected by `preserve` from `src` onto `dst`.
+///
+/// `src_meta` must be the `symlink_metadata` of `src`. Symlinks themselves
+/// are updated (never their targets). Failures caused by missing privileges
+/// or unsupported filesystems are pushed onto `warnings`.
+pub(crate) fn apply_metadata(
+    src: &Path,
+    dst: &Path,
+    src_meta: &Metadata,
+    preserve: &PreserveOptions,
+    warnings: &mut Vec<String>,
+) -> Result<()> {
+    let is_symlink = src_meta.file_type().is_symlink();
+
+    // chown clears setuid bits and capabilities, so it has to come first
+    if preserve.ownership {
+        preserve_ownership(dst, src_meta, warnings)?;
+    }
+
+    if preserve.mode && !is_symlink {
+        let mode = src_meta.permissions().mode() & 0o7777;
+        fs::set_permissions(dst, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set permissions: {}", dst.display()))?;
+    }
+
+    if preserve.xattrs || preserve.acls || preserve.capabilities {
+        copy_xattrs(src, dst, preserve, warnings)?;
+    }
+
+    if preserve.timestamps || preserve.clamp_mtime.is_some() {
+        let times = if preserve.timestamps {
+            file_times(src_meta)
+        } else {
+            file_times(&fs::symlink_metadata(dst)?)
+        };
+        let (atime, mtime) = match preserve.clamp_mtime {
+            Some(epoch) => clamp_times(times, epoch),
+            None => times,
+        };
+        set_times(dst, atime, mtime)
+            .with_context(|| format!("Failed to set timestamps: {}", dst.display()))?;
+    }
+
+    Ok(())
+}
+
+fn preserve_ownership(dst: &Path, src_meta: &Metadata, warnings: &mut Vec<String>) -> Result<()> {
+    let dst_meta = fs::symlink_metadata(dst)?;
+    if dst_meta.uid() == src_meta.uid() && dst_meta.gid() == src_meta.gid() {
+        return Ok(());
+    }
+    // SAFETY: geteuid has no preconditions and cannot fail
+    if unsafe { libc::geteuid() } != 0 {
+        warnings.push(format!(
+            "Not running as root, ownership not preserved: {} (uid {}, gid {})",
+            dst.display(),
+            src_meta.uid(),
+            src_meta.gid()
+        ));
+        return Ok(());
+    }
+    std::os::unix::fs::lchown(dst, Some(src_meta.uid()), Some(src_meta.gid()))
+        .with_context(|| format!("Failed to set ownership: {}", dst.display()))
+}
+
+fn copy_xattrs(
+    src: &Path,
+    dst: &Path,
+    preserve: &PreserveOptions,
+    warnings: &mut Vec<String>,
+) -> Result<()> {
+    let names = match list_xattrs(src) {
+        Ok(names) => names,
+        // Source filesystem has no xattr support: nothing to preserve
+        Err(e) if e.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to list xattrs: {}", src.display()))
+        }
+    };
+
+    for name in names.iter().filter(|n| preserve.wants_xattr(n.to_bytes())) {
+        let value = get_xattr(src, name)
+            .with_context(|| format!("Failed to read xattr {:?}: {}", name, src.display()))?;
+        if let Err(e) = set_xattr(dst, name, &value) {
+            match e.raw_os_error() {
+                Some(libc::EPERM) | Some(libc::EACCES) => warnings.push(format!(
+                    "Missing privilege to set {}: {}",
+                    name.to_string_lossy(),
+                    dst.display()
+                )),
+                Some(libc::ENOTSUP) => warnings.push(format!(
+                    "Destination filesystem does not support {}: {}",
+                    name.to_string_lossy(),
+                    dst.display()
+                )),
+                _ => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to set xattr {:?}: {}", name, dst.display())
+                    })
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains NUL"))
+}
+
+/// List the extended attribute names of `path` (not following symlinks).
+pub(crate) fn list_xattrs(path: &Path) -> io::Result<Vec<CString>> {
+    let cpath = c_path(path)?;
+    loop {
+        // SAFETY: a null buffer with size 0 only queries the required size
+        let size = unsafe { libc::llistxattr(cpath.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let mut buf = vec![0u8; size as usize];
+        // SAFETY: buf is valid for buf.len() bytes
+        let size = unsafe { libc::llistxattr(cpath.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            // The list grew between the two calls; try again
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(err);
+        }
+        buf.truncate(size as usize);
+        return Ok(buf
+            .split_inclusive(|&b| b == 0)
+            .filter_map(|name| CStr::from_bytes_with_nul(name).ok())
+            .map(CStr::to_owned)
+            .collect());
+    }
+}
+
+/// Read one extended attribute of `path` (not following symlinks).
+pub(crate) fn get_xattr(path: &Path, name: &CStr) -> io::Result<Vec<u8>> {
+    let cpath = c_path(path)?;
+    loop {
+        // SAFETY: a null buffer with size 0 only queries the required size
+        let size =
+            unsafe { libc::lgetxattr(cpath.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        // SAFETY: buf is valid for buf.len() bytes
+        let size = unsafe {
+            libc::lgetxattr(
+                cpath.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(err);
+        }
+        buf.truncate(size as usize);
+        return Ok(buf);
+    }
+}
+
+/// Set one extended attribute on `path` (not following symlinks).
+pub(crate) fn set_xattr(path: &Path, name: &CStr, value: &[u8]) -> io::Result<()> {
+    let cpath = c_path(path)?;
+    // SAFETY: all pointers are valid for the lengths passed
+    let ret = unsafe {
+        libc::lsetxattr(
+            cpath.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A (seconds, nanoseconds) timestamp.
+type FileTime = (i64, i64);
+
+/// Access and modification times from `meta`.
+fn file_times(meta: &Metadata) -> (FileTime, FileTime) {
+    (
+        (meta.atime(), meta.atime_nsec()),
+        (meta.mtime(), meta.mtime_nsec()),
+    )
+}
+
+/// Cap both times at `epoch` seconds.
+fn clamp_times((atime, mtime): (FileTime, FileTime), epoch: i64) -> (FileTime, FileTime) {
+    let clamp = |time: FileTime| if time.0 >= epoch { (epoch, 0) } else { time };
+    (clamp(atime), clamp(mtime))
+}
+
+/// Set access and modification times of `path` without following symlinks.
+fn set_times(path: &Path, atime: FileTime, mtime: FileTime) -> io::Result<()> {
+    let times = [
+        libc::timespec {
+            tv_sec: atime.0 as libc::time_t,
+            tv_nsec: atime.1 as _,
+        },
+        libc::timespec {
+            tv_sec: mtime.0 as libc::time_t,
+            tv_nsec: mtime.1 as _,
+        },
+    ];
+    let cpath = c_path(path)?;
+    // SAFETY: cpath is NUL-terminated and times points to two timespecs
+    let ret = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            cpath.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_apply_metadata_mode_and_times() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::write(&src, "x").unwrap();
+        fs::write(&dst, "x").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o4711)).unwrap();
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let meta = fs::symlink_metadata(&src).unwrap();
+        let mut warnings = Vec::new();
+        apply_metadata(&src, &dst, &meta, &PreserveOptions::all(), &mut warnings).unwrap();
+
+        let dst_meta = fs::metadata(&dst).unwrap();
+        assert_eq!(dst_meta.permissions().mode() & 0o7777, 0o4711);
+        assert_eq!(dst_meta.modified().unwrap(), mtime);
+
+        // The default only carries over the mode, like fs::copy
+        let other = temp.path().join("other");
+        fs::write(&other, "x").unwrap();
+        apply_metadata(
+            &src,
+            &other,
+            &meta,
+            &PreserveOptions::default(),
+            &mut warnings,
+        )
+        .unwrap();
+        let other_meta = fs::metadata(&other).unwrap();
+        assert_eq!(other_meta.permissions().mode() & 0o7777, 0o4711);
+        assert_ne!(other_meta.modified().unwrap(), mtime);
+    }
+
+    #[test]
+    fn test_apply_metadata_copies_user_xattrs() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::write(&src, "x").unwrap();
+        fs::write(&dst, "x").unwrap();
+        let name = CString::new("user.leviso.test").unwrap();
+        if set_xattr(&src, &name, b"value").is_err() {
+            return; // Filesystem without user xattrs (e.g. some tmpfs setups)
+        }
+
+        let meta = fs::symlink_metadata(&src).unwrap();
+        let mut warnings = Vec::new();
+        apply_metadata(&src, &dst, &meta, &PreserveOptions::all(), &mut warnings).unwrap();
+        assert_eq!(get_xattr(&dst, &name).unwrap(), b"value");
+
+        // With xattrs disabled nothing is copied
+        let other = temp.path().join("other");
+        fs::write(&other, "x").unwrap();
+        let preserve = PreserveOptions {
+            xattrs: false,
+            ..PreserveOptions::all()
+        };
+        apply_metadata(&src, &other, &meta, &preserve, &mut warnings).unwrap();
+        assert!(get_xattr(&other, &name).is_err());
+    }
+
+    #[test]
+    fn test_clamp_mtime() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let dst = temp.path().join("dst");
+        fs::write(&src, "x").unwrap();
+        fs::write(&dst, "x").unwrap();
+        let epoch = 1_700_000_000;
+
+        let meta = fs::symlink_metadata(&src).unwrap();
+        let preserve = PreserveOptions {
+            clamp_mtime: Some(epoch),
+            ..PreserveOptions::all()
+        };
+        apply_metadata(&src, &dst, &meta, &preserve, &mut Vec::new()).unwrap();
+        assert_eq!(fs::metadata(&dst).unwrap().mtime(), epoch);
+
+        // Older times are left alone
+        let old = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000);
+        fs::File::options()
+            .write(true)
+            .open(&dst)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        clamp_tree_mtimes(temp.path(), epoch).unwrap();
+        assert_eq!(fs::metadata(&dst).unwrap().mtime(), 1_000);
+        assert_eq!(fs::metadata(&src).unwrap().mtime(), epoch);
+        assert_eq!(fs::metadata(temp.path()).unwrap().mtime(), epoch);
+    }
+}
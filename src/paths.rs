@@ -1,6 +1,12 @@
 //! Library and binary path searching.
 
-use std::path::{Path, PathBuf};
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Symlink hops after which resolution gives up, matching Linux's ELOOP limit.
+const MAX_SYMLINK_HOPS: usize = 40;
 
 /// Find a library in standard paths within a rootfs.
 ///
@@ -66,6 +72,63 @@ pub fn find_sbin_binary(source_root: &Path, binary: &str) -> Option<PathBuf> {
         .find(|p| p.symlink_metadata().is_ok())
 }
 
+/// Resolve `path` inside `root` as if `root` were `/`, following symlinks.
+///
+/// Absolute symlink targets are taken relative to `root` and `..` never
+/// climbs above it, the way the kernel resolves paths inside a chroot. Path
+/// components that don't exist are kept as they are, so the result can be
+/// checked with `exists()` to find dangling links. `path` may be absolute
+/// (an image path such as `/usr/lib/libc.so.6`) or relative to `root`.
+///
+/// # Errors
+///
+/// Fails on symlink loops and unreadable symlinks.
+pub fn resolve_in_root(root: &Path, path: &Path) -> Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+    let mut pending: VecDeque<PathBuf> = path
+        .components()
+        .map(|c| PathBuf::from(c.as_os_str()))
+        .collect();
+    let mut hops = 0;
+
+    while let Some(component) = pending.pop_front() {
+        match component.components().next() {
+            Some(Component::Normal(name)) => {
+                let candidate = resolved.join(name);
+                let host_path = root.join(&candidate);
+                let is_symlink = fs::symlink_metadata(&host_path)
+                    .map(|m| m.file_type().is_symlink())
+                    .unwrap_or(false);
+                if !is_symlink {
+                    resolved = candidate;
+                    continue;
+                }
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    bail!(
+                        "Too many levels of symbolic links resolving {} in {}",
+                        path.display(),
+                        root.display()
+                    );
+                }
+                let target = fs::read_link(&host_path)?;
+                if target.is_absolute() {
+                    resolved = PathBuf::new();
+                }
+                for c in target.components().rev() {
+                    pending.push_front(PathBuf::from(c.as_os_str()));
+                }
+            }
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            _ => {} // RootDir and CurDir
+        }
+    }
+
+    Ok(root.join(resolved))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,4 +208,23 @@ mod tests {
         assert!(found.is_some());
         assert_eq!(found.unwrap(), usr_bin_path);
     }
+
+    #[test]
+    fn test_resolve_in_root() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("usr/lib")).unwrap();
+        fs::write(root.join("usr/lib/libc.so.6"), "").unwrap();
+        std::os::unix::fs::symlink("usr/lib", root.join("lib")).unwrap();
+        std::os::unix::fs::symlink("/lib/libc.so.6", root.join("usr/lib/libc.so")).unwrap();
+        std::os::unix::fs::symlink("../../../../etc", root.join("usr/lib/escape")).unwrap();
+        std::os::unix::fs::symlink("loop", root.join("loop")).unwrap();
+
+        let resolved = resolve_in_root(root, Path::new("/lib/libc.so")).unwrap();
+        assert_eq!(resolved, root.join("usr/lib/libc.so.6"));
+        // ".." stops at the root instead of reaching the host
+        let resolved = resolve_in_root(root, Path::new("usr/lib/escape/passwd")).unwrap();
+        assert_eq!(resolved, root.join("etc/passwd"));
+        assert!(resolve_in_root(root, Path::new("/loop")).is_err());
+    }
 }
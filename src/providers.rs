@@ -0,0 +1,246 @@
+//! Index of which shared objects in a root export which symbols.
+
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::AnalysisCache;
+use crate::closure::{analyze_misses, read_each, ClosureOptions};
+use crate::elf::{read_elf_header, ET_DYN};
+use crate::paths::resolve_in_root;
+use crate::symbols::SymbolBinding;
+
+/// Options for [`SymbolIndex::build_with_options`].
+#[derive(Debug, Clone)]
+pub struct SymbolIndexOptions {
+    /// Directories to scan recursively, relative to the source root.
+    /// Directories that resolve to the same place (such as `lib64` and
+    /// `usr/lib64` on a merged-usr root) are scanned once.
+    pub dirs: Vec<PathBuf>,
+    /// How objects are analyzed (backend and worker threads).
+    pub analysis: ClosureOptions,
+}
+
+impl Default for SymbolIndexOptions {
+    fn default() -> Self {
+        Self {
+            dirs: ["usr/lib64", "lib64", "usr/lib", "lib"]
+                .map(PathBuf::from)
+                .to_vec(),
+            analysis: ClosureOptions::default(),
+        }
+    }
+}
+
+/// A shared object exporting a symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolProvider {
+    /// Host path of the object.
+    pub path: PathBuf,
+    pub soname: Option<String>,
+    /// Version the symbol is exported at, e.g. `GLIBC_2.34`.
+    pub version: Option<String>,
+    /// Exported at a non-default version, for old binaries only.
+    pub hidden: bool,
+}
+
+/// Exported dynamic symbols of every shared object under a root.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    /// Providers per mangled symbol name, ordered by path.
+    pub providers: BTreeMap<String, Vec<SymbolProvider>>,
+    /// Number of shared objects indexed.
+    pub objects: usize,
+    /// Shared objects that failed to analyze, with the error; they are not indexed.
+    pub failed: BTreeMap<PathBuf, String>,
+}
+
+impl SymbolIndex {
+    /// Index the standard library directories of `source_root`.
+    ///
+    /// Shorthand for [`build_with_options`](Self::build_with_options) with a
+    /// fresh cache and default options.
+    pub fn build(source_root: &Path) -> Result<Self> {
+        Self::build_with_options(
+            source_root,
+            &mut AnalysisCache::new(),
+            &SymbolIndexOptions::default(),
+        )
+    }
+
+    /// Index every shared object (ELF `ET_DYN` file named `*.so*`) under
+    /// `options.dirs` of `source_root`.
+    ///
+    /// Only regular files are read, so a library reached through several
+    /// symlinks is listed once. Sonames come from the same analysis as NEEDED
+    /// entries and are stored in `cache`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no backend is available or the backend can't read symbol
+    /// tables. Objects that fail to analyze are listed in
+    /// [`failed`](Self::failed) and left out.
+    pub fn build_with_options(
+        source_root: &Path,
+        cache: &mut AnalysisCache,
+        options: &SymbolIndexOptions,
+    ) -> Result<Self> {
+        let backend = options.analysis.backend()?;
+
+        let mut objects = Vec::new();
+        let mut scanned = HashSet::new();
+        for dir in &options.dirs {
+            let dir = resolve_in_root(source_root, dir)?;
+            if dir.is_dir() && scanned.insert(dir.clone()) {
+                collect_shared_objects(&dir, &mut objects)?;
+            }
+        }
+        objects.sort();
+        objects.dedup();
+
+        let failed: BTreeMap<PathBuf, String> =
+            analyze_misses(backend, &objects, cache, options.analysis.jobs)
+                .into_iter()
+                .map(|(path, e)| (path, format!("{:#}", e)))
+                .collect();
+        objects.retain(|path| !failed.contains_key(path));
+        let tables = read_each(&objects, options.analysis.jobs, |path| {
+            backend.symbols(path)
+        })?;
+
+        let mut index = Self {
+            providers: BTreeMap::new(),
+            objects: objects.len(),
+            failed,
+        };
+        for (path, table) in objects.iter().zip(tables) {
+            let soname = cache.get(path).and_then(|facts| facts.soname.clone());
+            for sym in table.symbols {
+                // Version-name markers (`GLIBC_2.34@@GLIBC_2.34`) aren't real exports
+                if !sym.defined
+                    || sym.binding == SymbolBinding::Local
+                    || sym.version.as_deref() == Some(sym.name.as_str())
+                {
+                    continue;
+                }
+                index
+                    .providers
+                    .entry(sym.name)
+                    .or_default()
+                    .push(SymbolProvider {
+                        path: path.clone(),
+                        soname: soname.clone(),
+                        version: sym.version,
+                        hidden: sym.hidden,
+                    });
+            }
+        }
+        Ok(index)
+    }
+
+    /// Every object exporting `name`, optionally only at `version`.
+    ///
+    /// `name` is the mangled name as it appears in `.dynsym`.
+    pub fn find(&self, name: &str, version: Option<&str>) -> Vec<&SymbolProvider> {
+        self.providers
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|provider| version.is_none_or(|v| provider.version.as_deref() == Some(v)))
+            .collect()
+    }
+
+    /// Mangled names whose demangled form contains `needle`, e.g.
+    /// `std::string::append`, in sorted order.
+    pub fn search_demangled(&self, needle: &str) -> Vec<&str> {
+        self.providers
+            .keys()
+            .filter(|name| demangle(name).contains(needle))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Demangle a C++ or Rust symbol name for display.
+///
+/// Rust names are shown without their hash suffix. Names that aren't mangled
+/// (plain C symbols) are returned unchanged.
+pub fn demangle(name: &str) -> Cow<'_, str> {
+    if let Ok(symbol) = rustc_demangle::try_demangle(name) {
+        return Cow::Owned(format!("{:#}", symbol));
+    }
+    if name.starts_with("_Z") {
+        if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+            if let Ok(demangled) = symbol.demangle(&Default::default()) {
+                return Cow::Owned(demangled);
+            }
+        }
+    }
+    Cow::Borrowed(name)
+}
+
+/// Regular files under `dir` that look like shared objects.
+fn collect_shared_objects(dir: &Path, objects: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("Failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            collect_shared_objects(&path, objects)?;
+        } else if file_type.is_file() && entry.file_name().to_string_lossy().contains(".so") {
+            if let Ok(Some(header)) = read_elf_header(&path) {
+                if header.elf_type == ET_DYN {
+                    objects.push(path);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(demangle("getenv"), "getenv");
+        assert_eq!(
+            demangle("_ZNSt8ios_base4InitC1Ev"),
+            "std::ios_base::Init::Init()"
+        );
+        assert_eq!(
+            demangle("_ZN4core3fmt5write17h0123456789abcdefE"),
+            "core::fmt::write"
+        );
+    }
+
+    #[test]
+    fn test_find_filters_by_version() {
+        let provider = |path: &str, version: &str, hidden| SymbolProvider {
+            path: PathBuf::from(path),
+            soname: Some("libc.so.6".to_string()),
+            version: Some(version.to_string()),
+            hidden,
+        };
+        let mut index = SymbolIndex::default();
+        index.providers.insert(
+            "memcpy".to_string(),
+            vec![
+                provider("/usr/lib64/libc.so.6", "GLIBC_2.2.5", true),
+                provider("/usr/lib64/libc.so.6", "GLIBC_2.14", false),
+            ],
+        );
+
+        assert_eq!(index.find("memcpy", None).len(), 2);
+        let found = index.find("memcpy", Some("GLIBC_2.14"));
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].hidden);
+        assert!(index.find("memcpy", Some("GLIBC_2.99")).is_empty());
+        assert!(index.find("memmove", None).is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+//! Hygiene lint for DT_RPATH and DT_RUNPATH entries.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::audit::{
+    expand_search_dir, find_elf_files, image_origin, ld_so_conf_dirs, trusted_dirs,
+};
+use crate::cache::AnalysisCache;
+use crate::closure::{analyze_misses, ClosureOptions};
+use crate::paths::resolve_in_root;
+
+/// Which dynamic tag an entry came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchPathTag {
+    Rpath,
+    Runpath,
+}
+
+impl fmt::Display for SearchPathTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rpath => "RPATH",
+            Self::Runpath => "RUNPATH",
+        })
+    }
+}
+
+/// What is wrong with a search path entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpathIssue {
+    /// Points into a build tree, e.g. `/home/builder/src/.libs`.
+    LeakedBuildPath,
+    /// The directory doesn't exist in the root.
+    Nonexistent,
+    /// The directory is world-writable, so anyone can plant a library there.
+    WorldWritable,
+    /// A relative path not anchored at `$ORIGIN`, resolved against the
+    /// process's working directory.
+    Relative,
+    /// `$ORIGIN` in a setuid or setgid file.
+    OriginOnSetuid,
+    /// A directory the loader searches anyway (trusted or from ld.so.conf).
+    DefaultDirectory,
+    /// The same directory appears earlier in the list.
+    Duplicate,
+}
+
+impl fmt::Display for RpathIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LeakedBuildPath => "leaked build path",
+            Self::Nonexistent => "directory does not exist",
+            Self::WorldWritable => "directory is world-writable",
+            Self::Relative => "relative path without $ORIGIN",
+            Self::OriginOnSetuid => "$ORIGIN in a setuid/setgid file",
+            Self::DefaultDirectory => "redundant default directory",
+            Self::Duplicate => "duplicate entry",
+        })
+    }
+}
+
+/// One problem with one search path entry of one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpathFinding {
+    /// Host path of the ELF file.
+    pub path: PathBuf,
+    pub tag: SearchPathTag,
+    /// The entry as written in the file.
+    pub entry: String,
+    pub issue: RpathIssue,
+}
+
+impl fmt::Display for RpathFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} entry {:?}: {}",
+            self.path.display(),
+            self.tag,
+            self.entry,
+            self.issue
+        )
+    }
+}
+
+/// Options for [`lint_rpaths_with_options`].
+#[derive(Debug, Clone)]
+pub struct RpathLintOptions {
+    /// Image path prefixes that only exist on build machines. Entries
+    /// under the host path of the root being linted, or containing a
+    /// libtool `.libs` component, are always treated as leaked.
+    pub build_path_prefixes: Vec<PathBuf>,
+    /// How ELF files are analyzed (backend and worker threads).
+    pub analysis: ClosureOptions,
+}
+
+impl Default for RpathLintOptions {
+    fn default() -> Self {
+        Self {
+            build_path_prefixes: ["/home", "/root", "/tmp", "/var/tmp", "/build", "/builddir"]
+                .map(PathBuf::from)
+                .to_vec(),
+            analysis: ClosureOptions::default(),
+        }
+    }
+}
+
+/// Outcome of [`lint_rpaths`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpathReport {
+    /// Loadable ELF files examined.
+    pub elf_files: usize,
+    /// Problems found, ordered by file, in entry order within a file.
+    pub findings: Vec<RpathFinding>,
+    /// Files that failed to analyze, with the error; they are not linted.
+    pub failed: BTreeMap<PathBuf, String>,
+}
+
+impl RpathReport {
+    /// Returns `true` if nothing was found and every file was linted.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Lint the RPATH and RUNPATH of every ELF file under `dest_root`.
+///
+/// Shorthand for [`lint_rpaths_with_options`] with a fresh cache and default options.
+pub fn lint_rpaths(dest_root: &Path) -> Result<RpathReport> {
+    lint_rpaths_with_options(
+        dest_root,
+        &mut AnalysisCache::new(),
+        &RpathLintOptions::default(),
+    )
+}
+
+/// Lint the RPATH and RUNPATH of every ELF file under `dest_root`.
+///
+/// Entries are checked as the file will see them in the root: `$ORIGIN`
+/// and `$LIB` are expanded against the file's real location, and
+/// directories are resolved inside `dest_root`. Entries using `$PLATFORM`
+/// are not checked for existence. One entry can have several findings.
+///
+/// # Errors
+///
+/// Fails if the tree can't be walked or no backend is available. Files that
+/// fail to analyze are listed in [`RpathReport::failed`].
+pub fn lint_rpaths_with_options(
+    dest_root: &Path,
+    cache: &mut AnalysisCache,
+    options: &RpathLintOptions,
+) -> Result<RpathReport> {
+    let backend = options.analysis.backend()?;
+
+    let elves = find_elf_files(dest_root)?;
+    let failed = analyze_misses(backend, elves.keys(), cache, options.analysis.jobs)
+        .into_iter()
+        .map(|(path, e)| (path, format!("{:#}", e)))
+        .collect();
+
+    let conf_dirs = ld_so_conf_dirs(dest_root)?;
+    let mut report = RpathReport {
+        elf_files: elves.len(),
+        findings: Vec::new(),
+        failed,
+    };
+    for (path, header) in &elves {
+        let Some(facts) = cache.get(path) else {
+            continue; // Analysis failed, already reported
+        };
+        if facts.rpath.is_empty() && facts.runpath.is_empty() {
+            continue;
+        }
+        let entries = facts
+            .rpath
+            .iter()
+            .map(|entry| (SearchPathTag::Rpath, entry))
+            .chain(
+                facts
+                    .runpath
+                    .iter()
+                    .map(|entry| (SearchPathTag::Runpath, entry)),
+            );
+
+        let origin = image_origin(dest_root, path)?;
+        let setuid = fs::symlink_metadata(path)?.mode() & 0o6000 != 0;
+        let defaults: HashSet<PathBuf> = trusted_dirs(header.class)
+            .iter()
+            .map(PathBuf::from)
+            .chain(conf_dirs.iter().cloned())
+            .collect();
+        let mut seen = HashSet::new();
+
+        for (tag, entry) in entries {
+            let mut issues = Vec::new();
+            let uses_origin = entry.contains("$ORIGIN") || entry.contains("${ORIGIN}");
+            let expanded = expand_search_dir(entry, &origin, header.class);
+            let normalized = normalize(&expanded);
+
+            if !uses_origin && is_leaked(entry, dest_root, options) {
+                issues.push(RpathIssue::LeakedBuildPath);
+            }
+            let relative = !entry.starts_with('/') && !uses_origin;
+            if relative {
+                issues.push(RpathIssue::Relative);
+            } else if !entry.contains("PLATFORM") {
+                let resolved = resolve_in_root(dest_root, &normalized)?;
+                match fs::metadata(&resolved) {
+                    Ok(meta) if meta.is_dir() => {
+                        if meta.mode() & 0o002 != 0 {
+                            issues.push(RpathIssue::WorldWritable);
+                        }
+                    }
+                    _ => issues.push(RpathIssue::Nonexistent),
+                }
+            }
+            if uses_origin && setuid {
+                issues.push(RpathIssue::OriginOnSetuid);
+            }
+            // A relative entry depends on the working directory, so it can't
+            // be compared against other directories
+            if !relative {
+                if !seen.insert(normalized.clone()) {
+                    issues.push(RpathIssue::Duplicate);
+                } else if defaults.contains(&normalized) {
+                    issues.push(RpathIssue::DefaultDirectory);
+                }
+            }
+
+            report
+                .findings
+                .extend(issues.into_iter().map(|issue| RpathFinding {
+                    path: path.clone(),
+                    tag,
+                    entry: entry.clone(),
+                    issue,
+                }));
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns `true` if `entry` points somewhere only a build machine has.
+fn is_leaked(entry: &str, dest_root: &Path, options: &RpathLintOptions) -> bool {
+    let path = Path::new(entry);
+    path.starts_with(dest_root)
+        || path.components().any(|c| c.as_os_str() == ".libs")
+        || options
+            .build_path_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+}
+
+/// Lexically fold `.` and `..` in an absolute image path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => {}
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::ElfFacts;
+    use crate::elf::ET_EXEC;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    /// A minimal ELF header, enough for `find_elf_files` to pick the file up.
+    fn write_elf(path: &Path) {
+        let mut bytes = vec![0u8; 64];
+        bytes[..4].copy_from_slice(b"\x7fELF");
+        bytes[4] = 2;
+        bytes[5] = 1;
+        bytes[16] = ET_EXEC as u8;
+        bytes[18] = 62;
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_lint_rpaths() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let tool = root.join("usr/bin/tool");
+        let helper = root.join("usr/bin/helper");
+        write_elf(&tool);
+        write_elf(&helper);
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o4755)).unwrap();
+        fs::create_dir_all(root.join("usr/lib64/tool")).unwrap();
+        fs::create_dir_all(root.join("opt/shared")).unwrap();
+        fs::set_permissions(root.join("opt/shared"), fs::Permissions::from_mode(0o777)).unwrap();
+
+        let mut cache = AnalysisCache::new();
+        cache.insert(
+            tool.clone(),
+            ElfFacts {
+                runpath: [
+                    "/home/builder/src/.libs",
+                    "$ORIGIN/../lib64/tool",
+                    "/usr/lib64",
+                    "/usr/lib64/../lib64/tool",
+                    "lib",
+                    "/opt/shared",
+                    "/opt/gone",
+                ]
+                .map(String::from)
+                .to_vec(),
+                ..ElfFacts::default()
+            },
+        );
+        cache.insert(
+            helper.clone(),
+            ElfFacts {
+                rpath: vec!["$ORIGIN/../lib64/tool".to_string()],
+                ..ElfFacts::default()
+            },
+        );
+
+        let report =
+            lint_rpaths_with_options(root, &mut cache, &RpathLintOptions::default()).unwrap();
+        let found: Vec<(&Path, &str, RpathIssue)> = report
+            .findings
+            .iter()
+            .map(|f| (f.path.as_path(), f.entry.as_str(), f.issue))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    helper.as_path(),
+                    "$ORIGIN/../lib64/tool",
+                    RpathIssue::OriginOnSetuid
+                ),
+                (
+                    tool.as_path(),
+                    "/home/builder/src/.libs",
+                    RpathIssue::LeakedBuildPath
+                ),
+                (
+                    tool.as_path(),
+                    "/home/builder/src/.libs",
+                    RpathIssue::Nonexistent
+                ),
+                (tool.as_path(), "/usr/lib64", RpathIssue::DefaultDirectory),
+                (
+                    tool.as_path(),
+                    "/usr/lib64/../lib64/tool",
+                    RpathIssue::Duplicate
+                ),
+                (tool.as_path(), "lib", RpathIssue::Relative),
+                (tool.as_path(), "/opt/shared", RpathIssue::WorldWritable),
+                (tool.as_path(), "/opt/gone", RpathIssue::Nonexistent),
+            ]
+        );
+        assert_eq!(report.elf_files, 2);
+    }
+}
@@ -0,0 +1,380 @@
+//! Security audit of a destination tree against an allowlist.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use crate::elf::read_elf_header;
+use crate::filter::glob_match;
+use crate::metadata::get_xattr;
+
+/// A security-relevant property of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SecurityIssue {
+    /// The setuid bit is set.
+    Setuid,
+    /// The setgid bit is set.
+    Setgid,
+    /// The file has a `security.capability` xattr.
+    Capability,
+    /// A world-writable file, or a world-writable directory without the
+    /// sticky bit.
+    WorldWritable,
+    /// A block or character device outside `/dev`.
+    DeviceOutsideDev,
+    /// An ELF file not owned by root.
+    NonRootElf,
+}
+
+impl SecurityIssue {
+    const ALL: [Self; 6] = [
+        Self::Setuid,
+        Self::Setgid,
+        Self::Capability,
+        Self::WorldWritable,
+        Self::DeviceOutsideDev,
+        Self::NonRootElf,
+    ];
+
+    /// Keyword used for this issue in allowlist files.
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Self::Setuid => "setuid",
+            Self::Setgid => "setgid",
+            Self::Capability => "capability",
+            Self::WorldWritable => "world-writable",
+            Self::DeviceOutsideDev => "device",
+            Self::NonRootElf => "non-root-elf",
+        }
+    }
+}
+
+impl fmt::Display for SecurityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.keyword())
+    }
+}
+
+/// One security-relevant property of one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityFinding {
+    /// Path inside the image, e.g. `/usr/bin/su`.
+    pub path: PathBuf,
+    pub issue: SecurityIssue,
+    /// Permission bits, including setuid, setgid and sticky.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl fmt::Display for SecurityFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} (mode {:04o}, owner {}:{})",
+            self.path.display(),
+            self.issue,
+            self.mode,
+            self.uid,
+            self.gid
+        )
+    }
+}
+
+/// One allowlist entry: an issue and a glob over image paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowRule {
+    pub issue: SecurityIssue,
+    /// Absolute image path pattern; `*` and `?` stop at `/`, `**` doesn't.
+    pub pattern: String,
+}
+
+impl AllowRule {
+    /// Check whether this rule allows `finding`.
+    pub fn allows(&self, finding: &SecurityFinding) -> bool {
+        self.issue == finding.issue
+            && glob_match(
+                self.pattern.as_bytes(),
+                finding.path.to_string_lossy().as_bytes(),
+            )
+    }
+}
+
+/// Findings that are expected in an image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecurityAllowlist {
+    pub rules: Vec<AllowRule>,
+}
+
+impl SecurityAllowlist {
+    /// An empty allowlist, which allows nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse an allowlist, one `ISSUE PATTERN` entry per line.
+    ///
+    /// Lines look like `setuid /usr/bin/su` or `world-writable /tmp`; issues
+    /// are the [`SecurityIssue::keyword`]s. Blank lines and lines starting
+    /// with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Fails on an unknown issue or a pattern that isn't absolute.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, pattern) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let pattern = pattern.trim();
+            let Some(issue) = SecurityIssue::ALL
+                .into_iter()
+                .find(|issue| issue.keyword() == keyword)
+            else {
+                bail!(
+                    "Invalid allowlist entry on line {}: unknown issue {:?}",
+                    number + 1,
+                    keyword
+                );
+            };
+            if !pattern.starts_with('/') {
+                bail!(
+                    "Invalid allowlist entry on line {}: {:?} (expected 'ISSUE /PATH')",
+                    number + 1,
+                    line
+                );
+            }
+            rules.push(AllowRule {
+                issue,
+                pattern: pattern.to_string(),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Read and parse an allowlist file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read allowlist: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Failed to parse allowlist: {}", path.display()))
+    }
+
+    /// Append a rule.
+    pub fn allow(&mut self, issue: SecurityIssue, pattern: &str) -> &mut Self {
+        self.rules.push(AllowRule {
+            issue,
+            pattern: pattern.to_string(),
+        });
+        self
+    }
+}
+
+/// Outcome of [`audit_security`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecurityReport {
+    /// Findings the allowlist covers, ordered by path.
+    pub allowed: Vec<SecurityFinding>,
+    /// Findings the allowlist doesn't cover, ordered by path.
+    pub unexpected: Vec<SecurityFinding>,
+    /// Allowlist rules that matched nothing, usually left over after a
+    /// file lost its special bits or was removed.
+    pub unused_rules: Vec<AllowRule>,
+}
+
+impl SecurityReport {
+    /// Returns `true` if every finding is allowlisted.
+    pub fn passes(&self) -> bool {
+        self.unexpected.is_empty()
+    }
+
+    /// Every finding as allowlist text, to bootstrap or refresh an allowlist
+    /// after reviewing the report.
+    pub fn to_allowlist(&self) -> String {
+        let mut findings: Vec<&SecurityFinding> =
+            self.allowed.iter().chain(&self.unexpected).collect();
+        findings.sort_by(|a, b| (&a.path, a.issue).cmp(&(&b.path, b.issue)));
+        findings
+            .iter()
+            .map(|finding| format!("{} {}\n", finding.issue, finding.path.display()))
+            .collect()
+    }
+}
+
+/// Audit every entry under `dest_root` and compare against `allowlist`.
+///
+/// Reports setuid and setgid files, files with capabilities, world-writable
+/// files and non-sticky directories, device nodes outside `/dev` and ELF
+/// files not owned by uid 0. Symlinks are not followed and their own
+/// permissions are ignored.
+///
+/// # Errors
+///
+/// Fails if the tree can't be walked.
+pub fn audit_security(dest_root: &Path, allowlist: &SecurityAllowlist) -> Result<SecurityReport> {
+    let mut findings = Vec::new();
+    collect_findings(dest_root, Path::new("/"), &mut findings)?;
+    findings.sort_by(|a, b| (&a.path, a.issue).cmp(&(&b.path, b.issue)));
+
+    let mut used = vec![false; allowlist.rules.len()];
+    let mut report = SecurityReport::default();
+    for finding in findings {
+        match allowlist
+            .rules
+            .iter()
+            .position(|rule| rule.allows(&finding))
+        {
+            Some(index) => {
+                used[index] = true;
+                report.allowed.push(finding);
+            }
+            None => report.unexpected.push(finding),
+        }
+    }
+    report.unused_rules = allowlist
+        .rules
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| !used)
+        .map(|(rule, _)| rule.clone())
+        .collect();
+    Ok(report)
+}
+
+fn collect_findings(
+    dir: &Path,
+    image_dir: &Path,
+    findings: &mut Vec<SecurityFinding>,
+) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let image_path = image_dir.join(entry.file_name());
+        let meta = fs::symlink_metadata(&path)
+            .with_context(|| format!("Failed to read metadata: {}", path.display()))?;
+        let file_type = meta.file_type();
+        if file_type.is_symlink() {
+            continue;
+        }
+
+        let mode = meta.mode() & 0o7777;
+        let mut issues = Vec::new();
+        if mode & 0o4000 != 0 && !file_type.is_dir() {
+            issues.push(SecurityIssue::Setuid);
+        }
+        // setgid on a directory only makes new files inherit its group
+        if mode & 0o2000 != 0 && !file_type.is_dir() {
+            issues.push(SecurityIssue::Setgid);
+        }
+        if mode & 0o002 != 0 && !(file_type.is_dir() && mode & 0o1000 != 0) {
+            issues.push(SecurityIssue::WorldWritable);
+        }
+        if (file_type.is_block_device() || file_type.is_char_device())
+            && !image_path.starts_with("/dev")
+        {
+            issues.push(SecurityIssue::DeviceOutsideDev);
+        }
+        if file_type.is_file() {
+            if get_xattr(&path, c"security.capability").is_ok() {
+                issues.push(SecurityIssue::Capability);
+            }
+            if meta.uid() != 0 && matches!(read_elf_header(&path), Ok(Some(_))) {
+                issues.push(SecurityIssue::NonRootElf);
+            }
+        }
+        findings.extend(issues.into_iter().map(|issue| SecurityFinding {
+            path: image_path.clone(),
+            issue,
+            mode,
+            uid: meta.uid(),
+            gid: meta.gid(),
+        }));
+
+        if file_type.is_dir() {
+            collect_findings(&path, &image_path, findings)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use tempfile::TempDir;
+
+    fn write(path: &Path, contents: &[u8], mode: u32) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_parse_allowlist() {
+        let allowlist = SecurityAllowlist::parse(
+            "# expected\nsetuid /usr/bin/su\n\nworld-writable   /var/tmp/**\n",
+        )
+        .unwrap();
+        assert_eq!(allowlist.rules.len(), 2);
+        assert_eq!(allowlist.rules[1].issue, SecurityIssue::WorldWritable);
+        assert_eq!(allowlist.rules[1].pattern, "/var/tmp/**");
+
+        assert!(SecurityAllowlist::parse("sticky /tmp").is_err());
+        assert!(SecurityAllowlist::parse("setuid usr/bin/su").is_err());
+        assert!(SecurityAllowlist::parse("setuid").is_err());
+    }
+
+    #[test]
+    fn test_audit_security() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        write(&root.join("usr/bin/su"), b"#!/bin/sh\n", 0o4755);
+        write(&root.join("usr/bin/wall"), b"#!/bin/sh\n", 0o2755);
+        write(&root.join("etc/motd"), b"hi\n", 0o666);
+        write(&root.join("etc/hostname"), b"box\n", 0o644);
+        fs::create_dir_all(root.join("tmp")).unwrap();
+        fs::set_permissions(root.join("tmp"), fs::Permissions::from_mode(0o1777)).unwrap();
+        fs::create_dir_all(root.join("srv/drop")).unwrap();
+        fs::set_permissions(root.join("srv/drop"), fs::Permissions::from_mode(0o777)).unwrap();
+        symlink("/etc/hostname", root.join("etc/link")).unwrap();
+
+        let mut allowlist = SecurityAllowlist::new();
+        allowlist
+            .allow(SecurityIssue::Setuid, "/usr/bin/*")
+            .allow(SecurityIssue::Capability, "/usr/bin/ping");
+        let report = audit_security(root, &allowlist).unwrap();
+
+        let issues = |findings: &[SecurityFinding]| {
+            findings
+                .iter()
+                .map(|f| (f.path.to_string_lossy().into_owned(), f.issue))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            issues(&report.allowed),
+            [("/usr/bin/su".to_string(), SecurityIssue::Setuid)]
+        );
+        assert_eq!(
+            issues(&report.unexpected),
+            [
+                ("/etc/motd".to_string(), SecurityIssue::WorldWritable),
+                ("/srv/drop".to_string(), SecurityIssue::WorldWritable),
+                ("/usr/bin/wall".to_string(), SecurityIssue::Setgid),
+            ]
+        );
+        assert_eq!(report.unused_rules, allowlist.rules[1..]);
+        assert!(!report.passes());
+        assert_eq!(report.allowed[0].mode, 0o4755);
+
+        // Feeding the report back as an allowlist makes it pass
+        let refreshed = SecurityAllowlist::parse(&report.to_allowlist()).unwrap();
+        assert!(audit_security(root, &refreshed).unwrap().passes());
+    }
+}
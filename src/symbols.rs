@@ -0,0 +1,570 @@
+//! Dynamic symbol tables and a static unresolved-symbol check (`ldd -r`
+//! without running anything).
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::closure::{read_each, ClosureOptions, DependencyClosure};
+
+/// Binding of a dynamic symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolBinding {
+    Local,
+    Global,
+    Weak,
+    /// `STB_GNU_UNIQUE`, a global the loader keeps one copy of per process.
+    Unique,
+}
+
+/// One entry of `.dynsym`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DynamicSymbol {
+    pub name: String,
+    /// Version from `.gnu.version`, e.g. `GLIBC_2.34`.
+    pub version: Option<String>,
+    /// Defined at a non-default version (`name@VER` rather than `name@@VER`),
+    /// so only references asking for that exact version bind to it.
+    pub hidden: bool,
+    pub binding: SymbolBinding,
+    /// `false` for imports (section index `UND`).
+    pub defined: bool,
+}
+
+/// One version required from a library, from `.gnu.version_r`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionNeed {
+    /// The NEEDED library expected to define the version.
+    pub library: String,
+    pub version: String,
+    /// Weak requirements don't stop the object from loading.
+    pub weak: bool,
+}
+
+/// Symbol-level view of one ELF object.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    /// `.dynsym` entries in table order, without the null entry.
+    pub symbols: Vec<DynamicSymbol>,
+    /// Version names defined in `.gnu.version_d`, without the base (soname) entry.
+    pub version_definitions: Vec<String>,
+    /// Versions required from other objects, from `.gnu.version_r`.
+    pub version_needs: Vec<VersionNeed>,
+    /// The object has a `.gnu.version` section, so unversioned definitions
+    /// are at the base or global index rather than simply unversioned.
+    pub versioned: bool,
+}
+
+impl SymbolTable {
+    /// Returns `true` if this object defines `name` in a way a reference
+    /// for `version` (or an unversioned reference) binds to.
+    ///
+    /// Like the loader, a versioned reference also binds to an unversioned
+    /// definition: any definition of an object without `.gnu.version`, and
+    /// non-hidden base or global index definitions of one with it.
+    pub fn provides(&self, name: &str, version: Option<&str>) -> bool {
+        self.symbols.iter().any(|sym| {
+            sym.defined
+                && sym.binding != SymbolBinding::Local
+                && sym.name == name
+                && match (version, sym.version.as_deref()) {
+                    (Some(version), Some(defined)) => defined == version,
+                    (Some(_), None) => !self.versioned || !sym.hidden,
+                    (None, _) => !sym.hidden,
+                }
+        })
+    }
+}
+
+/// A symbol an object imports that nothing in its load order provides.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UnresolvedSymbol {
+    pub name: String,
+    /// Version the import asks for, if it is versioned.
+    pub version: Option<String>,
+}
+
+impl fmt::Display for UnresolvedSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{}", self.name, version),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// A `.gnu.version_r` requirement the named library doesn't define.
+///
+/// The loader refuses to start the object with "version `X' not found".
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MissingVersion {
+    pub library: String,
+    pub version: String,
+}
+
+/// Outcome of [`check_symbols`], keyed by the host path of each object.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolReport {
+    /// Number of objects whose symbols were checked.
+    pub objects: usize,
+    /// Undefined, non-weak imports with no provider, sorted.
+    pub unresolved: BTreeMap<PathBuf, Vec<UnresolvedSymbol>>,
+    /// Required versions missing from the library that should define them.
+    pub missing_versions: BTreeMap<PathBuf, Vec<MissingVersion>>,
+}
+
+impl SymbolReport {
+    /// Returns `true` if every import resolves.
+    pub fn is_clean(&self) -> bool {
+        self.unresolved.is_empty() && self.missing_versions.is_empty()
+    }
+}
+
+/// Check that every import of every object in `closure` resolves.
+///
+/// Shorthand for [`check_symbols_with_options`] with default options.
+pub fn check_symbols(closure: &DependencyClosure) -> Result<SymbolReport> {
+    check_symbols_with_options(closure, &ClosureOptions::default())
+}
+
+/// Check that every import of every object in `closure` resolves.
+///
+/// Each root is checked the way the dynamic loader would bind it: its load
+/// order is the root followed by its libraries in breadth-first NEEDED order,
+/// and every object in it must find each undefined, non-weak symbol there,
+/// at the version it asks for. Versioned imports may bind to hidden
+/// definitions of that version and to unversioned ones; unversioned imports
+/// only to default or unversioned ones. Each
+/// `.gnu.version_r` requirement must also be defined by the library it names.
+///
+/// Libraries that could not be found are skipped (their absence is
+/// [`DependencyClosure::missing`]), so imports they would have provided show
+/// up as unresolved.
+///
+/// # Errors
+///
+/// Fails if no backend is available, the backend can't read symbol tables,
+/// or any object fails to parse.
+pub fn check_symbols_with_options(
+    closure: &DependencyClosure,
+    options: &ClosureOptions,
+) -> Result<SymbolReport> {
+    let backend = options.backend()?;
+    let objects: Vec<PathBuf> = closure.needed.keys().cloned().collect();
+    let tables = read_each(&objects, options.jobs, |path| backend.symbols(path))?;
+    let tables = objects.into_iter().zip(tables).collect();
+    Ok(check_tables(closure, &tables))
+}
+
+fn check_tables(
+    closure: &DependencyClosure,
+    tables: &BTreeMap<PathBuf, SymbolTable>,
+) -> SymbolReport {
+    let mut unresolved: BTreeMap<PathBuf, BTreeSet<UnresolvedSymbol>> = BTreeMap::new();
+    let mut missing: BTreeMap<PathBuf, BTreeSet<MissingVersion>> = BTreeMap::new();
+    let mut checked = HashSet::new();
+
+    for root in closure.roots.keys() {
+        let scope = load_order(closure, root);
+        let scope_tables: Vec<&SymbolTable> =
+            scope.iter().filter_map(|path| tables.get(path)).collect();
+
+        for object in &scope {
+            let Some(table) = tables.get(object) else {
+                continue;
+            };
+            checked.insert(object.clone());
+
+            for sym in &table.symbols {
+                if sym.defined || sym.binding != SymbolBinding::Global {
+                    continue;
+                }
+                let version = sym.version.as_deref();
+                if !scope_tables.iter().any(|t| t.provides(&sym.name, version)) {
+                    unresolved
+                        .entry(object.clone())
+                        .or_default()
+                        .insert(UnresolvedSymbol {
+                            name: sym.name.clone(),
+                            version: sym.version.clone(),
+                        });
+                }
+            }
+
+            for need in table.version_needs.iter().filter(|need| !need.weak) {
+                let Some(Some(library)) = closure.libraries.get(&need.library) else {
+                    continue;
+                };
+                let Some(lib_table) = tables.get(library) else {
+                    continue;
+                };
+                if !lib_table.version_definitions.contains(&need.version) {
+                    missing
+                        .entry(object.clone())
+                        .or_default()
+                        .insert(MissingVersion {
+                            library: need.library.clone(),
+                            version: need.version.clone(),
+                        });
+                }
+            }
+        }
+    }
+
+    SymbolReport {
+        objects: checked.len(),
+        unresolved: unresolved
+            .into_iter()
+            .map(|(path, syms)| (path, syms.into_iter().collect()))
+            .collect(),
+        missing_versions: missing
+            .into_iter()
+            .map(|(path, versions)| (path, versions.into_iter().collect()))
+            .collect(),
+    }
+}
+
+/// `root` and its libraries in breadth-first NEEDED order, each once.
+fn load_order(closure: &DependencyClosure, root: &Path) -> Vec<PathBuf> {
+    let mut order = vec![root.to_path_buf()];
+    let mut seen: HashSet<PathBuf> = order.iter().cloned().collect();
+    let mut next = 0;
+    while next < order.len() {
+        let deps = closure
+            .needed
+            .get(&order[next])
+            .cloned()
+            .unwrap_or_default();
+        for lib_name in deps {
+            if let Some(Some(lib_path)) = closure.libraries.get(&lib_name) {
+                if seen.insert(lib_path.clone()) {
+                    order.push(lib_path.clone());
+                }
+            }
+        }
+        next += 1;
+    }
+    order
+}
+
+/// Parse readelf -W --dyn-syms -V output into a [`SymbolTable`].
+///
+/// ```text
+/// Symbol table '.dynsym' contains 127 entries:
+///    Num:    Value          Size Type    Bind   Vis      Ndx Name
+///      2: 0000000000000000     0 FUNC    GLOBAL DEFAULT  UND getenv@GLIBC_2.2.5 (3)
+///     19: 000000000007dce0   271 FUNC    WEAK   DEFAULT   16 fgetc@@GLIBC_2.2.5
+///
+/// Version definition section '.gnu.version_d' contains 39 entries:
+///   0x001c: Rev: 1  Flags: none  Index: 2  Cnt: 1  Name: GLIBC_2.2.5
+///
+/// Version needs section '.gnu.version_r' contains 2 entries:
+///   0x0020: Version: 1  File: libc.so.6  Cnt: 10
+///   0x0030:   Name: GLIBC_2.28  Flags: none  Version: 12
+/// ```
+///
+/// eu-readelf numbers its section headers (`Symbol table [ 6] '.dynsym'`),
+/// spells undefined symbols `UNDEF` and labels definitions `Version:`
+/// rather than `Rev:`; both layouts are accepted.
+pub fn parse_readelf_symbols(output: &str) -> SymbolTable {
+    #[derive(PartialEq)]
+    enum Section {
+        None,
+        Symbols,
+        Definitions,
+        Needs,
+    }
+
+    let mut table = SymbolTable::default();
+    let mut section = Section::None;
+    let mut needs_file: Option<String> = None;
+
+    for line in output.lines() {
+        if line.starts_with("Symbol table ") && line.contains("'.dynsym'") {
+            section = Section::Symbols;
+            continue;
+        } else if line.starts_with("Version symbols section") {
+            table.versioned = true;
+            section = Section::None;
+            continue;
+        } else if line.starts_with("Symbol table ") {
+            section = Section::None;
+            continue;
+        } else if line.starts_with("Version definition section") {
+            section = Section::Definitions;
+            continue;
+        } else if line.starts_with("Version needs section") {
+            section = Section::Needs;
+            continue;
+        } else if !line.is_empty() && !line.starts_with(' ') {
+            // Any other unindented line starts a section we don't parse
+            section = Section::None;
+            continue;
+        }
+
+        match section {
+            Section::Symbols => {
+                if let Some(sym) = parse_symbol_line(line) {
+                    table.symbols.push(sym);
+                }
+            }
+            Section::Definitions => {
+                // Parent lines carry no flags
+                if line.contains("Flags:") && !line.contains("Flags: BASE") {
+                    if let Some(name) = field(line, "Name:") {
+                        table.version_definitions.push(name.to_string());
+                    }
+                }
+            }
+            Section::Needs => {
+                if let Some(file) = field(line, "File:") {
+                    needs_file = Some(file.to_string());
+                } else if let (Some(name), Some(file)) = (field(line, "Name:"), &needs_file) {
+                    table.version_needs.push(VersionNeed {
+                        library: file.clone(),
+                        version: name.to_string(),
+                        weak: field(line, "Flags:").is_some_and(|flags| flags.contains("WEAK")),
+                    });
+                }
+            }
+            Section::None => {}
+        }
+    }
+
+    table
+}
+
+/// The whitespace-delimited value following `label` on a line.
+fn field<'a>(line: &'a str, label: &str) -> Option<&'a str> {
+    let start = line.find(label)? + label.len();
+    line[start..].split_whitespace().next()
+}
+
+/// Parse one `Num: Value Size Type Bind Vis Ndx Name` row.
+fn parse_symbol_line(line: &str) -> Option<DynamicSymbol> {
+    let mut fields = line.split_whitespace();
+    if !fields.next()?.ends_with(':') {
+        return None;
+    }
+    let fields: Vec<&str> = fields.collect();
+    let binding = match *fields.get(3)? {
+        "LOCAL" => SymbolBinding::Local,
+        "GLOBAL" => SymbolBinding::Global,
+        "WEAK" => SymbolBinding::Weak,
+        "UNIQUE" => SymbolBinding::Unique,
+        _ => return None,
+    };
+    // Vis may be followed by bracketed extras such as [VARIANT_PCS]
+    let mut rest = fields[5..].iter().filter(|f| !f.starts_with('['));
+    let ndx = *rest.next()?;
+    // The null entry has no name; a trailing "(3)" version index is ignored
+    let raw = *rest.next()?;
+    let defined = ndx != "UND" && ndx != "UNDEF";
+
+    let (name, version, hidden) = if let Some((name, version)) = raw.split_once("@@") {
+        (name, Some(version), false)
+    } else if let Some((name, version)) = raw.split_once('@') {
+        (name, Some(version), defined)
+    } else {
+        (raw, None, false)
+    };
+    // llvm-readelf prints the marker symbol of a version definition as
+    // `GLIBC_2.10@@GLIBC_2.10`; GNU readelf leaves its version off
+    let version = version.filter(|version| *version != name);
+    Some(DynamicSymbol {
+        name: name.to_string(),
+        version: version.map(str::to_string),
+        hidden,
+        binding,
+        defined,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBFOO: &str = r#"
+Symbol table '.dynsym' contains 6 entries:
+   Num:    Value          Size Type    Bind   Vis      Ndx Name
+     0: 0000000000000000     0 NOTYPE  LOCAL  DEFAULT  UND
+     1: 0000000000000000     0 FUNC    GLOBAL DEFAULT  UND getenv@GLIBC_2.2.5 (3)
+     2: 0000000000000000     0 NOTYPE  WEAK   DEFAULT  UND __gmon_start__
+     3: 0000000000001120    42 FUNC    GLOBAL DEFAULT   13 foo_open@@FOO_1.0
+     4: 0000000000001150    42 FUNC    GLOBAL DEFAULT   13 foo_old@FOO_0.9
+     5: 0000000000000000     0 OBJECT  GLOBAL DEFAULT  ABS FOO_1.0
+
+Version symbols section '.gnu.version' contains 6 entries:
+ Addr: 0x0000000000000500  Offset: 0x00000500  Link: 3 (.dynsym)
+  000:   0 (*local*)       3 (GLIBC_2.2.5)   1 (*global*)      2 (FOO_1.0)
+
+Version definition section '.gnu.version_d' contains 3 entries:
+ Addr: 0x0000000000000510  Offset: 0x00000510  Link: 4 (.dynstr)
+  000000: Rev: 1  Flags: BASE  Index: 1  Cnt: 1  Name: libfoo.so.1
+  0x001c: Rev: 1  Flags: none  Index: 2  Cnt: 1  Name: FOO_1.0
+  0x0038: Rev: 1  Flags: none  Index: 4  Cnt: 2  Name: FOO_0.9
+  0x0054: Parent 1: FOO_1.0
+
+Version needs section '.gnu.version_r' contains 1 entry:
+ Addr: 0x0000000000000580  Offset: 0x00000580  Link: 4 (.dynstr)
+  000000: Version: 1  File: libc.so.6  Cnt: 2
+  0x0010:   Name: GLIBC_2.2.5  Flags: none  Version: 3
+  0x0020:   Name: GLIBC_2.99  Flags: WEAK  Version: 5
+"#;
+
+    fn sym(name: &str, version: Option<&str>, defined: bool) -> DynamicSymbol {
+        DynamicSymbol {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+            hidden: false,
+            binding: SymbolBinding::Global,
+            defined,
+        }
+    }
+
+    #[test]
+    fn test_parse_readelf_symbols() {
+        let table = parse_readelf_symbols(LIBFOO);
+        assert_eq!(table.symbols.len(), 5);
+        assert_eq!(table.symbols[0], sym("getenv", Some("GLIBC_2.2.5"), false));
+        assert_eq!(table.symbols[1].binding, SymbolBinding::Weak);
+        assert_eq!(table.symbols[2], sym("foo_open", Some("FOO_1.0"), true));
+        assert!(table.symbols[3].hidden);
+        assert_eq!(table.version_definitions, ["FOO_1.0", "FOO_0.9"]);
+        assert_eq!(table.version_needs.len(), 2);
+        assert_eq!(table.version_needs[0].library, "libc.so.6");
+        assert!(table.version_needs[1].weak);
+
+        assert!(table.provides("foo_open", None));
+        assert!(table.provides("foo_old", Some("FOO_0.9")));
+        assert!(!table.provides("foo_old", None));
+        assert!(!table.provides("foo_open", Some("FOO_2.0")));
+        assert!(table.versioned);
+    }
+
+    #[test]
+    fn test_parse_eu_readelf_symbols() {
+        let output = r#"
+Symbol table [ 5] '.dynsym' contains 4 entries:
+ 1 local symbol  String table: [ 6] '.dynstr'
+  Num:            Value   Size Type    Bind   Vis          Ndx Name
+    0: 0000000000000000      0 NOTYPE  LOCAL  DEFAULT    UNDEF 
+    1: 0000000000000000      0 FUNC    GLOBAL DEFAULT    UNDEF getenv@GLIBC_2.2.5 (3)
+    2: 0000000000001120     42 FUNC    GLOBAL DEFAULT       14 foo_open@@FOO_1.0
+    3: 0000000000000000      0 OBJECT  GLOBAL DEFAULT      ABS FOO_1.0@@FOO_1.0
+
+Version symbols section [ 7] '.gnu.version' contains 4 entries:
+ Addr: 0x0000000000000480  Offset: 0x000480  Link to section: [ 5] '.dynsym'
+   0:   0 *local*            3 GLIBC_2.2.5(libc.so.6)   2 FOO_1.0              2 FOO_1.0
+
+Version definition section [ 8] '.gnu.version_d' contains 2 entries:
+ Addr: 0x0000000000000490  Offset: 0x000490  Link to section: [ 6] '.dynstr'
+  000000: Version: 1  Flags: BASE  Index: 1  Cnt: 1  Name: libfoo.so.1
+  0x001c: Version: 1  Flags: none  Index: 2  Cnt: 1  Name: FOO_1.0
+
+Version needs section [ 9] '.gnu.version_r' contains 1 entry:
+ Addr: 0x00000000000004c8  Offset: 0x0004c8  Link to section: [ 6] '.dynstr'
+  000000: Version: 1  File: libc.so.6  Cnt: 1
+  0x0010: Name: GLIBC_2.2.5  Flags: WEAK  Version: 3
+"#;
+        let table = parse_readelf_symbols(output);
+        assert_eq!(table.symbols.len(), 3);
+        assert_eq!(table.symbols[0], sym("getenv", Some("GLIBC_2.2.5"), false));
+        assert_eq!(table.symbols[1], sym("foo_open", Some("FOO_1.0"), true));
+        assert_eq!(table.symbols[2].version, None);
+        assert_eq!(table.version_definitions, ["FOO_1.0"]);
+        assert_eq!(table.version_needs.len(), 1);
+        assert!(table.version_needs[0].weak);
+        assert!(table.versioned);
+    }
+
+    #[test]
+    fn test_versioned_reference_binds_to_unversioned_definition() {
+        // An object built without symbol versioning
+        let plain = SymbolTable {
+            symbols: vec![sym("foo_open", None, true)],
+            ..SymbolTable::default()
+        };
+        assert!(plain.provides("foo_open", Some("FOO_1.0")));
+        assert!(plain.provides("foo_open", None));
+
+        // A global index definition in a versioned object
+        let mut versioned = SymbolTable {
+            versioned: true,
+            ..plain
+        };
+        assert!(versioned.provides("foo_open", Some("FOO_1.0")));
+        versioned.symbols[0].hidden = true;
+        assert!(!versioned.provides("foo_open", Some("FOO_1.0")));
+    }
+
+    #[test]
+    fn test_check_tables_follows_load_order() {
+        let bin = PathBuf::from("/usr/bin/tool");
+        let foo = PathBuf::from("/usr/lib64/libfoo.so.1");
+        let libc = PathBuf::from("/usr/lib64/libc.so.6");
+        let mut closure = DependencyClosure::default();
+        closure
+            .needed
+            .insert(bin.clone(), vec!["libfoo.so.1".into()]);
+        closure.needed.insert(foo.clone(), vec!["libc.so.6".into()]);
+        closure.needed.insert(libc.clone(), Vec::new());
+        closure
+            .libraries
+            .insert("libfoo.so.1".into(), Some(foo.clone()));
+        closure
+            .libraries
+            .insert("libc.so.6".into(), Some(libc.clone()));
+        closure.roots.insert(bin.clone(), BTreeSet::new());
+
+        let mut tables = BTreeMap::new();
+        tables.insert(
+            bin.clone(),
+            SymbolTable {
+                symbols: vec![
+                    sym("foo_open", Some("FOO_1.0"), false),
+                    // libc isn't NEEDED by the binary but is in its load order
+                    sym("getenv", Some("GLIBC_2.2.5"), false),
+                    sym("foo_close", None, false),
+                ],
+                ..SymbolTable::default()
+            },
+        );
+        tables.insert(foo.clone(), parse_readelf_symbols(LIBFOO));
+        tables.insert(
+            libc.clone(),
+            SymbolTable {
+                symbols: vec![sym("getenv", Some("GLIBC_2.2.5"), true)],
+                version_definitions: vec!["GLIBC_2.2.5".into()],
+                ..SymbolTable::default()
+            },
+        );
+
+        let report = check_tables(&closure, &tables);
+        assert_eq!(report.objects, 3);
+        assert_eq!(
+            report.unresolved[&bin],
+            vec![UnresolvedSymbol {
+                name: "foo_close".into(),
+                version: None,
+            }]
+        );
+        assert_eq!(report.unresolved.len(), 1);
+        // The weak GLIBC_2.99 requirement is tolerated
+        assert!(report.missing_versions.is_empty());
+
+        // Version skew: libc in the image predates what libfoo was built against
+        tables.get_mut(&libc).unwrap().version_definitions.clear();
+        tables.get_mut(&libc).unwrap().symbols[0].version = Some("GLIBC_2.1".into());
+        let report = check_tables(&closure, &tables);
+        assert_eq!(report.unresolved[&foo][0].to_string(), "getenv@GLIBC_2.2.5");
+        assert_eq!(
+            report.missing_versions[&foo],
+            vec![MissingVersion {
+                library: "libc.so.6".into(),
+                version: "GLIBC_2.2.5".into(),
+            }]
+        );
+    }
+}
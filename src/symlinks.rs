@@ -0,0 +1,387 @@
+//! Validation and rewriting of symlinks in a destination tree.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::copy::{copy_dir_with_options, copy_file, CopyOptions, CopyReport};
+use crate::paths::resolve_in_root;
+
+/// How symlink targets are written back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkStyle {
+    /// Leave targets as they are; only validate.
+    #[default]
+    Keep,
+    /// Rewrite every target relative to the link's directory, so the tree
+    /// works wherever it is mounted.
+    Relative,
+    /// Rewrite every target as an absolute path inside the image.
+    Absolute,
+}
+
+/// Options for [`validate_symlinks`].
+#[derive(Debug, Clone, Default)]
+pub struct SymlinkOptions {
+    /// How to rewrite link targets.
+    pub style: SymlinkStyle,
+    /// Where to pull missing link targets from. `None` only reports them.
+    pub source_root: Option<PathBuf>,
+    /// How pulled-in targets are copied.
+    pub copy: CopyOptions,
+}
+
+/// A symlink whose target was changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewrittenSymlink {
+    /// Host path of the link.
+    pub path: PathBuf,
+    /// Target before the rewrite, as it was written.
+    pub old_target: PathBuf,
+    /// Target the link points to now.
+    pub new_target: PathBuf,
+}
+
+/// A symlink whose target does not exist in the destination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingSymlink {
+    /// Host path of the link.
+    pub path: PathBuf,
+    /// The link's target after any rewrite.
+    pub target: PathBuf,
+}
+
+/// Outcome of [`validate_symlinks`]. Paths are host paths under the root.
+#[derive(Debug, Clone, Default)]
+pub struct SymlinkReport {
+    /// Number of symlinks examined.
+    pub checked: usize,
+    /// Links rewritten by the style policy.
+    pub rewritten: Vec<RewrittenSymlink>,
+    /// Links still dangling after the pass.
+    pub dangling: Vec<DanglingSymlink>,
+    /// Links whose target leaves the image: relative targets climbing above
+    /// the root, or absolute targets spelled with the host path of the
+    /// source or destination root.
+    pub escaping: Vec<PathBuf>,
+    /// Targets copied in from the source root.
+    pub pulled: Vec<PathBuf>,
+    /// What copying the pulled targets did.
+    pub copy: CopyReport,
+}
+
+/// Resolve every symlink under `dest_root` inside that root and fix what can be fixed.
+///
+/// Links are resolved the way the image will see them once booted, with
+/// absolute targets relative to `dest_root`. Targets are rewritten according
+/// to `options.style`; leaked host paths are always turned back into image
+/// paths when rewriting. Missing targets are copied from
+/// `options.source_root` when given, otherwise reported as dangling; the
+/// symlinks inside a pulled-in directory are validated in turn.
+///
+/// # Errors
+///
+/// Fails if the tree can't be walked or a link can't be rewritten or its
+/// target copied.
+pub fn validate_symlinks(dest_root: &Path, options: &SymlinkOptions) -> Result<SymlinkReport> {
+    let mut report = SymlinkReport::default();
+    let mut links = Vec::new();
+    collect_symlinks(dest_root, &mut links)?;
+
+    // Pulled-in directories append their own links while this runs
+    let mut next = 0;
+    while let Some(link) = links.get(next).cloned() {
+        next += 1;
+        report.checked += 1;
+        let rel = link.strip_prefix(dest_root).unwrap_or(&link);
+        let target = fs::read_link(&link)?;
+
+        // The directory the kernel will resolve a relative target from
+        let parent = rel.parent().unwrap_or(Path::new(""));
+        let real_parent = resolve_in_root(dest_root, parent)?;
+        let parent_in_image = image_path(dest_root, &real_parent);
+
+        let (image_target, escapes) = image_target(&target, &parent_in_image, dest_root, options);
+        if escapes {
+            report.escaping.push(link.clone());
+        }
+
+        let new_target = match options.style {
+            SymlinkStyle::Keep => target.clone(),
+            SymlinkStyle::Absolute => image_target,
+            SymlinkStyle::Relative => relative_path(&parent_in_image, &image_target),
+        };
+        if new_target != target {
+            fs::remove_file(&link)?;
+            std::os::unix::fs::symlink(&new_target, &link)
+                .with_context(|| format!("Failed to rewrite symlink: {}", link.display()))?;
+            report.rewritten.push(RewrittenSymlink {
+                path: link.clone(),
+                old_target: target,
+                new_target: new_target.clone(),
+            });
+        }
+
+        let resolved = match resolve_in_root(dest_root, rel) {
+            Ok(resolved) if resolved.symlink_metadata().is_ok() => continue,
+            Ok(resolved) => resolved,
+            Err(_) => {
+                // Symlink loop: nothing to pull in
+                report.dangling.push(DanglingSymlink {
+                    path: link,
+                    target: new_target,
+                });
+                continue;
+            }
+        };
+
+        if let Some(source_root) = &options.source_root {
+            if pull_target(source_root, dest_root, &resolved, options, &mut report)? {
+                if resolved.symlink_metadata().is_ok_and(|meta| meta.is_dir()) {
+                    collect_symlinks(&resolved, &mut links)?;
+                }
+                continue;
+            }
+        }
+        report.dangling.push(DanglingSymlink {
+            path: link,
+            target: new_target,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Copy the source counterpart of a missing link target into the destination.
+///
+/// Returns `false` if the source root doesn't have it either.
+fn pull_target(
+    source_root: &Path,
+    dest_root: &Path,
+    resolved: &Path,
+    options: &SymlinkOptions,
+    report: &mut SymlinkReport,
+) -> Result<bool> {
+    let rel = resolved.strip_prefix(dest_root).unwrap_or(resolved);
+    let src = resolve_in_root(source_root, rel)?;
+    let Ok(meta) = fs::metadata(&src) else {
+        return Ok(false);
+    };
+
+    if let Some(parent) = resolved.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if meta.is_dir() {
+        report
+            .copy
+            .merge(copy_dir_with_options(&src, resolved, &options.copy)?);
+    } else {
+        copy_file(&src, resolved, &options.copy, &mut report.copy)?;
+    }
+    report.pulled.push(resolved.to_path_buf());
+    Ok(true)
+}
+
+/// All symlinks under `dir`, in sorted order, without following any.
+fn collect_symlinks(dir: &Path, links: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            links.push(entry.path());
+        } else if file_type.is_dir() {
+            collect_symlinks(&entry.path(), links)?;
+        }
+    }
+    Ok(())
+}
+
+/// The absolute image path of a host path under `root`.
+fn image_path(root: &Path, host_path: &Path) -> PathBuf {
+    Path::new("/").join(host_path.strip_prefix(root).unwrap_or(host_path))
+}
+
+/// The absolute, normalized image path a link target refers to.
+///
+/// Also returns whether the target leaves the image.
+fn image_target(
+    target: &Path,
+    parent_in_image: &Path,
+    dest_root: &Path,
+    options: &SymlinkOptions,
+) -> (PathBuf, bool) {
+    if target.is_absolute() {
+        // Host paths leaked in by a build that linked against the staging tree
+        let host_roots = std::iter::once(dest_root).chain(options.source_root.as_deref());
+        for root in host_roots {
+            if let Ok(inner) = target.strip_prefix(root) {
+                return (normalize(&Path::new("/").join(inner)).0, true);
+            }
+        }
+        return normalize(target);
+    }
+    normalize(&parent_in_image.join(target))
+}
+
+/// Lexically fold `.` and `..` in an absolute image path.
+///
+/// Returns the normalized path and whether `..` tried to climb above `/`.
+fn normalize(path: &Path) -> (PathBuf, bool) {
+    let mut normalized = PathBuf::from("/");
+    let mut escapes = false;
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => escapes |= !normalized.pop(),
+            _ => {}
+        }
+    }
+    (normalized, escapes)
+}
+
+/// The relative path from directory `from` to `to`, both absolute image paths.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(
+            relative_path(Path::new("/usr/lib64"), Path::new("/usr/lib/libfoo.so.1")),
+            PathBuf::from("../lib/libfoo.so.1")
+        );
+        assert_eq!(
+            relative_path(Path::new("/usr/lib"), Path::new("/usr/lib/libfoo.so.1")),
+            PathBuf::from("libfoo.so.1")
+        );
+        assert_eq!(
+            relative_path(Path::new("/"), Path::new("/")),
+            PathBuf::from(".")
+        );
+    }
+
+    #[test]
+    fn test_validate_symlinks_rewrites_and_reports() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("dst");
+        fs::create_dir_all(root.join("usr/lib")).unwrap();
+        fs::write(root.join("usr/lib/libfoo.so.1"), "").unwrap();
+        symlink("usr/lib", root.join("lib")).unwrap();
+        // Absolute in-image link, reached through the usr-merge symlink
+        symlink("/usr/lib/libfoo.so.1", root.join("usr/lib/libfoo.so")).unwrap();
+        // Leaked host path
+        symlink(
+            root.join("usr/lib/libfoo.so.1"),
+            root.join("usr/lib/libleak.so"),
+        )
+        .unwrap();
+        symlink("/usr/lib/libgone.so.2", root.join("usr/lib/libgone.so")).unwrap();
+
+        let options = SymlinkOptions {
+            style: SymlinkStyle::Relative,
+            ..SymlinkOptions::default()
+        };
+        let report = validate_symlinks(&root, &options).unwrap();
+
+        assert_eq!(report.checked, 4);
+        assert_eq!(
+            fs::read_link(root.join("usr/lib/libfoo.so")).unwrap(),
+            PathBuf::from("libfoo.so.1")
+        );
+        assert_eq!(
+            fs::read_link(root.join("usr/lib/libleak.so")).unwrap(),
+            PathBuf::from("libfoo.so.1")
+        );
+        assert_eq!(report.escaping, vec![root.join("usr/lib/libleak.so")]);
+        assert_eq!(report.dangling.len(), 1);
+        assert_eq!(report.dangling[0].path, root.join("usr/lib/libgone.so"));
+        assert_eq!(report.dangling[0].target, PathBuf::from("libgone.so.2"));
+    }
+
+    #[test]
+    fn test_validate_symlinks_pulls_missing_targets() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let root = temp.path().join("dst");
+        fs::create_dir_all(src.join("usr/share/zoneinfo")).unwrap();
+        fs::write(src.join("usr/share/zoneinfo/UTC"), "TZif").unwrap();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        symlink("../usr/share/zoneinfo/UTC", root.join("etc/localtime")).unwrap();
+
+        let options = SymlinkOptions {
+            style: SymlinkStyle::Absolute,
+            source_root: Some(src),
+            ..SymlinkOptions::default()
+        };
+        let report = validate_symlinks(&root, &options).unwrap();
+
+        assert!(report.dangling.is_empty());
+        assert_eq!(report.pulled, vec![root.join("usr/share/zoneinfo/UTC")]);
+        assert_eq!(
+            fs::read_link(root.join("etc/localtime")).unwrap(),
+            PathBuf::from("/usr/share/zoneinfo/UTC")
+        );
+        assert_eq!(fs::read(&report.pulled[0]).unwrap(), b"TZif");
+    }
+
+    #[test]
+    fn test_validate_symlinks_checks_links_in_pulled_directories() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        let root = temp.path().join("dst");
+        fs::create_dir_all(src.join("usr/lib/plugins")).unwrap();
+        fs::create_dir_all(src.join("usr/share/data")).unwrap();
+        fs::write(src.join("usr/share/data/mod.dat"), "data").unwrap();
+        symlink(
+            "/usr/share/data/mod.dat",
+            src.join("usr/lib/plugins/mod.dat"),
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        symlink("/usr/lib/plugins", root.join("etc/plugins")).unwrap();
+
+        let options = SymlinkOptions {
+            source_root: Some(src),
+            ..SymlinkOptions::default()
+        };
+        let report = validate_symlinks(&root, &options).unwrap();
+
+        // The link inside the pulled directory was followed up too
+        assert_eq!(report.checked, 2);
+        assert!(report.dangling.is_empty());
+        assert_eq!(
+            report.pulled,
+            vec![
+                root.join("usr/lib/plugins"),
+                root.join("usr/share/data/mod.dat")
+            ]
+        );
+        assert_eq!(
+            fs::read(root.join("usr/share/data/mod.dat")).unwrap(),
+            b"data"
+        );
+    }
+}
@@ -1,7 +1,18 @@
 //! Integration tests for leviso-elf using real system binaries.
 
+// test_directory_not_file accepts either outcome with a plain match.
+#![allow(clippy::single_match)]
+
 use leviso_cheat_test::cheat_aware;
-use leviso_elf::get_library_dependencies;
+use leviso_elf::{
+    audit_root, check_compat, check_hardening, check_libc, check_symbols, get_library_dependencies,
+    get_library_dependencies_batch, install_binaries, resolve_closure,
+    resolve_closure_with_options, scan_implicit_dependencies, validate_symlinks, AnalysisCache,
+    AuditFinding, CacheKeyMode, ClosureOptions, CompatTarget, ElfBackend, EuReadelf, GnuReadelf,
+    HardeningPolicy, ImplicitOptions, InstallOptions, KernelVersion, LibraryLayout, LlvmReadelf,
+    Objdump, SymbolIndex, SymbolIndexOptions, SymlinkOptions,
+};
+use std::collections::BTreeSet;
 use std::path::Path;
 
 #[cheat_aware(
@@ -67,3 +78,324 @@ fn test_directory_not_file() {
         Err(_) => {} // Also acceptable - readelf may error on directories
     }
 }
+
+/// Host library directories, including Debian-style multiarch paths.
+const HOST_LIB_PATHS: &[&str] = &["lib/x86_64-linux-gnu", "usr/lib/x86_64-linux-gnu"];
+
+/// Install the host's /usr/bin/ls and its libraries into `dest`.
+fn install_ls(dest: &Path) {
+    let layout = LibraryLayout {
+        dest_lib64_path: "usr/lib64",
+        dest_lib_path: "usr/lib",
+        extra_lib_paths: HOST_LIB_PATHS,
+        private_lib_dirs: &[],
+    };
+    install_binaries(
+        Path::new("/"),
+        &[Path::new("/usr/bin/ls")],
+        dest,
+        &layout,
+        &mut AnalysisCache::new(),
+        &InstallOptions::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_closure_shares_cache_across_roots() {
+    let sh = Path::new("/bin/sh");
+    let ls = Path::new("/bin/ls");
+    let mut cache = AnalysisCache::new();
+
+    let closure = resolve_closure(Path::new("/"), &[sh, ls], HOST_LIB_PATHS, &mut cache).unwrap();
+    assert!(closure.libraries.keys().any(|d| d.contains("libc")));
+    // Each object (including the shared libc) is analyzed exactly once
+    assert_eq!(cache.analyzed(), closure.needed.len());
+
+    // A second resolution over the same cache needs no further analysis
+    let again = resolve_closure(Path::new("/"), &[ls], HOST_LIB_PATHS, &mut cache).unwrap();
+    assert_eq!(cache.analyzed(), closure.needed.len());
+    assert_eq!(again.roots[ls], closure.roots[ls]);
+}
+
+#[test]
+fn test_parallel_closure_matches_serial() {
+    let roots = [
+        Path::new("/bin/sh"),
+        Path::new("/bin/ls"),
+        Path::new("/bin/cp"),
+    ];
+    let serial = resolve_closure(
+        Path::new("/"),
+        &roots,
+        HOST_LIB_PATHS,
+        &mut AnalysisCache::new(),
+    )
+    .unwrap();
+
+    let options = ClosureOptions {
+        jobs: 4,
+        ..ClosureOptions::default()
+    };
+    let parallel = resolve_closure_with_options(
+        Path::new("/"),
+        &roots,
+        HOST_LIB_PATHS,
+        &mut AnalysisCache::new(),
+        &options,
+    )
+    .unwrap();
+
+    assert_eq!(serial, parallel);
+}
+
+#[test]
+fn test_batch_matches_single_file_analysis() {
+    let paths = [
+        Path::new("/bin/sh"),
+        Path::new("/etc/passwd"),
+        Path::new("/bin/ls"),
+        Path::new("/bin/sh"),
+    ];
+    let batch = get_library_dependencies_batch(&paths).unwrap();
+    let single: Vec<_> = paths
+        .iter()
+        .map(|p| get_library_dependencies(p).unwrap())
+        .collect();
+    assert_eq!(batch, single);
+}
+
+#[test]
+fn test_batch_nonexistent_file_fails() {
+    let paths = [Path::new("/bin/sh"), Path::new("/nonexistent/binary")];
+    let err = get_library_dependencies_batch(&paths).unwrap_err();
+    assert!(err.to_string().contains("does not exist"));
+}
+
+#[test]
+fn test_persistent_cache_skips_analysis_on_rerun() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let cache_file = temp.path().join("elf-cache.json");
+    let roots = [Path::new("/bin/sh")];
+
+    let mut cache = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+    let first = resolve_closure(Path::new("/"), &roots, HOST_LIB_PATHS, &mut cache).unwrap();
+    assert!(cache.analyzed() > 0);
+    cache.save().unwrap();
+
+    let mut cache = AnalysisCache::persistent(&cache_file, CacheKeyMode::Identity).unwrap();
+    let second = resolve_closure(Path::new("/"), &roots, HOST_LIB_PATHS, &mut cache).unwrap();
+    assert_eq!(
+        cache.analyzed(),
+        0,
+        "unchanged files must come from the cache"
+    );
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_available_backends_agree() {
+    let reference = GnuReadelf::new();
+    if !reference.is_available() {
+        return;
+    }
+    let backends: [&dyn ElfBackend; 3] = [&LlvmReadelf::new(), &EuReadelf::new(), &Objdump::new()];
+    let paths = [
+        Path::new("/bin/sh"),
+        Path::new("/etc/passwd"),
+        Path::new("/bin/ls"),
+    ];
+    let expected = reference.analyze_batch(&paths).unwrap();
+    assert!(expected[0].interpreter.is_some());
+
+    for backend in backends.into_iter().filter(|b| b.is_available()) {
+        let facts = backend.analyze_batch(&paths).unwrap();
+        assert_eq!(facts, expected, "backend {} disagrees", backend.name());
+    }
+}
+
+#[test]
+fn test_available_backends_agree_beyond_dependencies() {
+    let reference = GnuReadelf::new();
+    if !reference.is_available() {
+        return;
+    }
+    let backends: [&dyn ElfBackend; 3] = [&LlvmReadelf::new(), &EuReadelf::new(), &Objdump::new()];
+    let libc = HOST_LIB_PATHS
+        .iter()
+        .map(|dir| Path::new("/").join(dir).join("libc.so.6"))
+        .find(|path| path.exists())
+        .unwrap();
+    let paths = [Path::new("/bin/ls"), libc.as_path()];
+
+    for backend in backends.into_iter().filter(|b| b.is_available()) {
+        let name = backend.name();
+        for path in paths {
+            let expected = reference.symbols(path).unwrap();
+            assert!(!expected.symbols.is_empty());
+            assert_eq!(backend.symbols(path).unwrap(), expected, "{name} symbols");
+            let expected = reference.notes(path).unwrap();
+            assert_eq!(backend.notes(path).unwrap(), expected, "{name} notes");
+            let expected = reference.hardening(path).unwrap();
+            assert_eq!(
+                backend.hardening(path).unwrap(),
+                expected,
+                "{name} hardening"
+            );
+        }
+        // Tools render .rodata junk differently; .dynstr is plain names
+        let dynstr = |backend: &dyn ElfBackend| -> BTreeSet<_> {
+            let strings = backend.strings(Path::new("/bin/ls")).unwrap();
+            strings
+                .into_iter()
+                .filter(|s| s.section == ".dynstr")
+                .map(|s| (s.offset, s.text))
+                .collect()
+        };
+        let expected = dynstr(&reference);
+        assert!(expected.iter().any(|(_, text)| text == "libc.so.6"));
+        assert_eq!(dynstr(backend), expected, "{name} strings");
+    }
+}
+
+#[test]
+fn test_audit_installed_root() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let dest = temp.path();
+    install_ls(dest);
+    // PT_INTERP is /lib64/ld-linux-x86-64.so.2
+    std::os::unix::fs::symlink("usr/lib64", dest.join("lib64")).unwrap();
+    let options = SymlinkOptions {
+        source_root: Some("/".into()),
+        ..SymlinkOptions::default()
+    };
+    validate_symlinks(dest, &options).unwrap();
+
+    let report = audit_root(dest).unwrap();
+    assert!(report.elf_files >= 3);
+    assert!(report.is_clean(), "{:#?}", report.findings);
+
+    std::fs::remove_file(dest.join("usr/lib/libselinux.so.1")).unwrap();
+    let report = audit_root(dest).unwrap();
+    assert!(report.findings.iter().any(|finding| matches!(
+        finding,
+        AuditFinding::MissingLibrary { path, library, .. }
+            if path == &dest.join("usr/bin/ls") && library == "libselinux.so.1"
+    )));
+}
+
+#[test]
+fn test_host_closure_symbols_resolve() {
+    let mut cache = AnalysisCache::new();
+    let roots = [Path::new("/bin/sh"), Path::new("/usr/bin/ls")];
+    let closure = resolve_closure(Path::new("/"), &roots, HOST_LIB_PATHS, &mut cache).unwrap();
+
+    let report = check_symbols(&closure).unwrap();
+    assert!(report.objects >= 3);
+    assert!(report.is_clean(), "{:#?}", report);
+}
+
+#[test]
+fn test_host_closure_uses_one_libc() {
+    let mut cache = AnalysisCache::new();
+    let roots = [Path::new("/bin/sh"), Path::new("/usr/bin/ls")];
+    let closure = resolve_closure(Path::new("/"), &roots, HOST_LIB_PATHS, &mut cache).unwrap();
+
+    let report = check_libc(&closure).unwrap();
+    assert_eq!(report.flavors().len(), 1, "{:#?}", report);
+    assert!(report.offenders().is_empty());
+    assert!(report.flavor(Path::new("/bin/sh")).is_some());
+}
+
+#[test]
+fn test_implicit_scan_skips_declared_libraries() {
+    let ls = Path::new("/usr/bin/ls");
+    let strings = GnuReadelf::new().strings(ls).unwrap();
+    assert!(strings
+        .iter()
+        .any(|s| s.section == ".dynstr" && s.text == "libc.so.6"));
+
+    let report = scan_implicit_dependencies(
+        Path::new("/"),
+        &[ls],
+        &mut AnalysisCache::new(),
+        &ImplicitOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(report.objects, 1);
+    assert!(report.required.is_empty());
+    // NEEDED entries are declared, not implicit
+    assert!(!report.possible.iter().any(|dep| dep.target == "libc.so.6"));
+}
+
+#[test]
+fn test_symbol_index_finds_libc_exports() {
+    let options = SymbolIndexOptions {
+        analysis: ClosureOptions {
+            jobs: 4,
+            ..ClosureOptions::default()
+        },
+        ..SymbolIndexOptions::default()
+    };
+    let index =
+        SymbolIndex::build_with_options(Path::new("/"), &mut AnalysisCache::new(), &options)
+            .unwrap();
+    let providers = index.find("getenv", Some("GLIBC_2.2.5"));
+    assert!(
+        providers
+            .iter()
+            .any(|p| p.soname.as_deref() == Some("libc.so.6") && !p.hidden),
+        "{:#?}",
+        providers
+    );
+    assert!(index.find("getenv", Some("NO_SUCH_VERSION")).is_empty());
+}
+
+#[test]
+fn test_host_closure_compat() {
+    let mut cache = AnalysisCache::new();
+    // find links libm, which requires GLIBC_PRIVATE and GLIBC_ABI_DT_RELR
+    let roots = [Path::new("/usr/bin/ls"), Path::new("/usr/bin/find")];
+    let closure = resolve_closure(Path::new("/"), &roots, HOST_LIB_PATHS, &mut cache).unwrap();
+
+    // The host's own libc satisfies everything, and no glibc needs kernel 99
+    let target = CompatTarget {
+        min_kernel: Some(KernelVersion::new(99, 0, 0)),
+        ..CompatTarget::default()
+    };
+    let report = check_compat(&closure, &target).unwrap();
+    assert!(report.is_clean(), "{:#?}", report.findings);
+    assert!(report.required_versions["libc.so.6"].starts_with("GLIBC_2."));
+
+    // Every glibc since 2.26 needs at least kernel 3.2
+    let target = CompatTarget {
+        min_kernel: Some(KernelVersion::new(2, 6, 32)),
+        ..CompatTarget::default()
+    };
+    let report = check_compat(&closure, &target).unwrap();
+    assert!(!report.is_clean());
+}
+
+#[test]
+fn test_hardening_of_installed_root() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let dest = temp.path();
+    install_ls(dest);
+
+    let policy = HardeningPolicy {
+        require_nx: true,
+        forbid_executable_stack: true,
+        forbid_textrel: true,
+        ..HardeningPolicy::default()
+    };
+    let report = check_hardening(dest, &policy).unwrap();
+    assert!(report.objects.len() >= 2);
+    assert!(report.passes(), "{:#?}", report.violations);
+    let ls = &report.objects[&dest.join("usr/bin/ls")];
+    assert!(ls.stack_protector);
+
+    let json = temp.path().join("hardening.json");
+    report.save(&json).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&std::fs::read(&json).unwrap()).unwrap();
+    assert!(value["objects"].as_object().unwrap().len() >= 2);
+}